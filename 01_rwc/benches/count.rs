@@ -0,0 +1,53 @@
+use std::{fs, hint::black_box};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rwc::{Counter, BLOCK_SIZE};
+
+const GUTENBERG: &str = "../gutenberg.org_files_135_135-0.txt";
+
+fn count(counter: &Counter, input: &[u8]) -> u64 {
+    let mut reader = std::io::BufReader::with_capacity(BLOCK_SIZE, input);
+    let res = counter.count_reader("", &mut reader).unwrap();
+    res.lines + res.words + res.chars + res.bytes
+}
+
+fn bench_count(c: &mut Criterion) {
+    let text = fs::read(GUTENBERG).expect("missing gutenberg text");
+    // Same text without newlines, so that the whole file is a single line
+    let single_line: Vec<u8> = text
+        .iter()
+        .map(|&b| if b == b'\n' { b' ' } else { b })
+        .collect();
+
+    let counters = [
+        (
+            "lines",
+            Counter {
+                lines: true,
+                ..Counter::default()
+            },
+        ),
+        ("default", Counter::default().or_default()),
+        ("all", Counter::all()),
+        (
+            "all_c_locale",
+            Counter {
+                utf8: false,
+                ..Counter::all()
+            },
+        ),
+    ];
+
+    let mut group = c.benchmark_group("gutenberg");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    for (name, counter) in counters.iter() {
+        group.bench_function(*name, |b| b.iter(|| count(counter, black_box(&text))));
+        group.bench_function(format!("{}_single_line", name), |b| {
+            b.iter(|| count(counter, black_box(&single_line)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_count);
+criterion_main!(benches);
@@ -0,0 +1,475 @@
+use std::time::Duration;
+
+use getopts::{Matches, Options};
+use rwc::{
+    Compression, Counter, Decompress, Format, FrequencyOptions, Histogram, Symlinks, WalkOptions,
+    WordMode,
+};
+
+#[derive(Debug)]
+pub struct Args {
+    pub exe_name: String,
+    pub counter: Counter,
+    /// Number of threads counting files
+    pub jobs: usize,
+    /// Split each file into byte ranges counted by the threads
+    pub split: bool,
+    pub total: TotalMode,
+    pub format: Format,
+    /// File with the NUL-separated list of paths
+    pub files0_from: Option<String>,
+    /// Count the files in directories, recursively
+    pub recursive: bool,
+    pub walk: WalkOptions,
+    /// Print counts summed by file extension instead of the counts of each file
+    pub by_extension: bool,
+    /// Keep counting the content appended to the files
+    pub follow: bool,
+    /// Time between the updates of followed files
+    pub interval: Duration,
+    /// Print the deltas and rates of followed files instead of their counts
+    pub deltas: bool,
+    /// Print what is detected in the inputs instead of their counts
+    pub detect: bool,
+    /// Number of most frequent words to print instead of the counts
+    pub top: Option<usize>,
+    pub frequency: FrequencyOptions,
+    /// Whether some inputs couldn't be listed (e.g. invalid names in the list of paths,
+    /// unreadable directories), which were skipped
+    pub input_errors: bool,
+    pub paths: Vec<String>,
+}
+
+/// When to print the total line, as in GNU wc `--total`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TotalMode {
+    /// Only when more than one file is counted
+    Auto,
+    Always,
+    /// Only the total, without the per file counts
+    Only,
+    Never,
+}
+
+/// What the command line asks for
+#[derive(Debug)]
+pub enum Command {
+    Count(Args),
+    /// Print the usage, given, and exit
+    Help(String),
+}
+
+impl Args {
+    /// Whether stdin is counted, as no path is given
+    pub fn reads_stdin(&self) -> bool {
+        self.paths.is_empty() && self.files0_from.is_none() && !self.recursive
+    }
+
+    /// Whether the counts are replaced by the frequencies of words, bytes or chars
+    pub fn frequencies(&self) -> bool {
+        self.top.is_some() || self.frequency.histogram.is_some()
+    }
+}
+
+/// Parses the command line arguments, without the program name. Errors are messages
+/// to print after the program name. With `--files0-from` and `-r`, the paths are not
+/// listed yet, as it needs reading files.
+pub fn parse_args(exe_name: &str, cmd_args: &[String], utf8: bool) -> Result<Command, String> {
+    let opts = options();
+    let matches = opts.parse(cmd_args).map_err(|err| err.to_string())?;
+
+    if matches.opt_present("h") {
+        let brief = format!("Usage: {} [OPTION]... [FILE]...", exe_name);
+        return Ok(Command::Help(opts.usage(&brief)));
+    }
+
+    let mut args = Args {
+        exe_name: exe_name.to_string(),
+        counter: Counter {
+            bytes: matches.opt_present("c"),
+            lines: matches.opt_present("l"),
+            words: matches.opt_present("w"),
+            chars: matches.opt_present("m"),
+            max_line_length: matches.opt_present("L"),
+            graphemes: matches.opt_present("graphemes"),
+            display_width: matches.opt_present("display-width"),
+            word_mode: WordMode::Posix,
+            decompress: if matches.opt_present("z") {
+                Decompress::Auto
+            } else {
+                Decompress::Never
+            },
+            utf8,
+        },
+        jobs: 1,
+        split: matches.opt_present("s"),
+        total: TotalMode::Auto,
+        format: Format::Text,
+        files0_from: matches.opt_str("files0-from"),
+        recursive: matches.opt_present("r"),
+        walk: WalkOptions {
+            include: matches.opt_strs("include"),
+            exclude: matches.opt_strs("exclude"),
+            ignore_files: !matches.opt_present("no-ignore"),
+            symlinks: Symlinks::Skip,
+        },
+        by_extension: matches.opt_present("by-extension"),
+        follow: matches.opt_present("follow"),
+        interval: Duration::from_secs(1),
+        deltas: matches.opt_present("deltas"),
+        detect: matches.opt_present("detect"),
+        top: None,
+        frequency: FrequencyOptions {
+            words: matches.opt_present("top"),
+            fold_case: matches.opt_present("fold-case"),
+            strip_punctuation: matches.opt_present("strip-punctuation"),
+            histogram: None,
+        },
+        input_errors: false,
+        paths: matches.free.clone(),
+    };
+
+    if let Some(jobs) = matches.opt_str("j") {
+        match jobs.parse() {
+            Ok(jobs) if jobs > 0 => args.jobs = jobs,
+            _ => return Err(format!("invalid number of jobs: {}", jobs)),
+        }
+    }
+
+    if let Some(interval) = matches.opt_str("interval") {
+        match interval.parse::<f64>() {
+            Ok(seconds) if seconds > 0.0 && seconds.is_finite() => {
+                args.interval = Duration::from_secs_f64(seconds)
+            }
+            _ => return Err(format!("invalid number of seconds: {}", interval)),
+        }
+    }
+
+    if let Some(top) = matches.opt_str("top") {
+        match top.parse() {
+            Ok(n) if n > 0 => args.top = Some(n),
+            _ => return Err(format!("invalid number of words: {}", top)),
+        }
+    }
+
+    if matches.opt_present("histogram") {
+        // In the C locale chars are bytes anyway
+        let unit = matches.opt_str("histogram");
+        let unit = unit.as_deref().unwrap_or("chars");
+        args.frequency.histogram = Some(choice("histogram", unit, Histogram::from_name)?);
+    }
+
+    if let Some(mode) = optional_choice(&matches, "word-mode", |mode| match mode {
+        "posix" => Some(WordMode::Posix),
+        "uax29" => Some(WordMode::Uax29),
+        _ => None,
+    })? {
+        args.counter.word_mode = mode;
+    }
+
+    if let Some(compression) = optional_choice(&matches, "compression", Compression::from_name)? {
+        args.counter.decompress = Decompress::Always(compression);
+    }
+
+    if let Some(total) = optional_choice(&matches, "total", |total| match total {
+        "auto" => Some(TotalMode::Auto),
+        "always" => Some(TotalMode::Always),
+        "only" => Some(TotalMode::Only),
+        "never" => Some(TotalMode::Never),
+        _ => None,
+    })? {
+        args.total = total;
+    }
+
+    if let Some(format) = optional_choice(&matches, "format", Format::from_name)? {
+        args.format = format;
+    }
+
+    if let Some(symlinks) = optional_choice(&matches, "symlinks", |symlinks| match symlinks {
+        "skip" => Some(Symlinks::Skip),
+        "follow" => Some(Symlinks::Follow),
+        _ => None,
+    })? {
+        args.walk.symlinks = symlinks;
+    }
+
+    if args.files0_from.is_some() && !args.paths.is_empty() {
+        return Err("file operands cannot be combined with --files0-from".to_string());
+    }
+
+    let modes = [
+        args.follow,
+        args.by_extension,
+        args.frequencies(),
+        args.detect,
+    ];
+    if modes.into_iter().filter(|&mode| mode).count() > 1 {
+        return Err(
+            "only one of --follow, --by-extension, --top or --histogram, and \
+                    --detect can be given"
+                .to_string(),
+        );
+    }
+
+    if args.follow {
+        if args.counter.decompress != Decompress::Never {
+            return Err("--follow cannot be combined with decompression".to_string());
+        }
+        if args.reads_stdin() {
+            return Err("--follow needs files to follow".to_string());
+        }
+    }
+
+    Ok(Command::Count(args))
+}
+
+/// Parses the value of an option among a set of names
+fn choice<T>(option: &str, value: &str, parse: impl Fn(&str) -> Option<T>) -> Result<T, String> {
+    parse(value).ok_or_else(|| format!("invalid argument '{}' for '--{}'", value, option))
+}
+
+fn optional_choice<T>(
+    matches: &Matches,
+    option: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<Option<T>, String> {
+    matches
+        .opt_str(option)
+        .map(|value| choice(option, &value, parse))
+        .transpose()
+}
+
+fn options() -> Options {
+    let mut opts = Options::new();
+    opts.optflag("c", "bytes", "print the byte counts");
+    opts.optflag("m", "chars", "print the character counts");
+    opts.optflag("l", "lines", "print the newline counts");
+    opts.optflag("w", "words", "print the word counts");
+    opts.optflag("L", "max-line-length", "print the maximum display width");
+    opts.optflag(
+        "",
+        "graphemes",
+        "print the counts of extended grapheme clusters (user-perceived characters)",
+    );
+    opts.optflag(
+        "",
+        "display-width",
+        "print the total display width of the text",
+    );
+    opts.optopt(
+        "",
+        "word-mode",
+        "how words are counted; MODE can be: posix (default), uax29 (Unicode word boundaries)",
+        "MODE",
+    );
+    opts.optflag(
+        "z",
+        "decompress",
+        "count the decompressed content of gzip, bzip2, xz and rcompress inputs, detected from their first bytes",
+    );
+    opts.optopt(
+        "",
+        "compression",
+        "decompress all inputs from FORMAT: gzip, bzip2, xz, rcompress",
+        "FORMAT",
+    );
+    opts.optopt(
+        "",
+        "files0-from",
+        "read input from the files specified by NUL-terminated names in file F; \
+         if F is - then read names from standard input",
+        "F",
+    );
+    opts.optopt(
+        "",
+        "total",
+        "when to print a line with total counts; WHEN can be: auto, always, only, never",
+        "WHEN",
+    );
+    opts.optopt(
+        "",
+        "format",
+        "output format; FORMAT can be: text (default), json, csv",
+        "FORMAT",
+    );
+    opts.optflag(
+        "r",
+        "recursive",
+        "count the files in directories, recursively (the current directory by default)",
+    );
+    opts.optmulti(
+        "",
+        "include",
+        "with -r, only count the files matching GLOB; can be repeated",
+        "GLOB",
+    );
+    opts.optmulti(
+        "",
+        "exclude",
+        "with -r, skip the files and directories matching GLOB; can be repeated",
+        "GLOB",
+    );
+    opts.optflag(
+        "",
+        "no-ignore",
+        "with -r, also count hidden files and the files ignored by .gitignore and .ignore",
+    );
+    opts.optopt(
+        "",
+        "symlinks",
+        "with -r, what to do with symbolic links; POLICY can be: skip (default), follow",
+        "POLICY",
+    );
+    opts.optflag(
+        "",
+        "by-extension",
+        "print the number of files and the counts summed by file extension",
+    );
+    opts.optopt(
+        "",
+        "top",
+        "print the N most frequent words with their counts instead of the counts",
+        "N",
+    );
+    opts.optflag(
+        "",
+        "fold-case",
+        "with --top, words differing only by case are the same word",
+    );
+    opts.optflag(
+        "",
+        "strip-punctuation",
+        "with --top, remove the punctuation at the start and end of words",
+    );
+    opts.optflagopt(
+        "",
+        "histogram",
+        "print the number of occurrences of each char, or of each byte with \
+         --histogram=bytes, instead of the counts",
+        "UNIT",
+    );
+    opts.optflag(
+        "",
+        "detect",
+        "print the encoding (UTF-8, UTF-16 with a BOM, or else ISO-8859-1), line endings, \
+         trailing newline and NUL bytes of each input instead of the counts",
+    );
+    opts.optflag(
+        "",
+        "follow",
+        "keep counting the content appended to the files, printing the counts when they \
+         change; truncated and replaced (e.g. rotated) files are followed",
+    );
+    opts.optopt(
+        "",
+        "interval",
+        "with --follow, check the files every N seconds (default 1)",
+        "N",
+    );
+    opts.optflag(
+        "",
+        "deltas",
+        "with --follow, print the counts of the appended content and their rates every \
+         interval",
+    );
+    opts.optopt("j", "jobs", "count files on N threads", "N");
+    opts.optflag(
+        "s",
+        "split",
+        "split each file into byte ranges counted by the threads",
+    );
+    opts.optflag("h", "help", "display this help and exit");
+    opts
+}
+
+#[cfg(test)]
+mod tests {
+    use rwc::{Compression, Decompress, Format};
+
+    use crate::args::{parse_args, Args, Command, TotalMode};
+
+    fn parse(cmd_args: &[&str]) -> Result<Args, String> {
+        let cmd_args: Vec<String> = cmd_args.iter().map(|arg| arg.to_string()).collect();
+        match parse_args("rwc", &cmd_args, true)? {
+            Command::Count(args) => Ok(args),
+            Command::Help(_) => Err("help".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_defaults() {
+        let args = parse(&[]).unwrap();
+        assert!(args.counter.is_default());
+        assert!(args.counter.utf8);
+        assert_eq!(
+            (args.jobs, args.total, args.format),
+            (1, TotalMode::Auto, Format::Text)
+        );
+        assert!(args.reads_stdin());
+    }
+
+    #[test]
+    fn test_options() {
+        let args = parse(&[
+            "-lw",
+            "--chars",
+            "-j",
+            "4",
+            "--total=only",
+            "--format",
+            "csv",
+            "--compression",
+            "xz",
+            "a.txt",
+            "-",
+        ])
+        .unwrap();
+        let counter = args.counter;
+        assert!(counter.lines && counter.words && counter.chars && !counter.bytes);
+        assert_eq!(counter.decompress, Decompress::Always(Compression::Xz));
+        assert_eq!(
+            (args.jobs, args.total, args.format),
+            (4, TotalMode::Only, Format::Csv)
+        );
+        assert_eq!(args.paths, vec!["a.txt", "-"]);
+        assert!(!args.reads_stdin());
+
+        // Like getopts, options and operands can be mixed, and "--" ends the options
+        let args = parse(&["a.txt", "-c", "--", "-l"]).unwrap();
+        assert!(args.counter.bytes && !args.counter.lines);
+        assert_eq!(args.paths, vec!["a.txt", "-l"]);
+    }
+
+    #[test]
+    fn test_help() {
+        let cmd_args = vec!["--help".to_string()];
+        match parse_args("rwc", &cmd_args, true) {
+            Ok(Command::Help(usage)) => {
+                assert!(usage.starts_with("Usage: rwc [OPTION]... [FILE]..."))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let cases: &[(&[&str], &str)] = &[
+            (&["--total=sometimes"], "invalid argument 'sometimes' for '--total'"),
+            (&["--histogram=words"], "invalid argument 'words' for '--histogram'"),
+            (&["-j", "0"], "invalid number of jobs: 0"),
+            (&["--top", "x"], "invalid number of words: x"),
+            (&["--interval", "-1", "--follow", "a"], "invalid number of seconds: -1"),
+            (&["--files0-from", "list", "a.txt"], "file operands cannot be combined with --files0-from"),
+            (&["--follow"], "--follow needs files to follow"),
+            (&["--follow", "-z", "a"], "--follow cannot be combined with decompression"),
+            (
+                &["--detect", "--top", "3"],
+                "only one of --follow, --by-extension, --top or --histogram, and --detect can be given",
+            ),
+            (&["--bogus"], "Unrecognized option: 'bogus'"),
+        ];
+        for (cmd_args, message) in cases {
+            assert_eq!(parse(cmd_args).unwrap_err(), *message, "{:?}", cmd_args);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::io::{self, BufRead, BufReader, Write};
+
+use flate2::bufread::MultiGzDecoder;
+
+use crate::BLOCK_SIZE;
+
+/// Length of the longest magic bytes, those of xz
+const MAGIC_LEN: usize = 6;
+
+/// Compression formats that can be counted transparently
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Bzip2,
+    Xz,
+    /// Huffman coding format of rcompress, from this repository
+    Rcompress,
+}
+
+impl Compression {
+    pub fn from_name(name: &str) -> Option<Compression> {
+        match name {
+            "gzip" => Some(Compression::Gzip),
+            "bzip2" => Some(Compression::Bzip2),
+            "xz" => Some(Compression::Xz),
+            "rcompress" => Some(Compression::Rcompress),
+            _ => None,
+        }
+    }
+
+    /// Detects the format from the magic bytes at the start of the input
+    pub fn detect(start: &[u8]) -> Option<Compression> {
+        if start.starts_with(&[0x1F, 0x8B]) {
+            Some(Compression::Gzip)
+        } else if start.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else if start.starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Compression::Xz)
+        } else if start.starts_with(&rcompress::MAGIC) {
+            Some(Compression::Rcompress)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether inputs are decompressed before being counted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Decompress {
+    /// Inputs are counted as they are
+    #[default]
+    Never,
+    /// Inputs starting with the magic bytes of a known format are decompressed, the
+    /// others are counted as they are
+    Auto,
+    /// Inputs are all in the given format
+    Always(Compression),
+}
+
+impl Decompress {
+    /// Returns the compression of the reader, peeking at its first bytes if needed, and
+    /// the bytes that had to be consumed to see enough of them, to be read first
+    pub(crate) fn compression<R: BufRead>(
+        &self,
+        reader: &mut R,
+    ) -> io::Result<(Option<Compression>, Vec<u8>)> {
+        match self {
+            Decompress::Never => Ok((None, Vec::new())),
+            Decompress::Auto => {
+                // A pipe or a slow device may return fewer bytes than the magic at once
+                let mut start = Vec::new();
+                while start.len() < MAGIC_LEN {
+                    let block = match reader.fill_buf() {
+                        Ok(block) if start.is_empty() && block.len() >= MAGIC_LEN => {
+                            return Ok((Compression::detect(block), start));
+                        }
+                        Ok([]) => break,
+                        Ok(block) => block,
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(err) => return Err(err),
+                    };
+                    let len = block.len().min(MAGIC_LEN - start.len());
+                    start.extend_from_slice(&block[..len]);
+                    reader.consume(len);
+                }
+                Ok((Compression::detect(&start), start))
+            }
+            Decompress::Always(compression) => Ok((Some(*compression), Vec::new())),
+        }
+    }
+}
+
+/// Updates the state (e.g. a `CountState`) with the decompressed content of the reader
+pub(crate) fn decode<R: BufRead, W: Write>(
+    compression: Compression,
+    reader: &mut R,
+    state: &mut W,
+) -> io::Result<()> {
+    match compression {
+        Compression::Gzip => {
+            // Like zcat, concatenated gzip members are all decompressed
+            let decoder = MultiGzDecoder::new(reader);
+            io::copy(&mut BufReader::with_capacity(BLOCK_SIZE, decoder), state)?;
+        }
+        Compression::Bzip2 => {
+            let decoder = bzip2_rs::DecoderReader::new(reader);
+            io::copy(&mut BufReader::with_capacity(BLOCK_SIZE, decoder), state)?;
+        }
+        Compression::Xz => lzma_rs::xz_decompress(reader, state).map_err(|err| match err {
+            lzma_rs::error::Error::IoError(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, format!("xz: {}", err)),
+        })?,
+        Compression::Rcompress => rcompress::decompress(reader, state)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, BufReader, Read};
+
+    use crate::{Compression, Counter, Decompress};
+
+    /// Reader returning a single byte at a time, like a slow pipe
+    struct ByteReader<'a>(&'a [u8]);
+
+    impl Read for ByteReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            (&mut self.0).take(1).read(buf)
+        }
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            Compression::detect(&[0x1F, 0x8B, 8]),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(Compression::detect(b"BZh91AY"), Some(Compression::Bzip2));
+        assert_eq!(
+            Compression::detect(b"\xFD7zXZ\x00\x00"),
+            Some(Compression::Xz)
+        );
+        assert_eq!(
+            Compression::detect(b"\x89RCZ\x01"),
+            Some(Compression::Rcompress)
+        );
+        assert_eq!(Compression::detect(b"plain text"), None);
+        assert_eq!(Compression::detect(b""), None);
+    }
+
+    #[test]
+    fn test_auto_counts_plain_input() {
+        let counter = Counter {
+            decompress: Decompress::Auto,
+            ..Counter::all()
+        };
+        let mut input = "not compressed\n".as_bytes();
+        let count = counter.count_reader("", &mut input).unwrap();
+        assert_eq!((count.lines, count.words, count.bytes), (1, 2, 15));
+    }
+
+    #[test]
+    fn test_auto_reads_the_whole_magic() {
+        let counter = Counter {
+            decompress: Decompress::Auto,
+            ..Counter::all()
+        };
+        let compressed = std::fs::read("tests/files/mixed.txt.xz").unwrap();
+        let mut input = BufReader::new(ByteReader(&compressed));
+        let count = counter.count_reader("", &mut input).unwrap();
+        let plain = counter.count_file("tests/files/mixed.txt").unwrap();
+        assert_eq!(
+            (count.lines, count.words, count.bytes),
+            (plain.lines, plain.words, plain.bytes)
+        );
+
+        // Inputs shorter than the magic are counted as they are
+        let mut input = BufReader::new(ByteReader(b"BZ"));
+        let count = counter.count_reader("", &mut input).unwrap();
+        assert_eq!(count.bytes, 2);
+    }
+
+    #[test]
+    fn test_invalid_data_fails() {
+        let counter = Counter {
+            decompress: Decompress::Always(Compression::Gzip),
+            ..Counter::all()
+        };
+        let mut input = "not compressed\n".as_bytes();
+        assert!(counter.count_reader("", &mut input).is_err());
+    }
+}
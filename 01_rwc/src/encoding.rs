@@ -0,0 +1,461 @@
+use std::{
+    char::REPLACEMENT_CHARACTER,
+    fs,
+    io::{self, BufRead, Read, Write},
+    mem,
+};
+
+use crate::{open_input, Counter};
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// Text encodings told apart by `detect_file`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// Any byte sequence that is not valid UTF-8
+    Latin1,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Latin1 => "ISO-8859-1",
+        }
+    }
+
+    /// Returns the UTF-16 encoding whose BOM starts the input, if any
+    fn utf16_from_bom(start: &[u8]) -> Option<Encoding> {
+        if start.starts_with(UTF16LE_BOM) {
+            Some(Encoding::Utf16Le)
+        } else if start.starts_with(UTF16BE_BOM) {
+            Some(Encoding::Utf16Be)
+        } else {
+            None
+        }
+    }
+}
+
+/// Style of the line endings of a text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEndings {
+    /// The text has no line ending
+    None,
+    Lf,
+    Crlf,
+    Cr,
+    Mixed,
+}
+
+impl LineEndings {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineEndings::None => "none",
+            LineEndings::Lf => "LF",
+            LineEndings::Crlf => "CRLF",
+            LineEndings::Cr => "CR",
+            LineEndings::Mixed => "mixed",
+        }
+    }
+}
+
+/// What `detect_file` found in an input
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Detection {
+    pub path: String,
+    pub encoding: Encoding,
+    /// Whether the input starts with a byte order mark
+    pub bom: bool,
+    /// Numbers of line endings of each style, a CRLF being counted only as such
+    pub lf: u64,
+    pub crlf: u64,
+    pub cr: u64,
+    /// Whether the last char is a line ending
+    pub trailing_newline: bool,
+    /// NUL chars, i.e. NUL code units in UTF-16
+    pub nul_bytes: u64,
+}
+
+impl Detection {
+    pub fn line_endings(&self) -> LineEndings {
+        match (self.lf > 0, self.crlf > 0, self.cr > 0) {
+            (false, false, false) => LineEndings::None,
+            (true, false, false) => LineEndings::Lf,
+            (false, true, false) => LineEndings::Crlf,
+            (false, false, true) => LineEndings::Cr,
+            _ => LineEndings::Mixed,
+        }
+    }
+}
+
+/// Decodes UTF-16 given in blocks of any size into UTF-8. Unpaired surrogates and a
+/// trailing odd byte are decoded as U+FFFD, as they are invalid.
+#[derive(Debug)]
+struct Utf16Decoder {
+    big_endian: bool,
+    /// Byte of a code unit split between blocks
+    odd: Option<u8>,
+    /// High surrogate whose low surrogate may be in the next block
+    high: Option<u16>,
+}
+
+impl Utf16Decoder {
+    fn decode(&mut self, block: &[u8], out: &mut String) {
+        let mut units = Vec::with_capacity(block.len() / 2 + 2);
+        units.extend(self.high.take());
+        let mut bytes = block;
+        if let (Some(first), Some((&second, rest))) = (self.odd, block.split_first()) {
+            units.push(self.unit([first, second]));
+            self.odd = None;
+            bytes = rest;
+        }
+        let mut pairs = bytes.chunks_exact(2);
+        units.extend(pairs.by_ref().map(|pair| self.unit([pair[0], pair[1]])));
+        if let [byte] = pairs.remainder() {
+            self.odd = Some(*byte);
+        }
+        if let Some(&last) = units.last() {
+            if (0xD800..0xDC00).contains(&last) {
+                self.high = units.pop();
+            }
+        }
+
+        out.extend(char::decode_utf16(units).map(|c| c.unwrap_or(REPLACEMENT_CHARACTER)));
+    }
+
+    fn end(&mut self, out: &mut String) {
+        if self.high.take().is_some() {
+            out.push(REPLACEMENT_CHARACTER);
+        }
+        if self.odd.take().is_some() {
+            out.push(REPLACEMENT_CHARACTER);
+        }
+    }
+
+    fn unit(&self, bytes: [u8; 2]) -> u16 {
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+}
+
+/// Writes text to another writer, decoded from UTF-16 into UTF-8 if it starts with a
+/// UTF-16 BOM, which is dropped. Other text is written as it is. `finish` must be called
+/// after the last write.
+pub(crate) struct Utf16Filter<W: Write> {
+    inner: W,
+    /// First bytes, kept until there are enough of them to detect a BOM
+    start: Vec<u8>,
+    started: bool,
+    decoder: Option<Utf16Decoder>,
+    decoded: String,
+    /// Bytes written to the filter, before decoding
+    pub(crate) bytes: u64,
+}
+
+impl<W: Write> Utf16Filter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Utf16Filter {
+            inner,
+            start: Vec::new(),
+            started: false,
+            decoder: None,
+            decoded: String::new(),
+            bytes: 0,
+        }
+    }
+
+    /// Returns the UTF-16 encoding of the text, if detected
+    pub(crate) fn utf16(&self) -> Option<Encoding> {
+        self.decoder
+            .as_ref()
+            .map(|decoder| match decoder.big_endian {
+                true => Encoding::Utf16Be,
+                false => Encoding::Utf16Le,
+            })
+    }
+
+    pub(crate) fn finish(&mut self) -> io::Result<()> {
+        if !self.started {
+            self.started = true;
+            let start = mem::take(&mut self.start);
+            self.inner.write_all(&start)?;
+        }
+        if let Some(decoder) = &mut self.decoder {
+            decoder.end(&mut self.decoded);
+            self.inner.write_all(self.decoded.as_bytes())?;
+            self.decoded.clear();
+        }
+        Ok(())
+    }
+
+    fn write_text(&mut self, block: &[u8]) -> io::Result<()> {
+        match &mut self.decoder {
+            Some(decoder) => {
+                decoder.decode(block, &mut self.decoded);
+                self.inner.write_all(self.decoded.as_bytes())?;
+                self.decoded.clear();
+                Ok(())
+            }
+            None => self.inner.write_all(block),
+        }
+    }
+}
+
+impl<W: Write> Write for Utf16Filter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes += buf.len() as u64;
+        if self.started {
+            self.write_text(buf)?;
+            return Ok(buf.len());
+        }
+
+        self.start.extend_from_slice(buf);
+        if self.start.len() >= 2 {
+            self.started = true;
+            let start = mem::take(&mut self.start);
+            let text = match Encoding::utf16_from_bom(&start) {
+                Some(encoding) => {
+                    self.decoder = Some(Utf16Decoder {
+                        big_endian: encoding == Encoding::Utf16Be,
+                        odd: None,
+                        high: None,
+                    });
+                    &start[2..]
+                }
+                None => &start[..],
+            };
+            self.write_text(text)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Finds the line endings, NUL bytes and UTF-8 validity of text given in blocks
+#[derive(Debug, Default)]
+struct Detector {
+    detection: Detection,
+    /// First bytes, to detect a UTF-8 BOM
+    start: Vec<u8>,
+    /// Whether the previous byte is a CR, which may be followed by a LF
+    after_cr: bool,
+    last: Option<u8>,
+    valid_utf8: bool,
+    /// Incomplete UTF-8 sequence at the end of the previous block
+    pending: Vec<u8>,
+}
+
+impl Detector {
+    fn update(&mut self, block: &[u8]) {
+        if self.start.len() < UTF8_BOM.len() {
+            let missing = UTF8_BOM.len() - self.start.len();
+            self.start
+                .extend_from_slice(&block[..missing.min(block.len())]);
+        }
+
+        for &b in block {
+            match b {
+                b'\n' if self.after_cr => self.detection.crlf += 1,
+                b'\n' => self.detection.lf += 1,
+                0 => self.detection.nul_bytes += 1,
+                _ => {}
+            }
+            if self.after_cr && b != b'\n' {
+                self.detection.cr += 1;
+            }
+            self.after_cr = b == b'\r';
+        }
+        if let Some(&last) = block.last() {
+            self.last = Some(last);
+        }
+
+        if self.valid_utf8 {
+            self.check_utf8(block);
+        }
+    }
+
+    /// Checks that the blocks are valid UTF-8, an incomplete sequence at the end of a
+    /// block being completed by the next one
+    fn check_utf8(&mut self, block: &[u8]) {
+        let joined;
+        let data = if self.pending.is_empty() {
+            block
+        } else {
+            let mut pending = mem::take(&mut self.pending);
+            pending.extend_from_slice(block);
+            joined = pending;
+            &joined[..]
+        };
+        let pending = match std::str::from_utf8(data) {
+            Ok(_) => Vec::new(),
+            Err(err) if err.error_len().is_none() => data[err.valid_up_to()..].to_vec(),
+            Err(_) => {
+                self.valid_utf8 = false;
+                Vec::new()
+            }
+        };
+        self.pending = pending;
+    }
+
+    fn finish(mut self, path: &str, utf16: Option<Encoding>) -> Detection {
+        if self.after_cr {
+            self.detection.cr += 1;
+        }
+        self.detection.path = path.to_string();
+        self.detection.trailing_newline = matches!(self.last, Some(b'\n' | b'\r'));
+        self.detection.encoding = match utf16 {
+            Some(encoding) => encoding,
+            None if self.valid_utf8 && self.pending.is_empty() => Encoding::Utf8,
+            None => Encoding::Latin1,
+        };
+        self.detection.bom = utf16.is_some() || self.start == UTF8_BOM;
+        self.detection
+    }
+}
+
+impl Write for Detector {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Counter {
+    /// Detects the encoding and the line endings of the content of a reader,
+    /// decompressed if selected by `decompress`. UTF-16 is detected from its BOM, and
+    /// its line endings and NUL chars are found once decoded. Text that is not valid
+    /// UTF-8 is reported as Latin-1, as any byte sequence is valid Latin-1.
+    pub fn detect_reader<R: BufRead>(&self, path: &str, reader: &mut R) -> io::Result<Detection> {
+        let mut detector = Detector {
+            valid_utf8: true,
+            ..Detector::default()
+        };
+        let mut filter = Utf16Filter::new(&mut detector);
+        self.write_content(&mut filter, reader)?;
+        filter.finish()?;
+        let utf16 = filter.utf16();
+        Ok(detector.finish(path, utf16))
+    }
+
+    /// Detects the encoding and the line endings of a file, or of stdin for `-`
+    pub fn detect_file(&self, path: &str) -> io::Result<Detection> {
+        let mut reader = open_input(path)?;
+        self.detect_reader(path, &mut reader)
+    }
+}
+
+/// Returns true if the file starts with a UTF-16 BOM
+pub(crate) fn starts_with_utf16_bom(path: &str) -> io::Result<bool> {
+    let mut start = Vec::with_capacity(2);
+    fs::File::open(path)?.take(2).read_to_end(&mut start)?;
+    Ok(Encoding::utf16_from_bom(&start).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Counter, Detection, Encoding, LineEndings};
+
+    fn detect(content: &[u8]) -> Detection {
+        // In blocks of 1 byte, so that sequences are split
+        let mut reader = std::io::BufReader::with_capacity(1, content);
+        Counter::all().detect_reader("test", &mut reader).unwrap()
+    }
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        let mut bytes = if big_endian {
+            vec![0xFE, 0xFF]
+        } else {
+            vec![0xFF, 0xFE]
+        };
+        for unit in text.encode_utf16() {
+            if big_endian {
+                bytes.extend(unit.to_be_bytes());
+            } else {
+                bytes.extend(unit.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_detect_utf8() {
+        let detection = detect("a\r\nb\nc\rdé\r\n".as_bytes());
+        assert_eq!(detection.encoding, Encoding::Utf8);
+        assert!(!detection.bom);
+        assert_eq!((detection.lf, detection.crlf, detection.cr), (1, 2, 1));
+        assert_eq!(detection.line_endings(), LineEndings::Mixed);
+        assert!(detection.trailing_newline);
+        assert_eq!(detection.nul_bytes, 0);
+
+        let detection = detect(b"\xEF\xBB\xBFa\r\nb\0");
+        assert!(detection.bom);
+        assert_eq!(detection.line_endings(), LineEndings::Crlf);
+        assert!(!detection.trailing_newline);
+        assert_eq!(detection.nul_bytes, 1);
+
+        let detection = detect(b"");
+        assert_eq!(detection.encoding, Encoding::Utf8);
+        assert_eq!(detection.line_endings(), LineEndings::None);
+        assert!(!detection.trailing_newline);
+    }
+
+    #[test]
+    fn test_detect_latin1() {
+        assert_eq!(detect(b"caf\xE9\r").encoding, Encoding::Latin1);
+        assert_eq!(detect(b"caf\xE9\r").line_endings(), LineEndings::Cr);
+        // Truncated UTF-8 sequence at the end
+        assert_eq!(detect(b"caf\xC3").encoding, Encoding::Latin1);
+    }
+
+    #[test]
+    fn test_detect_utf16() {
+        let detection = detect(&utf16("a\r\nb\0😀\n", false));
+        assert_eq!(detection.encoding, Encoding::Utf16Le);
+        assert!(detection.bom);
+        assert_eq!((detection.lf, detection.crlf, detection.cr), (1, 1, 0));
+        assert_eq!(detection.nul_bytes, 1);
+        assert!(detection.trailing_newline);
+
+        let detection = detect(&utf16("\u{10A}\r", true));
+        assert_eq!(detection.encoding, Encoding::Utf16Be);
+        assert_eq!(detection.line_endings(), LineEndings::Cr);
+    }
+
+    #[test]
+    fn test_count_utf16() {
+        let text = "héllo wörld 😀\nsecond line\n";
+        for big_endian in [false, true] {
+            let content = utf16(text, big_endian);
+            let mut reader = std::io::BufReader::with_capacity(3, &content[..]);
+            let count = Counter::all().count_reader("", &mut reader).unwrap();
+            assert_eq!(
+                (count.lines, count.words, count.chars, count.bytes),
+                (2, 5, 26, content.len() as u64)
+            );
+        }
+
+        // Unpaired surrogates and an odd byte are invalid chars
+        let mut content = vec![0xFF, 0xFE, b'a', 0, 0x00, 0xD8, b' ', 0, 0x00, 0xDC];
+        content.push(b'b');
+        let count = Counter::all().count_reader("", &mut &content[..]).unwrap();
+        assert_eq!((count.words, count.chars), (2, 5));
+    }
+}
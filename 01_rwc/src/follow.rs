@@ -0,0 +1,263 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    time::{Duration, Instant},
+};
+
+use crate::{CountResult, CountState, Counter, BLOCK_SIZE};
+
+/// Counts a file as it grows, like `tail -F` follows it: the content appended since the
+/// last poll is added to the counts, without counting the file again.
+///
+/// If the file is truncated, it is counted again from its start. If it is replaced
+/// (e.g. rotated, the path naming another file), the rest of the old file is counted and
+/// the new file is followed. Counts are those of all the content seen since the file was
+/// opened.
+pub struct Follower {
+    path: String,
+    counter: Counter,
+    file: File,
+    /// Device and inode of the open file, to detect that the path names another file
+    id: Option<(u64, u64)>,
+    /// Bytes of the open file counted so far
+    offset: u64,
+    /// Counts of the files and content that were replaced or truncated
+    previous: CountResult,
+    state: CountState,
+    /// Counts and time of the last poll
+    last: CountResult,
+    last_time: Instant,
+}
+
+/// Counts of a followed file at a poll
+#[derive(Debug, Clone, PartialEq)]
+pub struct Update {
+    /// Counts since the file was opened
+    pub counts: CountResult,
+    /// Counts of the content appended since the previous poll
+    pub delta: CountResult,
+    /// Time since the previous poll, zero for the first counts
+    pub elapsed: Duration,
+    /// Whether the file was truncated since the previous poll
+    pub truncated: bool,
+    /// Whether the path was found to name another file since the previous poll
+    pub replaced: bool,
+}
+
+impl Update {
+    /// Returns the names and values per second of the metrics selected by the counter,
+    /// or None for the first counts. The longest line is not a rate, so it is skipped.
+    pub fn rates(&self, counter: &Counter) -> Option<Vec<(&'static str, f64)>> {
+        if self.elapsed.is_zero() {
+            return None;
+        }
+        let seconds = self.elapsed.as_secs_f64();
+        Some(
+            self.delta
+                .values(counter)
+                .into_iter()
+                .filter(|(name, _)| *name != "max_line_length")
+                .map(|(name, value)| (name, value as f64 / seconds))
+                .collect(),
+        )
+    }
+}
+
+impl Follower {
+    /// Opens the file and counts its current content, returned as the first update
+    pub fn open(path: &str, counter: Counter) -> io::Result<(Follower, Update)> {
+        let file = File::open(path)?;
+        let id = file_id(&file.metadata()?);
+        let mut follower = Follower {
+            path: path.to_string(),
+            counter,
+            file,
+            id,
+            offset: 0,
+            previous: CountResult::new(path),
+            state: CountState::new(counter),
+            last: CountResult::new(path),
+            last_time: Instant::now(),
+        };
+        follower.read_appended()?;
+        let mut update = follower.update(false, false);
+        update.elapsed = Duration::ZERO;
+        Ok((follower, update))
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Counts the content appended since the previous poll
+    pub fn poll(&mut self) -> io::Result<Update> {
+        let truncated = self.file.metadata()?.len() < self.offset;
+        if truncated {
+            self.restart();
+            self.file.seek(SeekFrom::Start(0))?;
+        }
+        self.read_appended()?;
+
+        // While a file is rotated, its path may not exist for a moment: the old file is
+        // then still followed, until the path names the new one
+        let replaced = match fs::metadata(&self.path) {
+            Ok(metadata) => file_id(&metadata) != self.id,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => false,
+            Err(err) => return Err(err),
+        };
+        if replaced {
+            self.file = File::open(&self.path)?;
+            self.id = file_id(&self.file.metadata()?);
+            self.restart();
+            self.read_appended()?;
+        }
+
+        Ok(self.update(truncated, replaced))
+    }
+
+    /// Keeps the counts so far, and counts the following content from a fresh state
+    fn restart(&mut self) {
+        let counts = self.state.finish(&self.path);
+        self.previous = self.previous.clone() + counts;
+        self.state = CountState::new(self.counter);
+        self.offset = 0;
+    }
+
+    fn read_appended(&mut self) -> io::Result<()> {
+        let mut buffer = vec![0; BLOCK_SIZE];
+        loop {
+            let n = match self.file.read(&mut buffer) {
+                Ok(0) => return Ok(()),
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            self.state.update(&buffer[..n]);
+            self.offset += n as u64;
+        }
+    }
+
+    fn update(&mut self, truncated: bool, replaced: bool) -> Update {
+        let counts = self.previous.clone() + self.state.finish(&self.path);
+        let now = Instant::now();
+        let update = Update {
+            delta: counts.clone() - self.last.clone(),
+            counts: counts.clone(),
+            elapsed: now - self.last_time,
+            truncated,
+            replaced,
+        };
+        self.last = counts;
+        self.last_time = now;
+        update
+    }
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// Without inodes, replaced files are not detected
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write, time::Duration};
+
+    use crate::{Counter, Follower};
+
+    fn append(path: &str, content: &str) {
+        let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+    }
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("rwc-follow-{}-{}", name, std::process::id()));
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_follow_appended() {
+        let path = temp_path("appended");
+        fs::write(&path, "one two\nthr").unwrap();
+
+        let (mut follower, first) = Follower::open(&path, Counter::all()).unwrap();
+        assert_eq!((first.counts.lines, first.counts.words), (1, 3));
+        assert_eq!(first.delta, first.counts);
+        assert_eq!(first.elapsed, Duration::ZERO);
+        assert_eq!(first.rates(&Counter::all()), None);
+
+        // The word split between the polls is counted once
+        append(&path, "ee four\n");
+        let update = follower.poll().unwrap();
+        assert_eq!(
+            (
+                update.counts.lines,
+                update.counts.words,
+                update.counts.bytes
+            ),
+            (2, 4, 19)
+        );
+        assert_eq!(
+            (update.delta.lines, update.delta.words, update.delta.bytes),
+            (1, 1, 8)
+        );
+        assert!(!update.truncated && !update.replaced);
+        let rates = update.rates(&Counter::default()).unwrap();
+        assert_eq!(
+            rates.iter().map(|(name, _)| *name).collect::<Vec<_>>(),
+            vec!["lines", "words", "bytes"]
+        );
+
+        let update = follower.poll().unwrap();
+        assert_eq!((update.delta.lines, update.delta.bytes), (0, 0));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_follow_truncated() {
+        let path = temp_path("truncated");
+        fs::write(&path, "a b c\nd e\n").unwrap();
+        let (mut follower, _) = Follower::open(&path, Counter::all()).unwrap();
+
+        fs::write(&path, "f\n").unwrap();
+        let update = follower.poll().unwrap();
+        assert!(update.truncated);
+        assert_eq!((update.counts.lines, update.counts.words), (3, 6));
+        assert_eq!((update.delta.lines, update.delta.bytes), (1, 2));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_follow_replaced() {
+        let path = temp_path("replaced");
+        let rotated = format!("{}.1", path);
+        fs::write(&path, "a\n").unwrap();
+        let (mut follower, _) = Follower::open(&path, Counter::all()).unwrap();
+
+        // Written to the old file before and after it is renamed
+        append(&path, "b\n");
+        fs::rename(&path, &rotated).unwrap();
+        append(&rotated, "c\n");
+        let update = follower.poll().unwrap();
+        assert!(!update.replaced);
+        assert_eq!(update.counts.lines, 3);
+
+        append(&rotated, "d\n");
+        fs::write(&path, "e f\n").unwrap();
+        let update = follower.poll().unwrap();
+        assert!(update.replaced);
+        assert_eq!((update.counts.lines, update.counts.words), (5, 6));
+        assert_eq!(update.delta.lines, 2);
+
+        append(&path, "g\n");
+        assert_eq!(follower.poll().unwrap().counts.lines, 6);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&rotated).unwrap();
+    }
+}
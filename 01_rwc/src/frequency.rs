@@ -0,0 +1,370 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    mem,
+};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{Counter, WordMode};
+
+/// Units of the histogram
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Histogram {
+    Bytes,
+    /// Chars, which are bytes in the C locale
+    Chars,
+}
+
+impl Histogram {
+    pub fn from_name(name: &str) -> Option<Histogram> {
+        match name {
+            "bytes" => Some(Histogram::Bytes),
+            "chars" => Some(Histogram::Chars),
+            _ => None,
+        }
+    }
+}
+
+/// Selects what frequencies are computed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrequencyOptions {
+    /// Count the occurrences of each word
+    pub words: bool,
+    /// Words differing only by case are the same word, in lowercase
+    pub fold_case: bool,
+    /// Remove the chars that are neither letters nor digits at the start and the end of
+    /// words, e.g. quotes and commas. Words that are only punctuation are skipped.
+    pub strip_punctuation: bool,
+    pub histogram: Option<Histogram>,
+}
+
+/// A unit of the histogram
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Symbol {
+    Byte(u8),
+    Char(char),
+}
+
+/// Counts the occurrences of words, bytes or chars in input given in blocks of any size.
+///
+/// Words are the ones counted by the counter: runs of chars separated by whitespace, or
+/// UAX #29 words. Like the other counts, invalid UTF-8 bytes are not chars and they
+/// neither start nor end a word. Unlike `count_frequencies` in rcompress, the content is
+/// counted block by block and read errors are reported.
+#[derive(Debug, Clone)]
+pub struct Frequencies {
+    options: FrequencyOptions,
+    utf8: bool,
+    uax29: bool,
+    /// Incomplete UTF-8 sequence at the end of the previous block
+    pending: Vec<u8>,
+    /// Bytes of the current word
+    word: Vec<u8>,
+    words: HashMap<String, u64>,
+    bytes: Box<[u64; 256]>,
+    chars: HashMap<char, u64>,
+}
+
+impl Frequencies {
+    pub fn new(counter: &Counter, options: FrequencyOptions) -> Self {
+        Frequencies {
+            options,
+            utf8: counter.utf8,
+            uax29: counter.utf8 && counter.word_mode == WordMode::Uax29,
+            pending: Vec::new(),
+            word: Vec::new(),
+            words: HashMap::new(),
+            bytes: Box::new([0; 256]),
+            chars: HashMap::new(),
+        }
+    }
+
+    pub fn update(&mut self, block: &[u8]) {
+        // In the C locale every byte is a char
+        let byte_histogram = match self.options.histogram {
+            Some(Histogram::Bytes) => true,
+            Some(Histogram::Chars) => !self.utf8,
+            None => false,
+        };
+        if byte_histogram {
+            for &b in block {
+                self.bytes[b as usize] += 1;
+            }
+        }
+
+        if !self.utf8 {
+            if self.options.words {
+                for &b in block {
+                    if b.is_ascii_whitespace() {
+                        self.end_word();
+                    } else {
+                        self.word.push(b);
+                    }
+                }
+            }
+            return;
+        }
+
+        if !self.options.words && self.options.histogram != Some(Histogram::Chars) {
+            return;
+        }
+        let mut data = mem::take(&mut self.pending);
+        data.extend_from_slice(block);
+        let mut input = &data[..];
+        loop {
+            match std::str::from_utf8(input) {
+                Ok(text) => {
+                    self.text(text);
+                    break;
+                }
+                Err(err) => {
+                    let (valid, rest) = input.split_at(err.valid_up_to());
+                    self.text(std::str::from_utf8(valid).unwrap_or_default());
+                    match err.error_len() {
+                        Some(len) => input = &rest[len..],
+                        // The sequence may be completed by the next block
+                        None => {
+                            self.pending = rest.to_vec();
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Ends the input, e.g. before the content of the next file
+    pub fn end(&mut self) {
+        self.pending.clear();
+        self.end_word();
+    }
+
+    /// Returns the `n` most frequent words with their number of occurrences. Words with
+    /// the same number are sorted by their bytes, so that the order is stable.
+    pub fn top_words(&self, n: usize) -> Vec<(String, u64)> {
+        let mut words: Vec<(String, u64)> = self
+            .words
+            .iter()
+            .map(|(word, &count)| (word.clone(), count))
+            .collect();
+        words.sort_unstable_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        words.truncate(n);
+        words
+    }
+
+    /// Returns the number of occurrences of the bytes or chars found in the input, in
+    /// the order of their values
+    pub fn histogram(&self) -> Vec<(Symbol, u64)> {
+        let mut symbols: Vec<(Symbol, u64)> = (0..=255u8)
+            .filter(|&b| self.bytes[b as usize] > 0)
+            .map(|b| (Symbol::Byte(b), self.bytes[b as usize]))
+            .chain(
+                self.chars
+                    .iter()
+                    .map(|(&c, &count)| (Symbol::Char(c), count)),
+            )
+            .collect();
+        symbols.sort_unstable();
+        symbols
+    }
+
+    fn text(&mut self, text: &str) {
+        let chars = self.options.histogram == Some(Histogram::Chars);
+        for c in text.chars() {
+            if chars {
+                *self.chars.entry(c).or_insert(0) += 1;
+            }
+            if self.options.words {
+                if c.is_whitespace() {
+                    self.end_word();
+                } else {
+                    let mut buf = [0; 4];
+                    self.word
+                        .extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+        }
+    }
+
+    fn end_word(&mut self) {
+        if self.word.is_empty() {
+            return;
+        }
+        let token = String::from_utf8_lossy(&self.word).into_owned();
+        self.word.clear();
+
+        // No UAX #29 word spans whitespace, so they are found within each token
+        if self.uax29 {
+            for word in token.unicode_words() {
+                self.add_word(word);
+            }
+        } else {
+            self.add_word(&token);
+        }
+    }
+
+    fn add_word(&mut self, word: &str) {
+        let word = if self.options.strip_punctuation {
+            word.trim_matches(|c: char| !c.is_alphanumeric())
+        } else {
+            word
+        };
+        if word.is_empty() {
+            return;
+        }
+        let word = if self.options.fold_case {
+            word.to_lowercase()
+        } else {
+            word.to_string()
+        };
+        *self.words.entry(word).or_insert(0) += 1;
+    }
+}
+
+impl Write for Frequencies {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Counter {
+    /// Adds the frequencies in the content of a reader, decompressed if selected by
+    /// `decompress`
+    pub fn count_frequencies<R: BufRead>(
+        &self,
+        frequencies: &mut Frequencies,
+        reader: &mut R,
+    ) -> io::Result<()> {
+        let result = self.write_content(frequencies, reader);
+        // A failed input doesn't leave a partial word
+        frequencies.end();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Counter, Frequencies, FrequencyOptions, Histogram, Symbol, WordMode};
+
+    fn frequencies(counter: Counter, options: FrequencyOptions, blocks: &[&[u8]]) -> Frequencies {
+        let mut frequencies = Frequencies::new(&counter, options);
+        for block in blocks {
+            frequencies.update(block);
+        }
+        frequencies.end();
+        frequencies
+    }
+
+    fn words(options: FrequencyOptions, text: &str) -> Vec<(String, u64)> {
+        let words = FrequencyOptions {
+            words: true,
+            ..options
+        };
+        frequencies(Counter::all(), words, &[text.as_bytes()]).top_words(10)
+    }
+
+    fn pairs(words: &[(&str, u64)]) -> Vec<(String, u64)> {
+        words
+            .iter()
+            .map(|(word, count)| (word.to_string(), *count))
+            .collect()
+    }
+
+    #[test]
+    fn test_top_words() {
+        let text = "The cat saw the dog. The dog, \"the\" end!";
+        assert_eq!(
+            words(FrequencyOptions::default(), text),
+            pairs(&[
+                ("The", 2),
+                ("\"the\"", 1),
+                ("cat", 1),
+                ("dog,", 1),
+                ("dog.", 1),
+                ("end!", 1),
+                ("saw", 1),
+                ("the", 1),
+            ])
+        );
+        let folded = FrequencyOptions {
+            fold_case: true,
+            strip_punctuation: true,
+            ..FrequencyOptions::default()
+        };
+        assert_eq!(
+            words(folded, text),
+            pairs(&[("the", 4), ("dog", 2), ("cat", 1), ("end", 1), ("saw", 1)])
+        );
+        // Apostrophes within words are kept, and punctuation alone is skipped
+        assert_eq!(
+            words(folded, "don't -- 'stop'"),
+            pairs(&[("don't", 1), ("stop", 1)])
+        );
+    }
+
+    #[test]
+    fn test_words_across_blocks() {
+        let options = FrequencyOptions {
+            words: true,
+            ..FrequencyOptions::default()
+        };
+        let blocks: &[&[u8]] = &[b"caf\xC3", b"\xA9 caf", b"\xC3\xA9\n\xFFx"];
+        assert_eq!(
+            frequencies(Counter::all(), options, blocks).top_words(5),
+            pairs(&[("café", 2), ("x", 1)])
+        );
+
+        let uax29 = Counter {
+            word_mode: WordMode::Uax29,
+            ..Counter::all()
+        };
+        assert_eq!(
+            frequencies(uax29, options, &["读书, 读 (书)".as_bytes()]).top_words(5),
+            pairs(&[("书", 2), ("读", 2)])
+        );
+    }
+
+    #[test]
+    fn test_histogram() {
+        let chars = FrequencyOptions {
+            histogram: Some(Histogram::Chars),
+            ..FrequencyOptions::default()
+        };
+        let blocks: &[&[u8]] = &[b"ba\xC3", b"\xA9a\xFF"];
+        assert_eq!(
+            frequencies(Counter::all(), chars, blocks).histogram(),
+            vec![
+                (Symbol::Char('a'), 2),
+                (Symbol::Char('b'), 1),
+                (Symbol::Char('é'), 1)
+            ]
+        );
+
+        let c_locale = Counter {
+            utf8: false,
+            ..Counter::all()
+        };
+        let bytes = FrequencyOptions {
+            histogram: Some(Histogram::Bytes),
+            ..FrequencyOptions::default()
+        };
+        let expected = vec![
+            (Symbol::Byte(b'a'), 2),
+            (Symbol::Byte(b'b'), 1),
+            (Symbol::Byte(0xA9), 1),
+            (Symbol::Byte(0xC3), 1),
+            (Symbol::Byte(0xFF), 1),
+        ];
+        assert_eq!(
+            frequencies(Counter::all(), bytes, blocks).histogram(),
+            expected
+        );
+        assert_eq!(frequencies(c_locale, chars, blocks).histogram(), expected);
+    }
+}
@@ -0,0 +1,533 @@
+use std::{
+    env, fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    ops::{Add, Sub},
+};
+
+mod decompress;
+mod encoding;
+mod follow;
+mod frequency;
+mod output;
+mod parallel;
+mod segment;
+mod state;
+mod walk;
+
+use encoding::Utf16Filter;
+
+pub use decompress::{Compression, Decompress};
+pub use encoding::{Detection, Encoding, LineEndings};
+pub use follow::{Follower, Update};
+pub use frequency::{Frequencies, FrequencyOptions, Histogram, Symbol};
+pub use output::{Format, Mode, Output};
+pub use parallel::MIN_RANGE_SIZE;
+pub use state::{CountState, BLOCK_SIZE};
+pub use walk::{by_extension, walk, ExtensionSummary, Symlinks, WalkOptions};
+
+/// Word counting rules
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WordMode {
+    /// Words are runs of printable chars separated by whitespace, as in POSIX wc
+    #[default]
+    Posix,
+    /// Words are delimited by UAX #29 word boundaries, so that e.g. each CJK ideograph
+    /// is a word, and punctuation alone isn't. In the C locale words are the POSIX ones.
+    Uax29,
+}
+
+/// Selects which metrics to compute. Metrics that are not selected are left to zero,
+/// so that the work needed for them (e.g. decoding chars) is skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Counter {
+    pub bytes: bool,
+    pub lines: bool,
+    pub words: bool,
+    pub chars: bool,
+    /// Display width of the longest line
+    pub max_line_length: bool,
+    /// Extended grapheme clusters, i.e. user-perceived characters
+    pub graphemes: bool,
+    /// Total display width of the text, e.g. 2 columns for most CJK chars
+    pub display_width: bool,
+    /// How words are counted
+    pub word_mode: WordMode,
+    /// Whether inputs are decompressed, in which case the decompressed content is counted
+    pub decompress: Decompress,
+    /// Decode the input as UTF-8 (as wc does in a UTF-8 locale). When false every byte
+    /// is a char, as in the C locale.
+    pub utf8: bool,
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Counter {
+            bytes: false,
+            lines: false,
+            words: false,
+            chars: false,
+            max_line_length: false,
+            graphemes: false,
+            display_width: false,
+            word_mode: WordMode::Posix,
+            decompress: Decompress::Never,
+            utf8: true,
+        }
+    }
+}
+
+impl Counter {
+    /// Selects all the metrics of GNU wc
+    pub fn all() -> Self {
+        Counter {
+            bytes: true,
+            lines: true,
+            words: true,
+            chars: true,
+            max_line_length: true,
+            ..Counter::default()
+        }
+    }
+
+    /// Returns true if no metric was selected, in which case wc shows lines, words and bytes
+    pub fn is_default(&self) -> bool {
+        !self.bytes
+            && !self.lines
+            && !self.words
+            && !self.chars
+            && !self.max_line_length
+            && !self.graphemes
+            && !self.display_width
+    }
+
+    /// Returns the counter with wc default metrics (lines, words, bytes) if none was selected
+    pub fn or_default(self) -> Self {
+        if self.is_default() {
+            Counter {
+                bytes: true,
+                lines: true,
+                words: true,
+                ..self
+            }
+        } else {
+            self
+        }
+    }
+
+    /// Returns the names of the selected metrics, in the order wc prints them
+    pub fn metrics(&self) -> Vec<&'static str> {
+        [
+            (self.lines, "lines"),
+            (self.words, "words"),
+            (self.chars, "chars"),
+            (self.bytes, "bytes"),
+            (self.max_line_length, "max_line_length"),
+            (self.graphemes, "graphemes"),
+            (self.display_width, "display_width"),
+        ]
+        .iter()
+        .filter(|(selected, _)| *selected)
+        .map(|(_, name)| *name)
+        .collect()
+    }
+
+    /// Returns true if inputs can be counted in parts merged afterwards. Unicode segments
+    /// can't: their boundaries depend on the text around. Neither can compressed data.
+    pub fn splittable(&self) -> bool {
+        let segments =
+            self.utf8 && (self.graphemes || (self.words && self.word_mode == WordMode::Uax29));
+        !segments && self.decompress == Decompress::Never
+    }
+
+    /// Returns true if the metrics need the input to be decoded into chars
+    pub(crate) fn decodes(&self) -> bool {
+        self.chars || self.words || self.max_line_length || self.graphemes || self.display_width
+    }
+
+    /// Counts the content of a reader, decompressed if selected by `decompress`. Any
+    /// byte sequence can be counted: like GNU wc, invalid UTF-8 bytes are not counted as
+    /// chars, and they neither start nor end a word.
+    ///
+    /// With `utf8`, text starting with a UTF-16 BOM is decoded before being counted if the
+    /// metrics need chars, bytes being the ones of the UTF-16 text. Like GNU wc, bytes
+    /// and lines alone are counted without decoding.
+    ///
+    /// The reader's buffer is counted block by block, so memory use doesn't depend on the
+    /// length of the lines.
+    pub fn count_reader<R: BufRead>(&self, name: &str, reader: &mut R) -> io::Result<CountResult> {
+        let mut state = CountState::new(*self);
+        if !self.utf8 || !self.decodes() {
+            self.write_content(&mut state, reader)?;
+            return Ok(state.finish(name));
+        }
+
+        let mut filter = Utf16Filter::new(&mut state);
+        self.write_content(&mut filter, reader)?;
+        filter.finish()?;
+        let bytes = filter.bytes;
+        Ok(CountResult {
+            bytes,
+            ..state.finish(name)
+        })
+    }
+
+    /// Writes the content of the reader, decompressed if selected by `decompress`
+    pub(crate) fn write_content<R: BufRead, W: Write>(
+        &self,
+        writer: &mut W,
+        reader: &mut R,
+    ) -> io::Result<()> {
+        let (compression, start) = self.decompress.compression(reader)?;
+        let mut reader = start.as_slice().chain(reader);
+        match compression {
+            Some(compression) => decompress::decode(compression, &mut reader, writer),
+            None => self.update_from(writer, &mut reader),
+        }
+    }
+
+    /// Updates the state (e.g. a `CountState`) with the whole content of the reader
+    pub(crate) fn update_from<R: BufRead, W: Write>(
+        &self,
+        state: &mut W,
+        reader: &mut R,
+    ) -> io::Result<()> {
+        loop {
+            let block = match reader.fill_buf() {
+                Ok([]) => break,
+                Ok(block) => block,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            state.write_all(block)?;
+            let len = block.len();
+            reader.consume(len);
+        }
+        Ok(())
+    }
+
+    /// Counts a file, or stdin for `-`. Errors are the ones of the OS, e.g. reading a
+    /// directory fails with "Is a directory".
+    pub fn count_file(&self, path: &str) -> io::Result<CountResult> {
+        let mut reader = open_input(path)?;
+        self.count_reader(path, &mut reader)
+    }
+}
+
+/// Opens a file for reading, or stdin for `-` as in GNU wc
+pub fn open_input(path: &str) -> io::Result<Box<dyn BufRead>> {
+    if path == "-" {
+        return Ok(Box::new(BufReader::with_capacity(BLOCK_SIZE, io::stdin())));
+    }
+    let file = fs::File::open(path)?;
+    Ok(Box::new(BufReader::with_capacity(BLOCK_SIZE, file)))
+}
+
+/// Returns true if the locale environment variables select a UTF-8 encoding.
+/// As in POSIX, `LC_ALL` takes precedence over `LC_CTYPE`, which takes precedence over
+/// `LANG`; without any of them the C locale is used.
+pub fn locale_is_utf8() -> bool {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .map(|value| {
+            let value = value.to_lowercase();
+            value.contains("utf-8") || value.contains("utf8")
+        })
+        .unwrap_or(false)
+}
+
+/// Counts all the metrics of a reader
+pub fn count_reader<R: BufRead>(name: &str, reader: &mut R) -> io::Result<CountResult> {
+    Counter::all().count_reader(name, reader)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CountResult {
+    pub path: String,
+    pub bytes: u64,
+    pub words: u64,
+    pub chars: u64,
+    pub lines: u64,
+    pub max_line_length: u64,
+    pub graphemes: u64,
+    pub display_width: u64,
+}
+
+impl CountResult {
+    pub fn new(path: &str) -> CountResult {
+        CountResult {
+            path: path.to_string(),
+            ..CountResult::default()
+        }
+    }
+
+    /// Returns the names and values of the metrics selected by the counter (or the wc
+    /// default ones), in the order wc prints them
+    pub fn values(&self, options: &Counter) -> Vec<(&'static str, u64)> {
+        options
+            .or_default()
+            .metrics()
+            .into_iter()
+            .map(|name| {
+                let value = match name {
+                    "lines" => self.lines,
+                    "words" => self.words,
+                    "chars" => self.chars,
+                    "bytes" => self.bytes,
+                    "max_line_length" => self.max_line_length,
+                    "graphemes" => self.graphemes,
+                    _ => self.display_width,
+                };
+                (name, value)
+            })
+            .collect()
+    }
+
+    /// Formats the metrics selected by the counter right-aligned to `width`, followed by
+    /// the path if any
+    pub fn print(&self, options: &Counter, width: usize) -> String {
+        let mut fields: Vec<String> = self
+            .values(options)
+            .iter()
+            .map(|(_, value)| format!("{:>width$}", value))
+            .collect();
+
+        if !self.path.is_empty() {
+            fields.push(self.path.clone());
+        }
+
+        fields.join(" ")
+    }
+}
+
+impl Add for CountResult {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            path: self.path,
+            bytes: self.bytes + other.bytes,
+            words: self.words + other.words,
+            chars: self.chars + other.chars,
+            lines: self.lines + other.lines,
+            // As in GNU wc, the total shows the longest of all lines
+            max_line_length: self.max_line_length.max(other.max_line_length),
+            graphemes: self.graphemes + other.graphemes,
+            display_width: self.display_width + other.display_width,
+        }
+    }
+}
+
+impl Sub for CountResult {
+    type Output = Self;
+
+    /// Returns the counts of the content following the one counted by `other`. The
+    /// longest line can't be subtracted, so it is the one of `self`.
+    fn sub(self, other: Self) -> Self {
+        Self {
+            path: self.path,
+            bytes: self.bytes.saturating_sub(other.bytes),
+            words: self.words.saturating_sub(other.words),
+            chars: self.chars.saturating_sub(other.chars),
+            lines: self.lines.saturating_sub(other.lines),
+            max_line_length: self.max_line_length,
+            graphemes: self.graphemes.saturating_sub(other.graphemes),
+            display_width: self.display_width.saturating_sub(other.display_width),
+        }
+    }
+}
+
+/// Returns the message of an error without the "(os error N)" suffix, as printed by
+/// GNU tools
+pub fn error_message(error: &io::Error) -> String {
+    let message = error.to_string();
+    match error.raw_os_error() {
+        Some(code) => message
+            .strip_suffix(&format!(" (os error {})", code))
+            .map(str::to_string)
+            .unwrap_or(message),
+        None => message,
+    }
+}
+
+/// Computes the width of the count columns like GNU wc, from the metadata of the inputs
+/// before counting them: enough digits for the total size of the regular files, and at
+/// least 7 if some input is not a regular file (e.g. a pipe), as its size is unknown.
+/// A single metric of a single input is not padded. Inputs that failed are ignored.
+pub fn number_width(inputs: &[io::Result<fs::Metadata>], counter: &Counter) -> usize {
+    if inputs.len() == 1 && counter.or_default().metrics().len() == 1 {
+        return 1;
+    }
+
+    let mut minimum_width = 1;
+    let mut regular_total = 0;
+    for metadata in inputs.iter().flatten() {
+        if metadata.is_file() {
+            regular_total += metadata.len();
+        } else {
+            minimum_width = 7;
+        }
+    }
+    regular_total.to_string().len().max(minimum_width)
+}
+
+/// Reads a list of file names separated by NUL bytes, as given to `--files0-from`.
+/// A trailing NUL is optional; zero-length names are kept for the caller to report.
+pub fn read_files0<R: Read>(reader: &mut R) -> io::Result<Vec<String>> {
+    let mut content = Vec::new();
+    reader.read_to_end(&mut content)?;
+    if content.is_empty() {
+        return Ok(Vec::new());
+    }
+    // A list of a single NUL has one zero-length name
+    if content.last() == Some(&0) {
+        content.pop();
+    }
+    Ok(content
+        .split(|&b| b == 0)
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .collect())
+}
+
+/// Sums the successful counts into a "total" result
+pub fn sum_counts(counts: &[io::Result<CountResult>]) -> CountResult {
+    counts
+        .iter()
+        .filter_map(|count| count.as_ref().ok())
+        .fold(CountResult::new("total"), |acc, count| acc + count.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{
+        count_reader, error_message, number_width, read_files0, sum_counts, CountResult, Counter,
+    };
+
+    #[test]
+    fn test_count_reader() {
+        let mut input = "hello world\nthis is  rwc\nü".as_bytes();
+        let count = count_reader("test", &mut input).unwrap();
+        assert_eq!(
+            count,
+            CountResult {
+                path: "test".to_string(),
+                bytes: 27,
+                words: 6,
+                chars: 26,
+                lines: 2,
+                max_line_length: 12,
+                ..CountResult::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_counter_skips_unselected() {
+        let counter = Counter {
+            lines: true,
+            ..Counter::default()
+        };
+        let mut input = "a b\nc\n".as_bytes();
+        let count = counter.count_reader("", &mut input).unwrap();
+        assert_eq!(count.lines, 2);
+        assert_eq!(count.words, 0);
+        assert_eq!(count.chars, 0);
+    }
+
+    #[test]
+    fn test_print() {
+        let count = CountResult {
+            path: "file".to_string(),
+            bytes: 10,
+            words: 2,
+            chars: 8,
+            lines: 1,
+            max_line_length: 7,
+            ..CountResult::default()
+        };
+        assert_eq!(count.print(&Counter::default(), 1), "1 2 10 file");
+        assert_eq!(count.print(&Counter::default(), 3), "  1   2  10 file");
+        let chars = Counter {
+            chars: true,
+            ..Counter::default()
+        };
+        assert_eq!(count.print(&chars, 1), "8 file");
+        let chars_and_bytes = Counter {
+            chars: true,
+            bytes: true,
+            ..Counter::default()
+        };
+        assert_eq!(count.print(&chars_and_bytes, 2), " 8 10 file");
+        let max_line_length = Counter {
+            lines: true,
+            max_line_length: true,
+            ..Counter::default()
+        };
+        let stdin = CountResult {
+            path: String::new(),
+            ..count
+        };
+        assert_eq!(stdin.print(&max_line_length, 1), "1 7");
+    }
+
+    #[test]
+    fn test_error_message() {
+        let missing = fs::File::open("missing").unwrap_err();
+        assert_eq!(error_message(&missing), "No such file or directory");
+        assert_eq!(error_message(&std::io::Error::other("failed")), "failed");
+    }
+
+    #[test]
+    fn test_number_width() {
+        let file = || fs::metadata("Cargo.toml");
+        let size = file().unwrap().len() * 3;
+        let counter = Counter::default();
+        assert_eq!(
+            number_width(&[file(), file(), file()], &counter),
+            size.to_string().len()
+        );
+        assert_eq!(number_width(&[file(), fs::metadata("src")], &counter), 7);
+        assert_eq!(number_width(&[fs::metadata("missing")], &counter), 1);
+        let lines = Counter {
+            lines: true,
+            ..Counter::default()
+        };
+        assert_eq!(number_width(&[fs::metadata("src")], &lines), 1);
+    }
+
+    #[test]
+    fn test_sum_counts() {
+        let mut a = "a b\n".as_bytes();
+        let mut b = "c\n".as_bytes();
+        let counts = vec![
+            count_reader("a", &mut a),
+            count_reader("b", &mut b),
+            Err(std::io::Error::other("failed")),
+        ];
+        let total = sum_counts(&counts);
+        assert_eq!(total.path, "total");
+        assert_eq!(total.lines, 2);
+        assert_eq!(total.words, 3);
+        assert_eq!(total.max_line_length, 3);
+    }
+
+    #[test]
+    fn test_read_files0() {
+        let mut list = "a\0dir/b c\0\0d".as_bytes();
+        assert_eq!(
+            read_files0(&mut list).unwrap(),
+            vec!["a", "dir/b c", "", "d"]
+        );
+        let mut list = "a\0".as_bytes();
+        assert_eq!(read_files0(&mut list).unwrap(), vec!["a"]);
+        let mut list = "".as_bytes();
+        assert!(read_files0(&mut list).unwrap().is_empty());
+        let mut list = "\0".as_bytes();
+        assert_eq!(read_files0(&mut list).unwrap(), vec![""]);
+        let mut list = "a\0\0".as_bytes();
+        assert_eq!(read_files0(&mut list).unwrap(), vec!["a", ""]);
+    }
+}
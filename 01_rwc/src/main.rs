@@ -1,221 +1,419 @@
-use getopt::Opt;
+use args::{parse_args, Args, Command, TotalMode};
+use rwc::{
+    by_extension, error_message, locale_is_utf8, number_width, open_input, read_files0, sum_counts,
+    walk, CountResult, Counter, ExtensionSummary, Follower, Format, Frequencies, Mode, Output,
+};
 use std::{
     env, fs,
-    io::{self, BufRead, BufReader, Error},
-    ops::Add,
+    io::{self, BufReader, Write},
+    path::Path,
     process::exit,
+    thread,
 };
 
-#[derive(Debug)]
-struct Args {
-    exe_name: String,
-    count_bytes: bool,
-    count_lines: bool,
-    count_chars: bool,
-    count_words: bool,
-    paths: Vec<String>,
-}
+mod args;
 
 fn main() {
-    let args = get_args();
-
-    // If no path is given, count from stdin
-    if args.paths.len() == 0 {
-        let stdin_count = count_stdin();
-        print_count(&stdin_count, &args);
-        return;
-    }
+    let cmd_args: Vec<String> = env::args().collect();
+    // Messages are prefixed with the program name, without its directory
+    let exe_name = cmd_args.first().map_or("rwc".to_string(), |arg0| {
+        Path::new(arg0)
+            .file_name()
+            .map_or(arg0.clone(), |name| name.to_string_lossy().into_owned())
+    });
 
-    // Count from all given files
-    let counts: Vec<Result<CountResult, std::io::Error>> = args
-        .paths
-        .iter()
-        .map(|path| count_file(path.to_string()))
-        .collect();
-
-    counts.iter().for_each(|count| print_count(&count, &args));
+    let options = cmd_args.get(1..).unwrap_or_default();
+    let mut args = match parse_args(&exe_name, options, locale_is_utf8()) {
+        Ok(Command::Count(args)) => args,
+        Ok(Command::Help(usage)) => {
+            print!("{}", usage);
+            exit(0);
+        }
+        Err(message) => fail(&exe_name, &message),
+    };
+    list_paths(&mut args);
 
-    // Show total count if multiple files
-    if counts.len() > 1 {
-        let sum = sum_counts(counts);
-        print_count(&Ok(sum), &args);
+    let result = if args.follow {
+        follow(&args)
+    } else if args.frequencies() {
+        frequencies(&args)
+    } else if args.detect {
+        detect(&args)
+    } else {
+        report(&args)
+    };
+    match result {
+        Ok(true) if !args.input_errors => {}
+        Ok(_) => exit(1),
+        Err(err) => fail(
+            &args.exe_name,
+            &format!("write error: {}", error_message(&err)),
+        ),
     }
 }
 
-fn sum_counts(counts: Vec<Result<CountResult, Error>>) -> CountResult {
-    let sum: CountResult = counts.iter().filter_map(|count| count.as_ref().ok()).fold(
-        CountResult {
-            path: "total".to_string(),
-            bytes: 0,
-            words: 0,
-            chars: 0,
-            lines: 0,
-        },
-        |acc, count| acc + count.clone(),
-    );
-    sum
-}
-
-fn get_args() -> Args {
-    let mut args = Args {
-        exe_name: "".to_string(),
-        count_bytes: false,
-        count_lines: false,
-        count_chars: false,
-        count_words: false,
-        paths: Vec::new(),
-    };
+/// Reads the paths of `--files0-from` and lists the files in directories with `-r`,
+/// reporting the paths that can't be listed
+fn list_paths(args: &mut Args) {
+    if let Some(list) = &args.files0_from {
+        args.paths = files0_paths(&args.exe_name, list);
+    }
 
-    let mut cmd_args: Vec<String> = env::args().collect();
-    args.exe_name = cmd_args[0].clone();
-
-    let mut opts = getopt::Parser::new(&cmd_args, "clmwh");
-    loop {
-        match opts.next().transpose().unwrap() {
-            None => break,
-            Some(opt) => match opt {
-                Opt('c', None) => args.count_bytes = true,
-                Opt('l', None) => args.count_lines = true,
-                Opt('m', None) => args.count_chars = true,
-                Opt('w', None) => args.count_words = true,
-                Opt('h', None) => {
-                    print_usage();
-                    exit(0);
+    if args.recursive {
+        // Zero-length names are reported with the other listing errors, before the counts
+        for index in 0..args.paths.len() {
+            args.input_errors |= invalid_name(args, index);
+        }
+        args.paths.retain(|path| !path.is_empty());
+        // Like grep -r, the current directory is counted by default
+        if args.paths.is_empty() && args.files0_from.is_none() {
+            args.paths.push(".".to_string());
+        }
+        let mut paths = Vec::new();
+        for path in walk(&args.paths, &args.walk) {
+            match path {
+                Ok(path) => paths.push(path),
+                Err(err) => {
+                    eprintln!("{}: {}", args.exe_name, error_message(&err));
+                    args.input_errors = true;
                 }
-                _ => unreachable!(),
-            },
+            }
         }
+        args.paths = paths;
     }
-    args.paths = cmd_args.split_off(opts.index());
-    args
 }
 
-fn print_usage() {
-    println!(
-        "Usage: {} [-c] [-l] [-m] [-w] [FILES]",
-        env::args().collect::<Vec<String>>()[0]
-    );
+/// Returns the paths of the inputs, the empty path being stdin when no path is given
+fn input_paths(args: &Args) -> Vec<&str> {
+    if args.reads_stdin() {
+        vec![""]
+    } else {
+        args.paths.iter().map(String::as_str).collect()
+    }
 }
 
-#[derive(Debug, Clone)]
-struct CountResult {
-    path: String,
-    bytes: u64,
-    words: u64,
-    chars: u64,
-    lines: u64,
-}
+/// Counts the inputs and prints their counts, followed by the total. Returns false if
+/// some input couldn't be counted.
+fn report(args: &Args) -> io::Result<bool> {
+    // Only compute what is going to be printed
+    let counter = args.counter.or_default();
 
-impl CountResult {
-    pub fn new(path: &str) -> CountResult {
-        CountResult {
-            path: path.to_string(),
-            bytes: 0,
-            words: 0,
-            chars: 0,
-            lines: 0,
-        }
-    }
+    // If no path is given, count from stdin
+    let from_stdin = args.reads_stdin();
+    let inputs: Vec<io::Result<fs::Metadata>> = if from_stdin {
+        vec![stdin_metadata()]
+    } else {
+        args.paths.iter().map(|path| input_metadata(path)).collect()
+    };
+    let width = number_width(&inputs, &counter);
+    let mode = match args.by_extension {
+        true => Mode::ByExtension,
+        false => Mode::Counts,
+    };
+    let mut output = Output::new(io::stdout().lock(), args.format, counter, width, mode);
+    output.start()?;
 
-    pub fn print(&self, options: &Args) -> String {
-        let default_option = !options.count_bytes
-            && !options.count_lines
-            && !options.count_chars
-            && !options.count_words;
+    let mut counts: Vec<io::Result<CountResult>> = Vec::with_capacity(inputs.len());
+    if from_stdin {
+        let count = count_stdin(&counter);
+        print_count(&mut output, "", &count, args)?;
+        counts.push(count);
+    } else {
+        // Print each count as soon as it is available, stopping at the first write error
+        let mut written = Ok(());
+        counter.count_files(&args.paths, args.jobs, args.split, |count| {
+            let path = &args.paths[counts.len()];
+            if invalid_name(args, counts.len()) {
+                counts.push(count);
+                return;
+            }
+            if written.is_ok() {
+                written = print_count(&mut output, path, &count, args);
+            }
+            counts.push(count);
+        });
+        written?;
+    }
 
-        let mut fields: Vec<String> = Vec::new();
+    if args.by_extension {
+        let summaries = by_extension(&counts);
+        let total = ExtensionSummary {
+            extension: String::new(),
+            files: summaries.iter().map(|summary| summary.files).sum(),
+            counts: sum_counts(&counts),
+        };
+        let show_total = match args.total {
+            TotalMode::Auto => summaries.len() > 1,
+            TotalMode::Always | TotalMode::Only => true,
+            TotalMode::Never => false,
+        };
+        let rows = if args.total == TotalMode::Only {
+            &[]
+        } else {
+            &summaries[..]
+        };
+        output.summary(rows, show_total.then_some(&total))?;
+        output.finish()?;
+        return Ok(counts.iter().all(Result::is_ok));
+    }
 
-        if default_option || options.count_lines {
-            fields.push(self.lines.to_string());
+    let show_total = match args.total {
+        TotalMode::Auto => counts.len() > 1,
+        TotalMode::Always | TotalMode::Only => true,
+        TotalMode::Never => false,
+    };
+    if show_total {
+        let mut total = sum_counts(&counts);
+        // GNU wc doesn't label the total when it is the only line
+        if args.total == TotalMode::Only {
+            total.path.clear();
         }
+        output.total(&total)?;
+    }
+    output.finish()?;
+    Ok(counts.iter().all(Result::is_ok))
+}
 
-        if default_option || options.count_words {
-            fields.push(self.words.to_string());
-        }
+/// Prints the counts of the files, then their updates every interval until interrupted.
+/// Returns false once none of them can be followed.
+fn follow(args: &Args) -> io::Result<bool> {
+    let counter = args.counter.or_default();
+    // As the counts grow, columns are at least as wide as for inputs of unknown size
+    let inputs: Vec<io::Result<fs::Metadata>> =
+        args.paths.iter().map(|path| input_metadata(path)).collect();
+    let width = number_width(&inputs, &counter).max(7);
+    let mut output = Output::new(
+        io::stdout().lock(),
+        args.format,
+        counter,
+        width,
+        Mode::Follow {
+            deltas: args.deltas,
+        },
+    );
+    output.start()?;
 
-        if options.count_chars {
-            fields.push(self.chars.to_string());
-        } else if default_option || (options.count_bytes && !options.count_chars) {
-            fields.push(self.bytes.to_string());
+    let mut followers = Vec::with_capacity(args.paths.len());
+    for (index, path) in args.paths.iter().enumerate() {
+        if invalid_name(args, index) {
+            continue;
         }
+        match Follower::open(path, counter) {
+            Ok((follower, update)) => {
+                output.update(&update)?;
+                followers.push(follower);
+            }
+            Err(err) => print_error(args, path, &err),
+        }
+    }
 
-        fields.push(self.path.clone());
-
-        fields.join("\t")
+    while !followers.is_empty() {
+        thread::sleep(args.interval);
+        let mut index = 0;
+        while index < followers.len() {
+            let follower = &mut followers[index];
+            match follower.poll() {
+                Ok(update) => {
+                    // Messages of tail -F
+                    if update.truncated {
+                        eprintln!("{}: {}: file truncated", args.exe_name, follower.path());
+                    }
+                    if update.replaced {
+                        eprintln!(
+                            "{}: '{}' has been replaced; following new file",
+                            args.exe_name,
+                            follower.path()
+                        );
+                    }
+                    // Rates are printed even when nothing was appended
+                    let changed = update.delta.bytes > 0 || update.truncated || update.replaced;
+                    if changed || args.deltas || args.format != Format::Text {
+                        output.update(&update)?;
+                    }
+                    index += 1;
+                }
+                Err(err) => {
+                    print_error(args, follower.path(), &err);
+                    followers.remove(index);
+                }
+            }
+        }
     }
+    output.finish()?;
+    Ok(false)
 }
 
-impl Add for CountResult {
-    type Output = Self;
-
-    fn add(self, other: Self) -> Self {
-        Self {
-            path: self.path,
-            bytes: self.bytes + other.bytes,
-            words: self.words + other.words,
-            chars: self.chars + other.chars,
-            lines: self.lines + other.lines,
+/// Prints the most frequent words and the histogram of all the inputs. Returns false if
+/// some input couldn't be read.
+fn frequencies(args: &Args) -> io::Result<bool> {
+    let mut frequencies = Frequencies::new(&args.counter, args.frequency);
+    let mut all_read = true;
+    for (index, path) in input_paths(args).into_iter().enumerate() {
+        if invalid_name(args, index) {
+            all_read = false;
+            continue;
+        }
+        let result = open_input(if path.is_empty() { "-" } else { path }).and_then(|mut reader| {
+            args.counter
+                .count_frequencies(&mut frequencies, &mut reader)
+        });
+        if let Err(err) = result {
+            print_error(args, path, &err);
+            all_read = false;
         }
     }
+
+    let words = args.top.map_or(Vec::new(), |n| frequencies.top_words(n));
+    let histogram = match args.frequency.histogram {
+        Some(_) => frequencies.histogram(),
+        None => Vec::new(),
+    };
+    let mut output = Output::new(
+        io::stdout().lock(),
+        args.format,
+        args.counter,
+        1,
+        Mode::Frequencies,
+    );
+    output.start()?;
+    output.frequencies(&words, &histogram)?;
+    output.finish()?;
+    Ok(all_read)
 }
 
-fn count_file(path: String) -> Result<CountResult, std::io::Error> {
-    // Try to open file
-    match fs::metadata(&path) {
-        Ok(metadata) => {
-            if metadata.is_dir() {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("{}: is a directory", path),
-                ));
-            }
+/// Prints the encoding and line endings of each input. Returns false if some input
+/// couldn't be read.
+fn detect(args: &Args) -> io::Result<bool> {
+    let mut output = Output::new(
+        io::stdout().lock(),
+        args.format,
+        args.counter,
+        1,
+        Mode::Detect,
+    );
+    output.start()?;
+    let mut all_read = true;
+    for (index, path) in input_paths(args).into_iter().enumerate() {
+        if invalid_name(args, index) {
+            all_read = false;
+            continue;
         }
-        Err(err) => {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                format!("{}: open: no such file or directory", path),
-            ))
+        let detection = match path {
+            "" => args
+                .counter
+                .detect_reader(path, &mut BufReader::new(io::stdin())),
+            path => args.counter.detect_file(path),
+        };
+        all_read &= detection.is_ok();
+        match detection {
+            Ok(detection) => output.detection(&detection)?,
+            Err(err) if args.format == Format::Text => print_error(args, path, &err),
+            Err(err) => output.error(path, &err)?,
         }
     }
+    output.finish()?;
+    Ok(all_read)
+}
 
-    // Count file stats
-    let file = fs::File::open(&path).unwrap();
-    let mut reader = BufReader::new(file);
-    Ok(count_buf(path, &mut reader))
+fn fail(exe_name: &str, message: &str) -> ! {
+    eprintln!("{}: {}", exe_name, message);
+    exit(1);
 }
 
-fn count_stdin() -> Result<CountResult, std::io::Error> {
-    let mut reader = Box::new(BufReader::new(io::stdin()));
-    Ok(count_buf("".to_string(), &mut reader))
+/// Reads the paths of `--files0-from`, keeping zero-length names to report them in turn
+fn files0_paths(exe_name: &str, list: &str) -> Vec<String> {
+    let names = if list == "-" {
+        read_files0(&mut io::stdin().lock())
+    } else {
+        fs::File::open(list).and_then(|mut file| read_files0(&mut file))
+    };
+    match names {
+        Ok(names) => names,
+        Err(err) => fail(
+            exe_name,
+            &format!(
+                "cannot open '{}' for reading: {}",
+                list,
+                error_message(&err)
+            ),
+        ),
+    }
 }
 
-fn count_buf<R: BufRead>(name: String, reader: &mut R) -> CountResult {
-    let mut res: CountResult = CountResult::new(name.as_str());
+/// Reports the path at `index` if it is a zero-length name of `--files0-from`, which
+/// GNU wc does in turn with the counts. Returns whether it was one.
+fn invalid_name(args: &Args, index: usize) -> bool {
+    match &args.files0_from {
+        Some(list) if args.paths[index].is_empty() => {
+            eprintln!(
+                "{}: {}:{}: invalid zero-length file name",
+                args.exe_name,
+                list,
+                index + 1
+            );
+            true
+        }
+        _ => false,
+    }
+}
 
-    let mut buf = Vec::<u8>::new();
+fn count_stdin(counter: &Counter) -> io::Result<CountResult> {
+    let mut reader = BufReader::new(io::stdin());
+    counter.count_reader("", &mut reader)
+}
 
-    while reader
-        .read_until(b'\n', &mut buf)
-        .expect("read_until failed")
-        != 0
-    {
-        let line = String::from_utf8(buf).expect("from_utf8 failed");
+/// Returns the metadata of a file, or of stdin for `-`
+fn input_metadata(path: &str) -> io::Result<fs::Metadata> {
+    match path {
+        "-" => stdin_metadata(),
+        path => fs::metadata(path),
+    }
+}
 
-        res.lines += 1;
-        res.chars += line.chars().count() as u64;
-        res.bytes += line.bytes().count() as u64;
-        res.words += line.split_whitespace().count() as u64;
+/// Returns the metadata of stdin, used to know if it is a regular file
+#[cfg(unix)]
+fn stdin_metadata() -> io::Result<fs::Metadata> {
+    use std::os::fd::AsFd;
+    let fd = io::stdin().as_fd().try_clone_to_owned()?;
+    fs::File::from(fd).metadata()
+}
 
-        // return ownership to buf
-        buf = line.into_bytes();
-        buf.clear();
-    }
-    res
+#[cfg(not(unix))]
+fn stdin_metadata() -> io::Result<fs::Metadata> {
+    Err(io::Error::other("stdin metadata unavailable"))
 }
 
-fn print_count(count: &Result<CountResult, std::io::Error>, options: &Args) {
+fn print_count<W: Write>(
+    output: &mut Output<W>,
+    path: &str,
+    count: &io::Result<CountResult>,
+    options: &Args,
+) -> io::Result<()> {
     match count {
-        Ok(count) => println!("  {}", &count.print(options)),
-        Err(error) => println!("{}: {}", options.exe_name, error.to_string()),
+        Ok(_) if options.total == TotalMode::Only || options.by_extension => Ok(()),
+        Ok(count) => output.count(count),
+        // Like GNU wc, a directory also gets a line of zeros
+        Err(error)
+            if options.format == Format::Text
+                && error.kind() == io::ErrorKind::IsADirectory
+                && !options.by_extension =>
+        {
+            print_error(options, path, error);
+            if options.total == TotalMode::Only {
+                return Ok(());
+            }
+            output.count(&CountResult::new(path))
+        }
+        Err(error) if options.format == Format::Text || options.by_extension => {
+            print_error(options, path, error);
+            Ok(())
+        }
+        Err(error) => output.error(path, error),
     }
 }
+
+fn print_error(options: &Args, path: &str, error: &io::Error) {
+    // Like GNU wc, stdin is named "-" in messages
+    let path = if path.is_empty() { "-" } else { path };
+    eprintln!("{}: {}: {}", options.exe_name, path, error_message(error));
+}
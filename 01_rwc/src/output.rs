@@ -0,0 +1,870 @@
+use std::io::{self, Write};
+
+use crate::{
+    error_message, CountResult, Counter, Detection, ExtensionSummary, LineEndings, Symbol, Update,
+};
+
+/// Fields of detections in the CSV format
+const DETECTION_COLUMNS: [&str; 8] = [
+    "encoding",
+    "bom",
+    "line_endings",
+    "lf",
+    "crlf",
+    "cr",
+    "trailing_newline",
+    "nul_bytes",
+];
+
+/// Output format of the counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Right-aligned columns, as printed by GNU wc
+    Text,
+    /// A JSON array with one object per entry
+    Json,
+    /// A header line followed by one line per entry
+    Csv,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// What a report is made of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Counts of files, errors and the total, written by `count`, `error` and `total`
+    Counts,
+    /// A summary by extension, written by `summary`. Errors are left to the caller in all
+    /// formats.
+    ByExtension,
+    /// A stream of updates, written by `update` and flushed as soon as they are. As the
+    /// stream has no end, JSON updates are objects on their own lines (JSON Lines) instead
+    /// of an array. Errors are left to the caller in all formats.
+    ///
+    /// In the text format updates show the counts, or with `deltas` the counts of the
+    /// appended content and their rates per second.
+    Follow { deltas: bool },
+    /// The frequencies of words and of bytes or chars, written by `frequencies`. Errors
+    /// are left to the caller in all formats.
+    Frequencies,
+    /// What is detected in each input, written by `detection` and `error`, instead of
+    /// their counts
+    Detect,
+}
+
+/// Writes the entries of a report: counts of files, errors and the total.
+///
+/// In the JSON and CSV formats every entry has a `kind` (`file`, `error` or `total`),
+/// the path (empty for stdin and the total), the selected metrics and the error message.
+/// In the text format errors are left to the caller.
+///
+/// Depending on its `Mode`, a report can instead be a summary by extension, whose entries
+/// have the `extension` kind and the number of files, a stream of the updates of followed
+/// files, frequencies, or what is detected in the inputs.
+pub struct Output<W: Write> {
+    out: W,
+    format: Format,
+    counter: Counter,
+    width: usize,
+    mode: Mode,
+    entries: usize,
+}
+
+impl<W: Write> Output<W> {
+    pub fn new(out: W, format: Format, counter: Counter, width: usize, mode: Mode) -> Self {
+        Output {
+            out,
+            format,
+            counter: counter.or_default(),
+            width,
+            mode,
+            entries: 0,
+        }
+    }
+
+    /// Writes what comes before the entries
+    pub fn start(&mut self) -> io::Result<()> {
+        match (self.mode, self.format) {
+            (_, Format::Text) | (Mode::Follow { .. }, Format::Json) => Ok(()),
+            (Mode::Follow { .. }, Format::Csv) => {
+                let metrics = self.counter.metrics();
+                writeln!(
+                    self.out,
+                    "kind,path,seconds,{},{},{}",
+                    metrics.join(","),
+                    metrics
+                        .iter()
+                        .map(|name| format!("{}_delta", name))
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    self.rate_metrics()
+                        .iter()
+                        .map(|name| format!("{}_per_second", name))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                )
+            }
+            (_, Format::Json) => write!(self.out, "["),
+            (Mode::Frequencies, Format::Csv) => writeln!(self.out, "kind,value,count"),
+            (Mode::Detect, Format::Csv) => {
+                writeln!(self.out, "kind,path,{},error", DETECTION_COLUMNS.join(","))
+            }
+            (Mode::ByExtension, Format::Csv) => writeln!(
+                self.out,
+                "kind,extension,files,{}",
+                self.counter.metrics().join(",")
+            ),
+            (Mode::Counts, Format::Csv) => {
+                writeln!(
+                    self.out,
+                    "kind,path,{},error",
+                    self.counter.metrics().join(",")
+                )
+            }
+        }
+    }
+
+    /// Writes the counts by extension, followed by their total if any. In the text
+    /// format they are a table with a header, columns being as wide as their values.
+    pub fn summary(
+        &mut self,
+        summaries: &[ExtensionSummary],
+        total: Option<&ExtensionSummary>,
+    ) -> io::Result<()> {
+        let rows: Vec<(&str, &ExtensionSummary)> = summaries
+            .iter()
+            .map(|summary| ("extension", summary))
+            .chain(total.map(|total| ("total", total)))
+            .collect();
+
+        match self.format {
+            Format::Text => {
+                let mut table: Vec<Vec<String>> = vec![["extension", "files"]
+                    .into_iter()
+                    .chain(self.counter.metrics())
+                    .map(str::to_string)
+                    .collect()];
+                for (kind, summary) in &rows {
+                    let label = match (*kind, summary.extension.as_str()) {
+                        ("total", _) => "total".to_string(),
+                        (_, "") => "(none)".to_string(),
+                        (_, extension) => format!(".{}", extension),
+                    };
+                    let values = summary.counts.values(&self.counter);
+                    table.push(
+                        [label, summary.files.to_string()]
+                            .into_iter()
+                            .chain(values.iter().map(|(_, value)| value.to_string()))
+                            .collect(),
+                    );
+                }
+
+                let widths: Vec<usize> = (0..table[0].len())
+                    .map(|column| table.iter().map(|row| row[column].len()).max().unwrap_or(0))
+                    .collect();
+                for row in table {
+                    let cells: Vec<String> = row
+                        .iter()
+                        .zip(&widths)
+                        .enumerate()
+                        .map(|(column, (cell, &width))| match column {
+                            0 => format!("{:<width$}", cell),
+                            _ => format!("{:>width$}", cell),
+                        })
+                        .collect();
+                    writeln!(self.out, "{}", cells.join("  ").trim_end())?;
+                }
+                Ok(())
+            }
+            Format::Json => {
+                for (kind, summary) in rows {
+                    self.separator()?;
+                    write!(self.out, "{{\"kind\":\"{}\"", kind)?;
+                    if kind == "extension" {
+                        write!(
+                            self.out,
+                            ",\"extension\":{}",
+                            json_string(&summary.extension)
+                        )?;
+                    }
+                    write!(self.out, ",\"files\":{}", summary.files)?;
+                    for (name, value) in summary.counts.values(&self.counter) {
+                        write!(self.out, ",\"{}\":{}", name, value)?;
+                    }
+                    write!(self.out, "}}")?;
+                }
+                Ok(())
+            }
+            Format::Csv => {
+                for (kind, summary) in rows {
+                    let extension = if kind == "total" {
+                        ""
+                    } else {
+                        &summary.extension
+                    };
+                    let values: Vec<String> = summary
+                        .counts
+                        .values(&self.counter)
+                        .iter()
+                        .map(|(_, value)| value.to_string())
+                        .collect();
+                    writeln!(
+                        self.out,
+                        "{},{},{},{}",
+                        kind,
+                        csv_field(extension),
+                        summary.files,
+                        values.join(",")
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes the most frequent words followed by the histogram. In the text format each
+    /// line has the number of occurrences before the word, byte or char, as printed by
+    /// `uniq -c`, and a blank line separates the words from the histogram.
+    pub fn frequencies(
+        &mut self,
+        words: &[(String, u64)],
+        histogram: &[(Symbol, u64)],
+    ) -> io::Result<()> {
+        match self.format {
+            Format::Text => {
+                let width = |counts: &mut dyn Iterator<Item = u64>| {
+                    counts.max().unwrap_or(0).to_string().len()
+                };
+                let words_width = width(&mut words.iter().map(|(_, count)| *count));
+                for (word, count) in words {
+                    writeln!(self.out, "{:>words_width$} {}", count, word)?;
+                }
+                if !words.is_empty() && !histogram.is_empty() {
+                    writeln!(self.out)?;
+                }
+                let symbols_width = width(&mut histogram.iter().map(|(_, count)| *count));
+                for (symbol, count) in histogram {
+                    let label = match *symbol {
+                        Symbol::Byte(b) if b.is_ascii_graphic() || b == b' ' => {
+                            format!("'{}'", (b as char).escape_debug())
+                        }
+                        Symbol::Byte(b) => format!("0x{:02x}", b),
+                        Symbol::Char(c) => format!("'{}'", c.escape_debug()),
+                    };
+                    writeln!(self.out, "{:>symbols_width$} {}", count, label)?;
+                }
+                Ok(())
+            }
+            Format::Json => {
+                for (word, count) in words {
+                    self.separator()?;
+                    write!(
+                        self.out,
+                        "{{\"kind\":\"word\",\"word\":{},\"count\":{}}}",
+                        json_string(word),
+                        count
+                    )?;
+                }
+                for (symbol, count) in histogram {
+                    self.separator()?;
+                    match *symbol {
+                        Symbol::Byte(b) => write!(
+                            self.out,
+                            "{{\"kind\":\"byte\",\"byte\":{},\"count\":{}}}",
+                            b, count
+                        ),
+                        Symbol::Char(c) => write!(
+                            self.out,
+                            "{{\"kind\":\"char\",\"char\":{},\"count\":{}}}",
+                            json_string(&c.to_string()),
+                            count
+                        ),
+                    }?;
+                }
+                Ok(())
+            }
+            Format::Csv => {
+                for (word, count) in words {
+                    writeln!(self.out, "word,{},{}", csv_field(word), count)?;
+                }
+                for (symbol, count) in histogram {
+                    match *symbol {
+                        Symbol::Byte(b) => writeln!(self.out, "byte,{},{}", b, count),
+                        Symbol::Char(c) => {
+                            writeln!(self.out, "char,{},{}", csv_field(&c.to_string()), count)
+                        }
+                    }?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes what is detected in an input. In the text format it is a line like
+    /// `file.txt: UTF-8 with BOM, CRLF line endings (12), trailing newline`.
+    pub fn detection(&mut self, detection: &Detection) -> io::Result<()> {
+        let endings = detection.line_endings();
+        match self.format {
+            Format::Text => {
+                let mut fields = vec![if detection.bom {
+                    format!("{} with BOM", detection.encoding.name())
+                } else {
+                    detection.encoding.name().to_string()
+                }];
+                fields.push(match endings {
+                    LineEndings::None => "no line endings".to_string(),
+                    LineEndings::Mixed => {
+                        let counts: Vec<String> = [
+                            ("LF", detection.lf),
+                            ("CRLF", detection.crlf),
+                            ("CR", detection.cr),
+                        ]
+                        .iter()
+                        .filter(|(_, count)| *count > 0)
+                        .map(|(name, count)| format!("{} {}", name, count))
+                        .collect();
+                        format!("mixed line endings ({})", counts.join(", "))
+                    }
+                    _ => format!(
+                        "{} line endings ({})",
+                        endings.name(),
+                        detection.lf + detection.crlf + detection.cr
+                    ),
+                });
+                fields.push(match detection.trailing_newline {
+                    true => "trailing newline".to_string(),
+                    false => "no trailing newline".to_string(),
+                });
+                match detection.nul_bytes {
+                    0 => {}
+                    1 => fields.push("1 NUL byte".to_string()),
+                    n => fields.push(format!("{} NUL bytes", n)),
+                }
+                // Like GNU wc, stdin is named "-"
+                let path = match detection.path.as_str() {
+                    "" => "-",
+                    path => path,
+                };
+                writeln!(self.out, "{}: {}", path, fields.join(", "))
+            }
+            Format::Json => {
+                self.separator()?;
+                write!(
+                    self.out,
+                    "{{\"kind\":\"file\",\"path\":{},\"encoding\":\"{}\",\"bom\":{},\
+                     \"line_endings\":\"{}\",\"lf\":{},\"crlf\":{},\"cr\":{},\
+                     \"trailing_newline\":{},\"nul_bytes\":{}}}",
+                    json_string(&detection.path),
+                    detection.encoding.name(),
+                    detection.bom,
+                    endings.name(),
+                    detection.lf,
+                    detection.crlf,
+                    detection.cr,
+                    detection.trailing_newline,
+                    detection.nul_bytes
+                )
+            }
+            Format::Csv => writeln!(
+                self.out,
+                "file,{},{},{},{},{},{},{},{},{},",
+                csv_field(&detection.path),
+                detection.encoding.name(),
+                detection.bom,
+                endings.name(),
+                detection.lf,
+                detection.crlf,
+                detection.cr,
+                detection.trailing_newline,
+                detection.nul_bytes
+            ),
+        }
+    }
+
+    /// Writes the counts of a followed file
+    pub fn update(&mut self, update: &Update) -> io::Result<()> {
+        let seconds = update.elapsed.as_secs_f64();
+        let rates = update.rates(&self.counter);
+        match self.format {
+            Format::Text if self.mode == (Mode::Follow { deltas: true }) => {
+                let delta = update.delta.print(&self.counter, self.width);
+                match rates {
+                    Some(rates) => {
+                        let rates: Vec<String> = rates
+                            .iter()
+                            .map(|(name, rate)| format!("{:.1} {}/s", rate, name))
+                            .collect();
+                        writeln!(self.out, "{} ({})", delta, rates.join(", "))
+                    }
+                    None => writeln!(self.out, "{}", delta),
+                }
+            }
+            Format::Text => writeln!(
+                self.out,
+                "{}",
+                update.counts.print(&self.counter, self.width)
+            ),
+            Format::Json => {
+                let object = |values: Vec<String>| format!("{{{}}}", values.join(","));
+                let counts = update
+                    .counts
+                    .values(&self.counter)
+                    .iter()
+                    .map(|(name, value)| format!(",\"{}\":{}", name, value))
+                    .collect::<String>();
+                let delta = update
+                    .delta
+                    .values(&self.counter)
+                    .iter()
+                    .map(|(name, value)| format!("\"{}\":{}", name, value))
+                    .collect();
+                let rates = match rates {
+                    Some(rates) => object(
+                        rates
+                            .iter()
+                            .map(|(name, rate)| format!("\"{}\":{:.3}", name, rate))
+                            .collect(),
+                    ),
+                    None => "null".to_string(),
+                };
+                writeln!(
+                    self.out,
+                    "{{\"kind\":\"update\",\"path\":{},\"seconds\":{:.3}{},\"delta\":{},\"per_second\":{}}}",
+                    json_string(&update.counts.path),
+                    seconds,
+                    counts,
+                    object(delta),
+                    rates
+                )
+            }
+            Format::Csv => {
+                let mut fields = vec![
+                    "update".to_string(),
+                    csv_field(&update.counts.path),
+                    format!("{:.3}", seconds),
+                ];
+                for count in [&update.counts, &update.delta] {
+                    let values = count.values(&self.counter);
+                    fields.extend(values.iter().map(|(_, value)| value.to_string()));
+                }
+                match rates {
+                    Some(rates) => {
+                        fields.extend(rates.iter().map(|(_, rate)| format!("{:.3}", rate)))
+                    }
+                    // The first counts have no rates
+                    None => fields.extend(vec![String::new(); self.rate_metrics().len()]),
+                }
+                writeln!(self.out, "{}", fields.join(","))
+            }
+        }?;
+        self.out.flush()
+    }
+
+    pub fn count(&mut self, count: &CountResult) -> io::Result<()> {
+        self.entry("file", count)
+    }
+
+    pub fn total(&mut self, total: &CountResult) -> io::Result<()> {
+        self.entry("total", total)
+    }
+
+    pub fn error(&mut self, path: &str, error: &io::Error) -> io::Result<()> {
+        let message = error_message(error);
+        match self.format {
+            Format::Text => Ok(()),
+            Format::Json => {
+                self.separator()?;
+                write!(
+                    self.out,
+                    "{{\"kind\":\"error\",\"path\":{},\"error\":{}}}",
+                    json_string(path),
+                    json_string(&message)
+                )
+            }
+            Format::Csv => {
+                let columns = match self.mode {
+                    Mode::Detect => DETECTION_COLUMNS.len(),
+                    _ => self.counter.metrics().len(),
+                };
+                let empty = vec![""; columns];
+                writeln!(
+                    self.out,
+                    "error,{},{},{}",
+                    csv_field(path),
+                    empty.join(","),
+                    csv_field(&message)
+                )
+            }
+        }
+    }
+
+    /// Writes what comes after the entries
+    pub fn finish(&mut self) -> io::Result<()> {
+        match self.format {
+            Format::Json if matches!(self.mode, Mode::Follow { .. }) => Ok(()),
+            Format::Json if self.entries > 0 => writeln!(self.out, "\n]"),
+            Format::Json => writeln!(self.out, "]"),
+            _ => Ok(()),
+        }?;
+        self.out.flush()
+    }
+
+    fn entry(&mut self, kind: &str, count: &CountResult) -> io::Result<()> {
+        let values = count.values(&self.counter);
+        let path = if kind == "total" { "" } else { &count.path };
+        match self.format {
+            Format::Text => writeln!(self.out, "{}", count.print(&self.counter, self.width)),
+            Format::Json => {
+                self.separator()?;
+                write!(
+                    self.out,
+                    "{{\"kind\":\"{}\",\"path\":{}",
+                    kind,
+                    json_string(path)
+                )?;
+                for (name, value) in values {
+                    write!(self.out, ",\"{}\":{}", name, value)?;
+                }
+                write!(self.out, "}}")
+            }
+            Format::Csv => {
+                let values: Vec<String> =
+                    values.iter().map(|(_, value)| value.to_string()).collect();
+                writeln!(
+                    self.out,
+                    "{},{},{},",
+                    kind,
+                    csv_field(path),
+                    values.join(",")
+                )
+            }
+        }
+    }
+
+    /// Metrics of the updates that have a rate, as given by `Update::rates`
+    fn rate_metrics(&self) -> Vec<&'static str> {
+        let mut metrics = self.counter.metrics();
+        metrics.retain(|name| *name != "max_line_length");
+        metrics
+    }
+
+    /// Starts a new element of the JSON array
+    fn separator(&mut self) -> io::Result<()> {
+        let separator = if self.entries == 0 { "\n  " } else { ",\n  " };
+        self.entries += 1;
+        write!(self.out, "{}", separator)
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Quotes a CSV field if needed, as in RFC 4180
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, time::Duration};
+
+    use crate::{
+        CountResult, Counter, Detection, Encoding, ExtensionSummary, Format, Mode, Output, Symbol,
+        Update,
+    };
+
+    fn report(format: Format) -> String {
+        let mut out = Vec::new();
+        let mut output = Output::new(&mut out, format, Counter::default(), 2, Mode::Counts);
+        let count = CountResult {
+            path: "a \"file\", b".to_string(),
+            lines: 1,
+            words: 3,
+            bytes: 12,
+            ..CountResult::default()
+        };
+        output.start().unwrap();
+        output.count(&count).unwrap();
+        output
+            .error("missing", &io::Error::other("No such file or directory"))
+            .unwrap();
+        output
+            .total(&CountResult {
+                path: String::new(),
+                ..count
+            })
+            .unwrap();
+        output.finish().unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn summary(format: Format) -> String {
+        let mut out = Vec::new();
+        let mut output = Output::new(&mut out, format, Counter::default(), 1, Mode::ByExtension);
+        let rs = ExtensionSummary {
+            extension: "rs".to_string(),
+            files: 12,
+            counts: CountResult {
+                lines: 1200,
+                words: 5000,
+                bytes: 40000,
+                ..CountResult::default()
+            },
+        };
+        let none = ExtensionSummary {
+            extension: String::new(),
+            files: 1,
+            counts: CountResult {
+                lines: 3,
+                words: 4,
+                bytes: 5,
+                ..CountResult::default()
+            },
+        };
+        let total = ExtensionSummary {
+            extension: String::new(),
+            files: 13,
+            counts: rs.counts.clone() + none.counts.clone(),
+        };
+        output.start().unwrap();
+        output.summary(&[none, rs], Some(&total)).unwrap();
+        output.finish().unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn frequencies(format: Format) -> String {
+        let mut out = Vec::new();
+        let mut output = Output::new(&mut out, format, Counter::default(), 1, Mode::Frequencies);
+        let words = vec![("the".to_string(), 120), ("a,b".to_string(), 7)];
+        let histogram = vec![
+            (Symbol::Byte(b'\n'), 3),
+            (Symbol::Byte(b'\''), 10),
+            (Symbol::Char('é'), 2),
+        ];
+        output.start().unwrap();
+        output.frequencies(&words, &histogram).unwrap();
+        output.finish().unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_frequencies() {
+        assert_eq!(
+            frequencies(Format::Text),
+            "120 the\n  7 a,b\n\n 3 0x0a\n10 '\\''\n 2 'é'\n"
+        );
+        assert_eq!(
+            frequencies(Format::Json),
+            "[\n  {\"kind\":\"word\",\"word\":\"the\",\"count\":120},\n  \
+             {\"kind\":\"word\",\"word\":\"a,b\",\"count\":7},\n  \
+             {\"kind\":\"byte\",\"byte\":10,\"count\":3},\n  \
+             {\"kind\":\"byte\",\"byte\":39,\"count\":10},\n  \
+             {\"kind\":\"char\",\"char\":\"é\",\"count\":2}\n]\n"
+        );
+        assert_eq!(
+            frequencies(Format::Csv),
+            "kind,value,count\nword,the,120\nword,\"a,b\",7\nbyte,10,3\nbyte,39,10\nchar,é,2\n"
+        );
+    }
+
+    fn detections(format: Format) -> String {
+        let mut out = Vec::new();
+        let mut output = Output::new(&mut out, format, Counter::default(), 1, Mode::Detect);
+        output.start().unwrap();
+        output
+            .detection(&Detection {
+                path: "a.txt".to_string(),
+                encoding: Encoding::Utf16Le,
+                bom: true,
+                crlf: 12,
+                trailing_newline: true,
+                ..Detection::default()
+            })
+            .unwrap();
+        output
+            .detection(&Detection {
+                lf: 3,
+                cr: 1,
+                nul_bytes: 2,
+                ..Detection::default()
+            })
+            .unwrap();
+        output
+            .error("missing", &io::Error::other("No such file or directory"))
+            .unwrap();
+        output.finish().unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_detections() {
+        assert_eq!(
+            detections(Format::Text),
+            "a.txt: UTF-16LE with BOM, CRLF line endings (12), trailing newline\n\
+             -: UTF-8, mixed line endings (LF 3, CR 1), no trailing newline, 2 NUL bytes\n"
+        );
+        assert_eq!(
+            detections(Format::Json),
+            "[\n  {\"kind\":\"file\",\"path\":\"a.txt\",\"encoding\":\"UTF-16LE\",\"bom\":true,\
+             \"line_endings\":\"CRLF\",\"lf\":0,\"crlf\":12,\"cr\":0,\"trailing_newline\":true,\"nul_bytes\":0},\n  \
+             {\"kind\":\"file\",\"path\":\"\",\"encoding\":\"UTF-8\",\"bom\":false,\
+             \"line_endings\":\"mixed\",\"lf\":3,\"crlf\":0,\"cr\":1,\"trailing_newline\":false,\"nul_bytes\":2},\n  \
+             {\"kind\":\"error\",\"path\":\"missing\",\"error\":\"No such file or directory\"}\n]\n"
+        );
+        assert_eq!(
+            detections(Format::Csv),
+            "kind,path,encoding,bom,line_endings,lf,crlf,cr,trailing_newline,nul_bytes,error\n\
+             file,a.txt,UTF-16LE,true,CRLF,0,12,0,true,0,\n\
+             file,,UTF-8,false,mixed,3,0,1,false,2,\n\
+             error,missing,,,,,,,,,No such file or directory\n"
+        );
+    }
+
+    fn updates(format: Format, deltas: bool) -> String {
+        let mut out = Vec::new();
+        let counter = Counter {
+            lines: true,
+            bytes: true,
+            max_line_length: true,
+            ..Counter::default()
+        };
+        let mut output = Output::new(&mut out, format, counter, 3, Mode::Follow { deltas });
+        let counts = CountResult {
+            path: "app.log".to_string(),
+            lines: 10,
+            bytes: 500,
+            max_line_length: 60,
+            ..CountResult::default()
+        };
+        let first = Update {
+            counts: counts.clone(),
+            delta: counts.clone(),
+            elapsed: Duration::ZERO,
+            truncated: false,
+            replaced: false,
+        };
+        let second = Update {
+            counts: CountResult {
+                lines: 13,
+                bytes: 650,
+                ..counts.clone()
+            },
+            delta: CountResult {
+                lines: 3,
+                bytes: 150,
+                ..counts
+            },
+            elapsed: Duration::from_millis(2000),
+            ..first.clone()
+        };
+        output.start().unwrap();
+        output.update(&first).unwrap();
+        output.update(&second).unwrap();
+        output.finish().unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_updates() {
+        assert_eq!(
+            updates(Format::Text, false),
+            " 10 500  60 app.log\n 13 650  60 app.log\n"
+        );
+        assert_eq!(
+            updates(Format::Text, true),
+            " 10 500  60 app.log\n  3 150  60 app.log (1.5 lines/s, 75.0 bytes/s)\n"
+        );
+        assert_eq!(
+            updates(Format::Json, false),
+            "{\"kind\":\"update\",\"path\":\"app.log\",\"seconds\":0.000,\"lines\":10,\"bytes\":500,\"max_line_length\":60,\
+             \"delta\":{\"lines\":10,\"bytes\":500,\"max_line_length\":60},\"per_second\":null}\n\
+             {\"kind\":\"update\",\"path\":\"app.log\",\"seconds\":2.000,\"lines\":13,\"bytes\":650,\"max_line_length\":60,\
+             \"delta\":{\"lines\":3,\"bytes\":150,\"max_line_length\":60},\"per_second\":{\"lines\":1.500,\"bytes\":75.000}}\n"
+        );
+        assert_eq!(
+            updates(Format::Csv, false),
+            "kind,path,seconds,lines,bytes,max_line_length,lines_delta,bytes_delta,max_line_length_delta,\
+             lines_per_second,bytes_per_second\n\
+             update,app.log,0.000,10,500,60,10,500,60,,\n\
+             update,app.log,2.000,13,650,60,3,150,60,1.500,75.000\n"
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(
+            summary(Format::Text),
+            "extension  files  lines  words  bytes\n\
+             (none)         1      3      4      5\n\
+             .rs           12   1200   5000  40000\n\
+             total         13   1203   5004  40005\n"
+        );
+        assert_eq!(
+            summary(Format::Json),
+            "[\n  {\"kind\":\"extension\",\"extension\":\"\",\"files\":1,\"lines\":3,\"words\":4,\"bytes\":5},\n  \
+             {\"kind\":\"extension\",\"extension\":\"rs\",\"files\":12,\"lines\":1200,\"words\":5000,\"bytes\":40000},\n  \
+             {\"kind\":\"total\",\"files\":13,\"lines\":1203,\"words\":5004,\"bytes\":40005}\n]\n"
+        );
+        assert_eq!(
+            summary(Format::Csv),
+            "kind,extension,files,lines,words,bytes\n\
+             extension,,1,3,4,5\n\
+             extension,rs,12,1200,5000,40000\n\
+             total,,13,1203,5004,40005\n"
+        );
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(report(Format::Text), " 1  3 12 a \"file\", b\n 1  3 12\n");
+    }
+
+    #[test]
+    fn test_json() {
+        assert_eq!(
+            report(Format::Json),
+            "[\n  {\"kind\":\"file\",\"path\":\"a \\\"file\\\", b\",\"lines\":1,\"words\":3,\"bytes\":12},\n  \
+             {\"kind\":\"error\",\"path\":\"missing\",\"error\":\"No such file or directory\"},\n  \
+             {\"kind\":\"total\",\"path\":\"\",\"lines\":1,\"words\":3,\"bytes\":12}\n]\n"
+        );
+    }
+
+    #[test]
+    fn test_csv() {
+        assert_eq!(
+            report(Format::Csv),
+            "kind,path,lines,words,bytes,error\n\
+             file,\"a \"\"file\"\", b\",1,3,12,\n\
+             error,missing,,,,No such file or directory\n\
+             total,,1,3,12,\n"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    fs,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+use crate::{encoding::starts_with_utf16_bom, CountResult, CountState, Counter, BLOCK_SIZE};
+
+/// Files smaller than this are not worth splitting into ranges
+pub const MIN_RANGE_SIZE: u64 = 1024 * 1024;
+
+impl Counter {
+    /// Counts files on a pool of `jobs` threads. `on_result` is called on the calling
+    /// thread for every file, in the order of `paths`, as soon as the count of the file
+    /// and of all the files before it are available.
+    ///
+    /// With `split`, each file is also counted in byte ranges by `jobs` threads, see
+    /// `count_file_ranges`.
+    pub fn count_files<F>(&self, paths: &[String], jobs: usize, split: bool, mut on_result: F)
+    where
+        F: FnMut(io::Result<CountResult>),
+    {
+        let jobs = jobs.max(1);
+        let count = |path: &str| {
+            if split {
+                self.count_file_ranges(path, jobs)
+            } else {
+                self.count_file(path)
+            }
+        };
+
+        // Splitting already uses all the threads for each file
+        if jobs == 1 || split {
+            paths.iter().for_each(|path| on_result(count(path)));
+            return;
+        }
+
+        let next = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            for _ in 0..jobs.min(paths.len()) {
+                let sender = sender.clone();
+                let (next, count) = (&next, &count);
+                scope.spawn(move || loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = paths.get(index) else {
+                        break;
+                    };
+                    if sender.send((index, count(path))).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(sender);
+
+            // Results arrive in any order: keep them until their turn comes
+            let mut waiting: Vec<Option<io::Result<CountResult>>> = Vec::new();
+            waiting.resize_with(paths.len(), || None);
+            let mut printed = 0;
+            for (index, result) in receiver {
+                waiting[index] = Some(result);
+                while let Some(result) = waiting.get_mut(printed).and_then(Option::take) {
+                    on_result(result);
+                    printed += 1;
+                }
+            }
+        });
+    }
+
+    /// Counts a file by splitting it into up to `parts` byte ranges, counted in parallel
+    /// and then merged. Ranges are at least `MIN_RANGE_SIZE` bytes long. Files are counted
+    /// as a whole if the counter is not `splittable`.
+    pub fn count_file_ranges(&self, path: &str, parts: usize) -> io::Result<CountResult> {
+        let len = match fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => metadata.len(),
+            // Directories, pipes, missing files... are handled as usual
+            _ => return self.count_file(path),
+        };
+        let parts = (parts as u64).min(len / MIN_RANGE_SIZE).max(1);
+        // UTF-16 is decoded from the start of the file
+        if parts == 1
+            || !self.splittable()
+            || (self.utf8 && self.decodes() && starts_with_utf16_bom(path)?)
+        {
+            return self.count_file(path);
+        }
+
+        let range_size = len / parts;
+        let states: Vec<io::Result<CountState>> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..parts)
+                .map(|part| {
+                    let start = part * range_size;
+                    let end = if part == parts - 1 {
+                        len
+                    } else {
+                        start + range_size
+                    };
+                    scope.spawn(move || self.count_range(path, start, end - start))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err(io::Error::other("counting thread panicked")))
+                })
+                .collect()
+        });
+
+        let state = states
+            .into_iter()
+            .reduce(|acc, state| Ok(acc? + state?))
+            .unwrap_or_else(|| Ok(CountState::new(*self)))?;
+        Ok(state.finish(path))
+    }
+
+    /// Counts `len` bytes of a file starting at `start`
+    fn count_range(&self, path: &str, start: u64, len: u64) -> io::Result<CountState> {
+        let mut file = fs::File::open(path)?;
+        file.seek(SeekFrom::Start(start))?;
+        let mut reader = BufReader::with_capacity(BLOCK_SIZE, file.take(len));
+        let mut state = CountState::new(*self);
+        self.update_from(&mut state, &mut reader)?;
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write};
+
+    use crate::{Counter, MIN_RANGE_SIZE};
+
+    #[test]
+    fn test_count_file_ranges() {
+        // Words, multi-byte chars and invalid bytes land on the split points
+        let mut content = Vec::new();
+        let pattern = "wörd 😀€\n\u{a0}x\t".as_bytes();
+        while (content.len() as u64) < 3 * MIN_RANGE_SIZE + 17 {
+            content.extend_from_slice(pattern);
+            content.push(0xe2);
+        }
+        let path = std::env::temp_dir().join(format!("rwc-ranges-{}", std::process::id()));
+        fs::File::create(&path)
+            .unwrap()
+            .write_all(&content)
+            .unwrap();
+        let path = path.to_str().unwrap().to_string();
+
+        for counter in [
+            Counter::all(),
+            Counter {
+                utf8: false,
+                ..Counter::all()
+            },
+        ] {
+            let expected = counter.count_file(&path).unwrap();
+            for parts in 2..=5 {
+                assert_eq!(
+                    counter.count_file_ranges(&path, parts).unwrap(),
+                    expected,
+                    "{}",
+                    parts
+                );
+            }
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_count_files_order() {
+        let paths: Vec<String> = ["src/lib.rs", "missing", "src/state.rs", "src", "Cargo.toml"]
+            .iter()
+            .map(|path| path.to_string())
+            .collect();
+        let counter = Counter::all();
+        let mut results = Vec::new();
+        counter.count_files(&paths, 3, false, |result| results.push(result));
+
+        assert_eq!(results.len(), paths.len());
+        for (path, result) in paths.iter().zip(&results) {
+            match counter.count_file(path) {
+                Ok(expected) => assert_eq!(result.as_ref().unwrap(), &expected),
+                Err(_) => assert!(result.is_err()),
+            }
+        }
+    }
+}
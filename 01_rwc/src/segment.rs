@@ -0,0 +1,123 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Text is segmented once this many bytes are buffered
+const SEGMENT_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Segmentation {
+    /// Extended grapheme clusters
+    Graphemes,
+    /// UAX #29 word boundaries, counting the segments with letters or digits
+    Words,
+}
+
+/// Counts Unicode text segments in text given in pieces of any size.
+///
+/// The boundary of a segment may depend on the text following it (e.g. a combining
+/// mark extends a grapheme, and "can" followed by "'t" is a single word), so the last
+/// segments of the buffered text are only counted when more text comes or at the end.
+#[derive(Debug, Clone)]
+pub(crate) struct Segmenter {
+    segmentation: Segmentation,
+    buffer: String,
+    pub(crate) count: u64,
+}
+
+impl Segmenter {
+    pub(crate) fn new(segmentation: Segmentation) -> Self {
+        Segmenter {
+            segmentation,
+            buffer: String::new(),
+            count: 0,
+        }
+    }
+
+    pub(crate) fn push(&mut self, s: &str) {
+        self.buffer.push_str(s);
+        if self.buffer.len() >= SEGMENT_SIZE {
+            self.count_complete();
+        }
+    }
+
+    /// Ends the text, e.g. at invalid bytes, which are not part of any segment
+    pub(crate) fn end(&mut self) {
+        let segments: Vec<&str> = self.segments().collect();
+        self.count += self.count_segments(&segments);
+        self.buffer.clear();
+    }
+
+    /// Counts the segments that more text can't change, keeping the others buffered
+    fn count_complete(&mut self) {
+        // Word boundaries look up to two chars ahead, grapheme boundaries only one
+        let keep = match self.segmentation {
+            Segmentation::Graphemes => 1,
+            Segmentation::Words => 2,
+        };
+        let segments: Vec<&str> = self.segments().collect();
+        if segments.len() <= keep {
+            return;
+        }
+
+        let complete = &segments[..segments.len() - keep];
+        let count = self.count_segments(complete);
+        let len: usize = complete.iter().map(|segment| segment.len()).sum();
+        self.count += count;
+        self.buffer.drain(..len);
+    }
+
+    fn segments(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        match self.segmentation {
+            Segmentation::Graphemes => Box::new(self.buffer.graphemes(true)),
+            Segmentation::Words => Box::new(self.buffer.split_word_bounds()),
+        }
+    }
+
+    fn count_segments(&self, segments: &[&str]) -> u64 {
+        match self.segmentation {
+            Segmentation::Graphemes => segments.len() as u64,
+            Segmentation::Words => segments
+                .iter()
+                .filter(|segment| segment.chars().any(char::is_alphanumeric))
+                .count() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::segment::{Segmentation, Segmenter};
+
+    fn count(segmentation: Segmentation, pieces: &[&str]) -> u64 {
+        let mut segmenter = Segmenter::new(segmentation);
+        for piece in pieces {
+            segmenter.push(piece);
+            // Segment after every piece, as if the buffer was full
+            segmenter.count_complete();
+        }
+        segmenter.end();
+        segmenter.count
+    }
+
+    #[test]
+    fn test_graphemes() {
+        assert_eq!(count(Segmentation::Graphemes, &["e\u{301}a"]), 2);
+        assert_eq!(count(Segmentation::Graphemes, &["e", "\u{301}", "a"]), 2);
+        // Family emoji joined with ZWJ, and a pair of regional indicators
+        let family = "👨\u{200d}👩\u{200d}👧";
+        assert_eq!(count(Segmentation::Graphemes, &[family, "🇫🇷"]), 2);
+        assert_eq!(
+            count(Segmentation::Graphemes, &["👨\u{200d}", "👩", "🇫", "🇷🇫"]),
+            3
+        );
+    }
+
+    #[test]
+    fn test_words() {
+        assert_eq!(count(Segmentation::Words, &["我喜欢读书"]), 5);
+        assert_eq!(count(Segmentation::Words, &["can't stop, 3.14!"]), 3);
+        assert_eq!(
+            count(Segmentation::Words, &["can", "'", "t st", "op, 3.", "14!"]),
+            3
+        );
+    }
+}
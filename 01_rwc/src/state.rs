@@ -0,0 +1,680 @@
+use std::{
+    io::{self, Write},
+    ops::Add,
+};
+
+use memchr::memchr_iter;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::{
+    segment::{Segmentation, Segmenter},
+    CountResult, Counter, WordMode,
+};
+
+/// Size of the blocks read from files
+pub const BLOCK_SIZE: usize = 64 * 1024;
+
+/// Incremental counting state, updated with blocks of input of any size.
+///
+/// Words and UTF-8 sequences may straddle blocks: the state remembers whether the
+/// previous block ended inside a word, and keeps the bytes of an incomplete UTF-8
+/// sequence until the following block completes (or invalidates) it.
+///
+/// States of consecutive parts of the same input can also be counted independently
+/// and then merged with `+`, as if the parts were counted in sequence.
+#[derive(Debug, Clone)]
+pub struct CountState {
+    counter: Counter,
+    bytes: u64,
+    lines: u64,
+    /// Words counted as if the input started outside of a word
+    words: u64,
+    chars: u64,
+    in_word: bool,
+    /// Whether the first char affecting words was a printable one (`Some(true)`) or a
+    /// space (`Some(false)`). Needed to know if a word continues from a previous state.
+    first: Option<bool>,
+    pending: Vec<u8>,
+    /// Continuation bytes at the start of the input, which may complete a sequence left
+    /// pending by a previous state
+    head: Vec<u8>,
+    started: bool,
+    /// Columns of the first line, whose start may be on a previous state
+    first_line: LineStart,
+    first_line_ended: bool,
+    /// Columns of the current line
+    line: LineStart,
+    /// Longest line ended after the first one
+    max_line_length: u64,
+    display_width: u64,
+    /// Unicode segments, only counted in UTF-8
+    graphemes: Option<Segmenter>,
+    uax29_words: Option<Segmenter>,
+}
+
+/// Display width of a line start. As tabs move to the next multiple of 8, the end
+/// column depends on the column where the line starts: `width` columns are followed,
+/// after the first tab, by `after_tab` columns from a tab stop.
+#[derive(Debug, Clone, Copy, Default)]
+struct LineStart {
+    width: u64,
+    after_tab: Option<u64>,
+}
+
+impl LineStart {
+    fn advance(&mut self, width: u64) {
+        match &mut self.after_tab {
+            Some(after) => *after += width,
+            None => self.width += width,
+        }
+    }
+
+    fn tab(&mut self) {
+        self.after_tab = Some(self.after_tab.map_or(0, next_tab_stop));
+    }
+
+    /// Returns the end column when starting at `start`
+    fn end(&self, start: u64) -> u64 {
+        match self.after_tab {
+            Some(after) => next_tab_stop(start + self.width) + after,
+            None => start + self.width,
+        }
+    }
+
+    /// Returns the line start followed by `other`
+    fn then(self, other: LineStart) -> LineStart {
+        match self.after_tab {
+            Some(after) => LineStart {
+                width: self.width,
+                after_tab: Some(other.end(after)),
+            },
+            None => LineStart {
+                width: self.width + other.width,
+                after_tab: other.after_tab,
+            },
+        }
+    }
+}
+
+fn next_tab_stop(column: u64) -> u64 {
+    column + 8 - column % 8
+}
+
+impl CountState {
+    pub fn new(counter: Counter) -> Self {
+        CountState {
+            counter,
+            bytes: 0,
+            lines: 0,
+            words: 0,
+            chars: 0,
+            in_word: false,
+            first: None,
+            pending: Vec::with_capacity(4),
+            head: Vec::new(),
+            started: false,
+            first_line: LineStart::default(),
+            first_line_ended: false,
+            line: LineStart::default(),
+            max_line_length: 0,
+            display_width: 0,
+            graphemes: (counter.utf8 && counter.graphemes)
+                .then(|| Segmenter::new(Segmentation::Graphemes)),
+            uax29_words: (counter.utf8 && counter.words && counter.word_mode == WordMode::Uax29)
+                .then(|| Segmenter::new(Segmentation::Words)),
+        }
+    }
+
+    pub fn update(&mut self, block: &[u8]) {
+        self.bytes += block.len() as u64;
+
+        if self.counter.lines {
+            self.lines += memchr_iter(b'\n', block).count() as u64;
+        }
+
+        if !self.decodes() {
+            return;
+        }
+
+        if self.counter.utf8 {
+            let block = self.take_head(block);
+            let rest = self.complete_pending(block);
+            self.scan_utf8(rest);
+        } else {
+            self.scan_single_bytes(block);
+        }
+    }
+
+    /// Returns the counts so far. The bytes of an incomplete UTF-8 sequence at the end of
+    /// the input are invalid, so they are not counted as chars.
+    pub fn finish(&self, name: &str) -> CountResult {
+        // Segments are counted once the text is known to end
+        let segments = |segmenter: &Option<Segmenter>| {
+            segmenter.clone().map(|mut segmenter| {
+                segmenter.end();
+                segmenter.count
+            })
+        };
+        // In the C locale every char is a grapheme
+        let graphemes = segments(&self.graphemes).unwrap_or(self.chars);
+        let words = segments(&self.uax29_words).unwrap_or(self.words);
+
+        CountResult {
+            path: name.to_string(),
+            bytes: self.bytes,
+            lines: self.lines,
+            words: if self.counter.words { words } else { 0 },
+            chars: if self.counter.chars { self.chars } else { 0 },
+            max_line_length: if self.counter.max_line_length {
+                self.max_line_length
+                    .max(self.first_line.end(0))
+                    .max(self.line.end(0))
+            } else {
+                0
+            },
+            graphemes: if self.counter.graphemes { graphemes } else { 0 },
+            display_width: if self.counter.display_width {
+                self.display_width
+            } else {
+                0
+            },
+        }
+    }
+
+    fn decodes(&self) -> bool {
+        self.counter.decodes()
+    }
+
+    /// Ends the Unicode segments at invalid bytes
+    fn segment_break(&mut self) {
+        self.graphemes.iter_mut().for_each(Segmenter::end);
+        self.uax29_words.iter_mut().for_each(Segmenter::end);
+    }
+
+    /// Stashes the continuation bytes at the very start of the input. Counting them alone
+    /// they are invalid, but when merging with a previous state they may complete its
+    /// pending sequence.
+    fn take_head<'a>(&mut self, block: &'a [u8]) -> &'a [u8] {
+        if self.started {
+            return block;
+        }
+        let len = block
+            .iter()
+            .take(3 - self.head.len())
+            .take_while(|&&b| is_continuation(b))
+            .count();
+        self.head.extend_from_slice(&block[..len]);
+        let rest = &block[len..];
+        self.started = !rest.is_empty() || self.head.len() == 3;
+        rest
+    }
+
+    /// Completes the UTF-8 sequence left pending by the previous block, returning the
+    /// part of the block following it
+    fn complete_pending<'a>(&mut self, block: &'a [u8]) -> &'a [u8] {
+        if self.pending.is_empty() {
+            return block;
+        }
+
+        let pending_len = self.pending.len();
+        let mut sequence = std::mem::take(&mut self.pending);
+        sequence.extend_from_slice(&block[..block.len().min(4 - pending_len)]);
+
+        let unit_len = match std::str::from_utf8(&sequence) {
+            Ok(s) => s.chars().next().map_or(0, |c| self.char(c)),
+            Err(err) if err.valid_up_to() > 0 => {
+                let valid = std::str::from_utf8(&sequence[..err.valid_up_to()]).unwrap_or_default();
+                valid.chars().next().map_or(0, |c| self.char(c))
+            }
+            // The first sequence is invalid: its bytes are skipped
+            Err(err) => match err.error_len() {
+                Some(len) => {
+                    self.segment_break();
+                    len
+                }
+                None => {
+                    // Still incomplete, the whole block was consumed
+                    self.pending = sequence;
+                    return &[];
+                }
+            },
+        };
+
+        &block[unit_len.saturating_sub(pending_len)..]
+    }
+
+    /// Counts UTF-8 text, skipping invalid sequences: like GNU wc, invalid bytes are not
+    /// counted as chars, and they neither start nor end a word
+    fn scan_utf8(&mut self, block: &[u8]) {
+        let mut rest = block;
+        while !rest.is_empty() {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    self.text(valid);
+                    return;
+                }
+                Err(err) => {
+                    let (valid, invalid) = rest.split_at(err.valid_up_to());
+                    // The prefix up to valid_up_to is valid by definition
+                    self.text(std::str::from_utf8(valid).unwrap_or_default());
+                    match err.error_len() {
+                        Some(len) => {
+                            self.segment_break();
+                            rest = &invalid[len..];
+                        }
+                        None => {
+                            self.pending.extend_from_slice(invalid);
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn text(&mut self, s: &str) {
+        let bytes = s.as_bytes();
+
+        if self.counter.chars {
+            // Every char starts with a byte that is not a continuation byte (10xxxxxx)
+            self.chars += bytes.iter().filter(|&&b| !is_continuation(b)).count() as u64;
+        }
+
+        if self.counter.words && self.uax29_words.is_none() {
+            let mut i = 0;
+            while i < bytes.len() {
+                // ASCII runs are classified byte by byte, other chars are decoded
+                let ascii_len = bytes[i..].iter().take_while(|b| b.is_ascii()).count();
+                self.count_c_words(&bytes[i..i + ascii_len]);
+                i += ascii_len;
+                if let Some(c) = s[i..].chars().next() {
+                    self.word_boundary(is_word_separator(c), is_printable(c));
+                    i += c.len_utf8();
+                }
+            }
+        }
+
+        if self.counter.max_line_length {
+            s.chars().for_each(|c| self.column(c));
+        }
+
+        self.segments(s);
+    }
+
+    /// Updates the Unicode segments and the display width
+    fn segments(&mut self, s: &str) {
+        if let Some(graphemes) = &mut self.graphemes {
+            graphemes.push(s);
+        }
+        if let Some(words) = &mut self.uax29_words {
+            words.push(s);
+        }
+        if self.counter.display_width {
+            self.display_width += s.width() as u64;
+        }
+    }
+
+    /// Counts a single char, returning its length in bytes
+    fn char(&mut self, c: char) -> usize {
+        self.chars += 1;
+        self.word_boundary(is_word_separator(c), is_printable(c));
+        if self.counter.max_line_length {
+            self.column(c);
+        }
+        self.segments(c.encode_utf8(&mut [0; 4]));
+        c.len_utf8()
+    }
+
+    /// Updates the line width: like GNU wc, tabs move to the next multiple of 8, line
+    /// feeds, carriage returns and form feeds end the line, and other chars add their
+    /// display width (none for control chars)
+    fn column(&mut self, c: char) {
+        match c {
+            '\n' | '\r' | '\x0c' => self.end_line(),
+            '\t' => self.line.tab(),
+            c => self.line.advance(c.width().unwrap_or(0) as u64),
+        }
+    }
+
+    fn end_line(&mut self) {
+        if self.first_line_ended {
+            self.max_line_length = self.max_line_length.max(self.line.end(0));
+        } else {
+            self.first_line = self.line;
+            self.first_line_ended = true;
+        }
+        self.line = LineStart::default();
+    }
+
+    /// Counts chars and words as in the C locale, where every byte is a char
+    fn scan_single_bytes(&mut self, block: &[u8]) {
+        self.chars += block.len() as u64;
+        if self.counter.words {
+            self.count_c_words(block);
+        }
+        if self.counter.max_line_length {
+            for &b in block {
+                match b {
+                    b'\n' | b'\r' | 0x0C => self.end_line(),
+                    b'\t' => self.line.tab(),
+                    0x20..=0x7E => self.line.advance(1),
+                    _ => {}
+                }
+            }
+        }
+        if self.counter.display_width {
+            self.display_width +=
+                block.iter().filter(|b| (0x20..=0x7E).contains(*b)).count() as u64;
+        }
+    }
+
+    /// Counts words of single byte chars. Same as calling `word_boundary` for every
+    /// byte, but keeping the state in locals makes the loop much faster.
+    fn count_c_words(&mut self, bytes: &[u8]) {
+        if self.first.is_none() {
+            self.first = bytes
+                .iter()
+                .map(|&b| C_CLASSES[b as usize])
+                .find(|&class| class != NEUTRAL)
+                .map(|class| class == PRINTABLE);
+        }
+
+        let mut in_word = self.in_word;
+        let mut words = 0;
+        for &b in bytes {
+            match C_CLASSES[b as usize] {
+                SPACE => in_word = false,
+                PRINTABLE => {
+                    words += !in_word as u64;
+                    in_word = true;
+                }
+                _ => {}
+            }
+        }
+        self.in_word = in_word;
+        self.words += words;
+    }
+
+    /// Updates the word count: words are sequences of printable chars separated by
+    /// whitespace, other chars don't affect them
+    fn word_boundary(&mut self, space: bool, printable: bool) {
+        if self.first.is_none() && (space || printable) {
+            self.first = Some(printable && !space);
+        }
+        if space {
+            self.in_word = false;
+        } else if printable && !self.in_word {
+            self.in_word = true;
+            self.words += 1;
+        }
+    }
+}
+
+/// Counts the data written, so that decoders writing their output can be counted
+impl Write for CountState {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Merges the state of the following part of the input
+impl Add for CountState {
+    type Output = Self;
+
+    fn add(mut self, other: Self) -> Self {
+        if !self.started && self.bytes == 0 {
+            return other;
+        }
+
+        if self.counter.utf8 && self.decodes() {
+            if !self.started {
+                // Still at the start of the input: the head just grows
+                self.head.extend_from_slice(&other.head);
+                self.started = other.started;
+            } else {
+                let rest = self.complete_pending(&other.head);
+                self.scan_utf8(rest);
+                // The other state went on with a byte that can't continue the sequence
+                if other.started && !self.pending.is_empty() {
+                    self.pending.clear();
+                    self.segment_break();
+                }
+            }
+        }
+
+        // A word continues across the junction if the other part starts with a printable
+        self.words += other.words;
+        if other.first == Some(true) && self.in_word {
+            self.words -= 1;
+        }
+        if other.first.is_some() {
+            self.in_word = other.in_word;
+        }
+        if self.first.is_none() {
+            self.first = other.first;
+        }
+
+        // The first line of the other part continues the current line
+        if other.first_line_ended {
+            let junction = self.line.then(other.first_line);
+            if self.first_line_ended {
+                self.max_line_length = self.max_line_length.max(junction.end(0));
+            } else {
+                self.first_line = junction;
+                self.first_line_ended = true;
+            }
+            self.max_line_length = self.max_line_length.max(other.max_line_length);
+            self.line = other.line;
+        } else {
+            self.line = self.line.then(other.line);
+        }
+
+        // Unicode segments can't be merged exactly (see `Counter::splittable`): a segment
+        // straddling the junction is counted twice
+        for (segmenter, other) in [
+            (&mut self.graphemes, other.graphemes),
+            (&mut self.uax29_words, other.uax29_words),
+        ] {
+            if let (Some(segmenter), Some(mut other)) = (segmenter.as_mut(), other) {
+                segmenter.end();
+                other.end();
+                segmenter.count += other.count;
+            }
+        }
+
+        self.bytes += other.bytes;
+        self.lines += other.lines;
+        self.chars += other.chars;
+        self.display_width += other.display_width;
+        if other.started {
+            self.pending = other.pending;
+        }
+        self
+    }
+}
+
+fn is_continuation(b: u8) -> bool {
+    (b & 0xC0) == 0x80
+}
+
+/// Whether a char separates words as in GNU wc with a UTF-8 locale: the spaces of glibc's
+/// `iswspace`, which unlike `char::is_whitespace` leave out U+0085, U+2028 and U+2029,
+/// and the no-break spaces that GNU wc adds to them
+fn is_word_separator(c: char) -> bool {
+    matches!(
+        c,
+        '\t'..='\r'
+            | ' '
+            | '\u{A0}'
+            | '\u{1680}'
+            | '\u{2000}'..='\u{200A}'
+            | '\u{202F}'
+            | '\u{205F}'
+            | '\u{2060}'
+            | '\u{3000}'
+    )
+}
+
+/// Whether a char can start a word: glibc's `iswprint` also rejects the line and paragraph
+/// separators U+2028 and U+2029, so that alone they count as neither space nor word
+fn is_printable(c: char) -> bool {
+    !c.is_control() && !matches!(c, '\u{2028}' | '\u{2029}')
+}
+
+const NEUTRAL: u8 = 0;
+const SPACE: u8 = 1;
+const PRINTABLE: u8 = 2;
+
+/// Class of each byte in the C locale: whitespace, printable (not space) or neither
+const C_CLASSES: [u8; 256] = {
+    let mut classes = [NEUTRAL; 256];
+    let mut b = 0;
+    while b < 256 {
+        classes[b] = match b as u8 {
+            b' ' | b'\t' | b'\n' | 0x0B | 0x0C | b'\r' => SPACE,
+            0x21..=0x7E => PRINTABLE,
+            _ => NEUTRAL,
+        };
+        b += 1;
+    }
+    classes
+};
+
+#[cfg(test)]
+mod tests {
+    use crate::{CountState, Counter, WordMode};
+
+    fn count_blocks(input: &[u8], block_size: usize) -> (u64, u64, u64, u64, u64) {
+        let mut state = CountState::new(Counter::all());
+        for block in input.chunks(block_size) {
+            state.update(block);
+        }
+        summary(state)
+    }
+
+    fn summary(state: CountState) -> (u64, u64, u64, u64, u64) {
+        let res = state.finish("");
+        (
+            res.lines,
+            res.words,
+            res.chars,
+            res.bytes,
+            res.max_line_length,
+        )
+    }
+
+    #[test]
+    fn test_block_boundaries() {
+        let input = "héllo wörld 😀\nsecond  line\t€ end\n".as_bytes();
+        let expected = count_blocks(input, input.len());
+        assert_eq!(expected, (2, 7, 33, 40, 21));
+
+        for block_size in 1..input.len() {
+            assert_eq!(count_blocks(input, block_size), expected, "{}", block_size);
+        }
+    }
+
+    /// Counts the two parts separately and merges the states
+    fn count_split(input: &[u8], split: usize) -> (u64, u64, u64, u64, u64) {
+        let mut left = CountState::new(Counter::all());
+        left.update(&input[..split]);
+        let mut right = CountState::new(Counter::all());
+        right.update(&input[split..]);
+        summary(left + right)
+    }
+
+    #[test]
+    fn test_merge() {
+        let inputs: [&[u8]; 6] = [
+            "héllo wörld 😀\nsecond  line\t€ end\n".as_bytes(),
+            b"a\xe2\x82 b \xf0\x9f\x98 c\xc3\n\xff",
+            b"word\x01\x02more \x80\x80\x80\x80x",
+            b"\x9f\x98\x80 \xf0\x9f\x98\x80\xf0",
+            "a\tbc\t\td\ref\tg\n\t中文\tx".as_bytes(),
+            b"\t\tab\tc",
+        ];
+        for input in inputs {
+            let expected = count_blocks(input, input.len());
+            for split in 0..=input.len() {
+                assert_eq!(count_split(input, split), expected, "{:?} {}", input, split);
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_many_parts() {
+        let input = "aé 😀b\nc€ d".as_bytes();
+        let expected = count_blocks(input, input.len());
+        let merged = input
+            .iter()
+            .map(|b| {
+                let mut state = CountState::new(Counter::all());
+                state.update(std::slice::from_ref(b));
+                state
+            })
+            .reduce(|acc, state| acc + state)
+            .unwrap();
+        assert_eq!(summary(merged), expected);
+    }
+
+    #[test]
+    fn test_max_line_length() {
+        // Expected values from GNU wc -L
+        let utf8 = "ab\tc\x01\u{80}d\x0b\x0cxyz\n中\u{301}x\t".as_bytes();
+        assert_eq!(count_blocks(utf8, utf8.len()).4, 10);
+        assert_eq!(count_blocks("中\u{301}x\t".as_bytes(), 7).4, 8);
+
+        let c_locale = Counter {
+            utf8: false,
+            ..Counter::all()
+        };
+        let mut state = CountState::new(c_locale);
+        state.update("😀😀 é\r\nabc".as_bytes());
+        assert_eq!(state.finish("").max_line_length, 3);
+    }
+
+    #[test]
+    fn test_unicode_segments() {
+        let counter = Counter {
+            words: true,
+            graphemes: true,
+            display_width: true,
+            word_mode: WordMode::Uax29,
+            ..Counter::default()
+        };
+        // Segments are also broken by invalid bytes
+        let input = "我喜欢读书. Cafe\u{301} can't\n".repeat(300) + "e\u{301}";
+        let mut input = input.into_bytes();
+        input.extend_from_slice(b"\xff\xcc\x81x");
+
+        for block_size in [1, 7, 4096, input.len()] {
+            let mut state = CountState::new(counter);
+            input
+                .chunks(block_size)
+                .for_each(|block| state.update(block));
+            let res = state.finish("");
+            assert_eq!(
+                (res.words, res.graphemes, res.display_width),
+                (300 * 7 + 2, 300 * 18 + 3, 300 * 22 + 2),
+                "{}",
+                block_size
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_sequences_across_blocks() {
+        let input = b"a\xe2\x82 b \xf0\x9f\x98 c\xc3\n\xff";
+        let expected = count_blocks(input, input.len());
+        assert_eq!(expected, (1, 3, 7, 14, 6));
+
+        for block_size in 1..input.len() {
+            assert_eq!(count_blocks(input, block_size), expected, "{}", block_size);
+        }
+    }
+}
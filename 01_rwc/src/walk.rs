@@ -0,0 +1,293 @@
+use std::{collections::BTreeMap, io, path::Path};
+
+use ignore::{overrides::OverrideBuilder, WalkBuilder};
+
+use crate::CountResult;
+
+/// What to do with symbolic links found while walking directories
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Symlinks {
+    /// Links are not counted, and linked directories are not walked
+    #[default]
+    Skip,
+    /// Linked files are counted and linked directories walked, loops being detected
+    Follow,
+}
+
+/// Options of the recursive walk of directories
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    /// Globs of the files to count; all the files if empty
+    pub include: Vec<String>,
+    /// Globs of the files and directories to skip
+    pub exclude: Vec<String>,
+    /// Skip the files ignored by `.gitignore` and `.ignore` files, and hidden files
+    pub ignore_files: bool,
+    pub symlinks: Symlinks,
+}
+
+/// Expands the directories among `paths` into the files they contain, recursively.
+/// Other paths are returned as they are, as files given explicitly are always counted.
+///
+/// Globs match the path relative to the walked directory, or only the file name if they
+/// contain no `/`, as in `.gitignore`. Files are sorted by name within each directory.
+/// Entries that can't be read are returned as errors, and the walk goes on.
+pub fn walk(paths: &[String], options: &WalkOptions) -> Vec<io::Result<String>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if !Path::new(path).is_dir() {
+            files.push(Ok(path.clone()));
+            continue;
+        }
+
+        // Included files are matched separately, as matching an override glob would
+        // count them even when hidden or ignored
+        let excludes = options.exclude.iter().map(|glob| format!("!{}", glob));
+        let (excludes, includes) = match (
+            overrides(path, excludes),
+            overrides(path, options.include.iter().cloned()),
+        ) {
+            (Ok(excludes), Ok(includes)) => (excludes, includes),
+            (Err(err), _) | (_, Err(err)) => {
+                files.push(Err(err));
+                continue;
+            }
+        };
+        let walker = WalkBuilder::new(path)
+            .standard_filters(options.ignore_files)
+            .require_git(false)
+            .follow_links(options.symlinks == Symlinks::Follow)
+            .overrides(excludes)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .build();
+
+        for entry in walker {
+            match entry {
+                Ok(entry) => {
+                    // Without following links, their type is the one of the link
+                    let is_file = entry
+                        .file_type()
+                        .is_some_and(|file_type| file_type.is_file());
+                    let included = options.include.is_empty()
+                        || includes.matched(entry.path(), false).is_whitelist();
+                    if is_file && included {
+                        files.push(Ok(entry.path().to_string_lossy().into_owned()));
+                    }
+                }
+                Err(err) => files.push(Err(walk_error(err))),
+            }
+        }
+    }
+    files
+}
+
+fn overrides(
+    root: &str,
+    globs: impl Iterator<Item = String>,
+) -> io::Result<ignore::overrides::Override> {
+    let mut builder = OverrideBuilder::new(root);
+    for glob in globs {
+        builder
+            .add(&glob)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+    }
+    builder
+        .build()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))
+}
+
+/// Converts a walk error, keeping only the message of the OS error when there is one
+fn walk_error(err: ignore::Error) -> io::Error {
+    let path = walk_error_path(&err).map(|path| path.to_string_lossy().into_owned());
+    let message = match err.io_error().and_then(os_error_code) {
+        Some(code) => crate::error_message(&io::Error::from_raw_os_error(code)),
+        None => err.to_string(),
+    };
+    match path {
+        Some(path) => io::Error::other(format!("{}: {}", path, message)),
+        None => io::Error::other(message),
+    }
+}
+
+/// Returns the code of the OS error, which may be wrapped in a walkdir error
+fn os_error_code(err: &io::Error) -> Option<i32> {
+    err.raw_os_error().or_else(|| {
+        let source = err.get_ref()?.source()?;
+        source.downcast_ref::<io::Error>()?.raw_os_error()
+    })
+}
+
+fn walk_error_path(err: &ignore::Error) -> Option<&Path> {
+    match err {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            walk_error_path(err)
+        }
+        ignore::Error::Loop { child, .. } => Some(child),
+        _ => None,
+    }
+}
+
+/// Counts summed by file extension
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtensionSummary {
+    /// Extension without the dot, empty for files without extension
+    pub extension: String,
+    pub files: usize,
+    pub counts: CountResult,
+}
+
+/// Sums the successful counts by extension, sorted by extension
+pub fn by_extension(counts: &[io::Result<CountResult>]) -> Vec<ExtensionSummary> {
+    let mut summaries: BTreeMap<String, ExtensionSummary> = BTreeMap::new();
+    for count in counts.iter().filter_map(|count| count.as_ref().ok()) {
+        let extension = Path::new(&count.path)
+            .extension()
+            .map(|extension| extension.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let summary = summaries
+            .entry(extension.clone())
+            .or_insert_with(|| ExtensionSummary {
+                counts: CountResult::new(&extension),
+                extension,
+                files: 0,
+            });
+        summary.files += 1;
+        summary.counts = summary.counts.clone() + count.clone();
+    }
+    summaries.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use crate::{by_extension, walk, CountResult, Symlinks, WalkOptions};
+
+    /// Creates a directory tree in a fresh temporary directory
+    fn tree(name: &str, files: &[&str]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("rwc-walk-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "a b\n").unwrap();
+        }
+        root
+    }
+
+    fn walked(root: &Path, options: &WalkOptions) -> Vec<String> {
+        let prefix = format!("{}/", root.display());
+        walk(&[root.to_string_lossy().into_owned()], options)
+            .into_iter()
+            .map(|path| path.unwrap().strip_prefix(&prefix).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_walk_filters() {
+        let root = tree(
+            "filters",
+            &[
+                "b.rs",
+                "a.txt",
+                "src/main.rs",
+                "target/out.rs",
+                ".hidden.rs",
+            ],
+        );
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+
+        let all = WalkOptions::default();
+        assert_eq!(
+            walked(&root, &all),
+            vec![
+                ".gitignore",
+                ".hidden.rs",
+                "a.txt",
+                "b.rs",
+                "src/main.rs",
+                "target/out.rs"
+            ]
+        );
+
+        let ignoring = WalkOptions {
+            ignore_files: true,
+            ..WalkOptions::default()
+        };
+        assert_eq!(
+            walked(&root, &ignoring),
+            vec!["a.txt", "b.rs", "src/main.rs"]
+        );
+
+        let rust = WalkOptions {
+            include: vec!["*.rs".to_string()],
+            exclude: vec!["src".to_string()],
+            ..ignoring
+        };
+        assert_eq!(walked(&root, &rust), vec!["b.rs"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_walk_symlinks() {
+        let root = tree("symlinks", &["dir/a.txt"]);
+        std::os::unix::fs::symlink(root.join("dir"), root.join("link")).unwrap();
+        std::os::unix::fs::symlink(root.join("dir/a.txt"), root.join("file")).unwrap();
+        // A loop back to the root
+        std::os::unix::fs::symlink(&root, root.join("dir/up")).unwrap();
+
+        assert_eq!(walked(&root, &WalkOptions::default()), vec!["dir/a.txt"]);
+
+        let follow = WalkOptions {
+            symlinks: Symlinks::Follow,
+            ..WalkOptions::default()
+        };
+        let files: Vec<_> = walk(&[root.to_string_lossy().into_owned()], &follow);
+        let found: Vec<String> = files
+            .iter()
+            .filter_map(|file| file.as_ref().ok())
+            .map(|path| {
+                path.strip_prefix(&format!("{}/", root.display()))
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(found, vec!["dir/a.txt", "file", "link/a.txt"]);
+        // The loops are reported
+        assert!(files.iter().any(|file| file.is_err()));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_by_extension() {
+        let count = |path: &str, lines| {
+            Ok(CountResult {
+                lines,
+                ..CountResult::new(path)
+            })
+        };
+        let counts = vec![
+            count("a.rs", 1),
+            count("src/b.rs", 2),
+            count("Makefile", 3),
+            Err(std::io::Error::other("failed")),
+            count("c.toml", 4),
+        ];
+        let summary: Vec<(String, usize, u64)> = by_extension(&counts)
+            .into_iter()
+            .map(|summary| (summary.extension, summary.files, summary.counts.lines))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("".to_string(), 1, 3),
+                ("rs".to_string(), 2, 3),
+                ("toml".to_string(), 1, 4)
+            ]
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    fs,
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+const FIXTURES: [&str; 5] = [
+    "tests/files/empty.txt",
+    "tests/files/no_newline.txt",
+    "tests/files/crlf.txt",
+    "tests/files/binary.bin",
+    "tests/files/mixed.txt",
+];
+
+/// Runs rwc with `input` piped to its stdin
+fn rwc(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rwc"))
+        .args(args)
+        .env("LC_ALL", "C.UTF-8")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// Checks the output and exit status of a run expected to succeed
+fn assert_counts(args: &[&str], input: &[u8], expected: &str) {
+    let output = rwc(args, input);
+    assert_eq!(stdout(&output), expected, "rwc {:?}", args);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+fn with_fixtures<'a>(options: &[&'a str]) -> Vec<&'a str> {
+    options.iter().chain(FIXTURES.iter()).copied().collect()
+}
+
+// Expected outputs are the ones of GNU wc 9.1 with LC_ALL=C.UTF-8
+
+#[test]
+fn single_file() {
+    assert_counts(
+        &["tests/files/crlf.txt"],
+        b"",
+        " 4  6 38 tests/files/crlf.txt\n",
+    );
+    // CR is not a line separator, but is part of the line length
+    assert_counts(
+        &["-L", "tests/files/crlf.txt"],
+        b"",
+        "12 tests/files/crlf.txt\n",
+    );
+    assert_counts(
+        &["-c", "tests/files/empty.txt"],
+        b"",
+        "0 tests/files/empty.txt\n",
+    );
+}
+
+#[test]
+fn files_with_total() {
+    assert_counts(
+        &with_fixtures(&[]),
+        b"",
+        "  0   0   0 tests/files/empty.txt\n  \
+         1   6  30 tests/files/no_newline.txt\n  \
+         4   6  38 tests/files/crlf.txt\n  \
+         3   0  12 tests/files/binary.bin\n  \
+         3   8  38 tests/files/mixed.txt\n \
+         11  20 118 total\n",
+    );
+    assert_counts(
+        &with_fixtures(&["-lwmcL"]),
+        b"",
+        "  0   0   0   0   0 tests/files/empty.txt\n  \
+         1   6  30  30  18 tests/files/no_newline.txt\n  \
+         4   6  38  38  12 tests/files/crlf.txt\n  \
+         3   0   7  12   1 tests/files/binary.bin\n  \
+         3   8  27  38  15 tests/files/mixed.txt\n \
+         11  20 102 118  18 total\n",
+    );
+}
+
+#[test]
+fn single_metrics() {
+    let cases = [
+        ("-l", ["0", "1", "4", "3", "3", "11"]),
+        ("-w", ["0", "6", "6", "0", "8", "20"]),
+        ("-c", ["0", "30", "38", "12", "38", "118"]),
+        ("-m", ["0", "30", "38", "7", "27", "102"]),
+        ("-L", ["0", "18", "12", "1", "15", "18"]),
+    ];
+    for (option, values) in cases {
+        let names = FIXTURES.iter().chain(["total"].iter());
+        let expected: String = values
+            .iter()
+            .zip(names)
+            .map(|(value, name)| format!("{:>3} {}\n", value, name))
+            .collect();
+        assert_counts(&with_fixtures(&[option]), b"", &expected);
+    }
+}
+
+#[test]
+fn huge_single_line() {
+    let path = std::env::temp_dir().join(format!("rwc-huge-line-{}", std::process::id()));
+    let mut content = vec![b'x'; 3_000_000];
+    content.extend_from_slice(" yzé".as_bytes());
+    fs::write(&path, &content).unwrap();
+
+    let path_str = path.to_str().unwrap();
+    let counts = rwc(&[path_str], b"");
+    let lengths = rwc(&["-L", "-m", path_str], b"");
+    fs::remove_file(&path).unwrap();
+    assert_eq!(
+        stdout(&counts),
+        format!("      0       2 3000005 {}\n", path_str)
+    );
+    assert_eq!(stdout(&lengths), format!("3000004 3000004 {}\n", path_str));
+}
+
+#[test]
+fn stdin() {
+    let mixed = fs::read("tests/files/mixed.txt").unwrap();
+    // The width of counts of a pipe is unknown
+    assert_counts(&[], &mixed, "      3       8      38\n");
+    assert_counts(&["-l", "-"], &mixed, "3 -\n");
+    assert_counts(&[], b"", "      0       0       0\n");
+}
+
+#[test]
+fn stdin_as_dash_among_files() {
+    let mixed = fs::read("tests/files/mixed.txt").unwrap();
+    assert_counts(
+        &["tests/files/crlf.txt", "-"],
+        &mixed,
+        "      4       6      38 tests/files/crlf.txt\n      \
+         3       8      38 -\n      \
+         7      14      76 total\n",
+    );
+}
+
+#[test]
+fn stdin_and_file_counts_match() {
+    for path in FIXTURES {
+        let content = fs::read(path).unwrap();
+        let from_file = stdout(&rwc(&["-lwmcL", path], b""));
+        let from_stdin = stdout(&rwc(&["-lwmcL", "-"], &content));
+        // Only the widths and the names differ
+        let values = |line: &str| {
+            let fields: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+            fields[..5].to_vec()
+        };
+        assert_eq!(values(&from_file), values(&from_stdin), "{}", path);
+    }
+}
@@ -0,0 +1,45 @@
+use rwc::{Compression, CountResult, Counter, Decompress};
+
+fn count(path: &str, decompress: Decompress) -> CountResult {
+    let counter = Counter {
+        decompress,
+        ..Counter::all()
+    };
+    counter.count_file(path).unwrap()
+}
+
+/// The fixtures are tests/files/mixed.txt compressed with gzip, bzip2, xz and rcompress
+#[test]
+fn decompressed_content_is_counted() {
+    let plain = count("tests/files/mixed.txt", Decompress::Never);
+    let cases = [
+        ("tests/files/mixed.txt.gz", Compression::Gzip),
+        ("tests/files/mixed.txt.bz2", Compression::Bzip2),
+        ("tests/files/mixed.txt.xz", Compression::Xz),
+        ("tests/files/mixed.txt.rcz", Compression::Rcompress),
+    ];
+
+    for (path, compression) in cases {
+        let expected = CountResult {
+            path: path.to_string(),
+            ..plain.clone()
+        };
+        assert_eq!(count(path, Decompress::Always(compression)), expected);
+        assert_eq!(count(path, Decompress::Auto), expected);
+    }
+}
+
+#[test]
+fn auto_counts_uncompressed_files_as_they_are() {
+    let plain = count("tests/files/mixed.txt", Decompress::Never);
+    assert_eq!(count("tests/files/mixed.txt", Decompress::Auto), plain);
+}
+
+#[test]
+fn invalid_compressed_data_fails() {
+    let counter = Counter {
+        decompress: Decompress::Always(Compression::Xz),
+        ..Counter::all()
+    };
+    assert!(counter.count_file("tests/files/mixed.txt.gz").is_err());
+}
@@ -0,0 +1,84 @@
+use std::{fs::File, io::BufReader};
+
+use rwc::{CountResult, Counter};
+
+/// Counts a fixture with all metrics, decoding as UTF-8 or byte by byte
+fn count(path: &str, utf8: bool) -> CountResult {
+    let counter = Counter {
+        utf8,
+        ..Counter::all()
+    };
+    let mut reader = BufReader::new(File::open(path).unwrap());
+    counter.count_reader(path, &mut reader).unwrap()
+}
+
+/// Expected values are the output of GNU wc 9.1 with LC_ALL=C.UTF-8 and LC_ALL=C
+#[test]
+fn counts_match_gnu_wc() {
+    let cases = [
+        ("tests/files/latin1.txt", true, (3, 4, 21, 27)),
+        ("tests/files/latin1.txt", false, (3, 4, 27, 27)),
+        ("tests/files/mixed.txt", true, (3, 8, 27, 38)),
+        ("tests/files/mixed.txt", false, (3, 7, 38, 38)),
+        ("tests/files/binary.bin", true, (3, 0, 7, 12)),
+        ("tests/files/binary.bin", false, (3, 0, 12, 12)),
+    ];
+
+    for (path, utf8, (lines, words, chars, bytes)) in cases {
+        let result = count(path, utf8);
+        assert_eq!(
+            (result.lines, result.words, result.chars, result.bytes),
+            (lines, words, chars, bytes),
+            "{} (utf8: {})",
+            path,
+            utf8
+        );
+    }
+}
+
+/// Separators of GNU wc 9.1 with LC_ALL=C.UTF-8: the no-break spaces are, while U+0085,
+/// U+2028 and U+2029 are not, nor are U+200B and U+001C
+#[test]
+fn word_separators_match_gnu_wc() {
+    let counter = Counter {
+        utf8: true,
+        ..Counter::all()
+    };
+    let code_points = [
+        '\u{85}', '\u{2028}', '\u{2029}', '\u{A0}', '\u{2007}', '\u{202F}', '\u{2060}', '\u{1680}',
+        '\u{205F}', '\u{3000}', '\u{200B}', '\u{1C}',
+    ];
+    let text: Vec<String> = code_points.iter().map(|c| format!("a{}b", c)).collect();
+    let text = text.join(" ") + "\n";
+    let result = counter.count_reader("", &mut text.as_bytes()).unwrap();
+    assert_eq!((result.lines, result.words, result.chars), (1, 19, 48));
+
+    for (c, words) in [
+        ('\u{85}', 1),
+        ('\u{2028}', 1),
+        ('\u{A0}', 2),
+        ('\u{2060}', 2),
+    ] {
+        let text = format!("a{}b", c);
+        let result = counter.count_reader("", &mut text.as_bytes()).unwrap();
+        assert_eq!(result.words, words, "{:?}", c);
+    }
+
+    // Neither separators nor printable, so they never start a word
+    let result = counter
+        .count_reader("", &mut " \u{85} \u{2028} \u{2029} ".as_bytes())
+        .unwrap();
+    assert_eq!(result.words, 0);
+}
+
+#[test]
+fn bytes_and_lines_never_decode() {
+    let counter = Counter {
+        bytes: true,
+        lines: true,
+        ..Counter::default()
+    };
+    let mut input = &[0xFFu8, 0xFE, b'\n', 0xC3][..];
+    let result = counter.count_reader("", &mut input).unwrap();
+    assert_eq!((result.lines, result.bytes), (1, 4));
+}
@@ -0,0 +1,148 @@
+use std::{
+    fs,
+    io::{self, Read},
+    os::unix::fs::PermissionsExt,
+    process::{Command, Output},
+};
+
+fn rwc(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rwc"))
+        .args(args)
+        .env("LC_ALL", "C.UTF-8")
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn success_exits_with_zero() {
+    let output = rwc(&["tests/files/mixed.txt"]);
+    assert_eq!(stdout(&output), " 3  8 38 tests/files/mixed.txt\n");
+    assert_eq!(stderr(&output), "");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn missing_file_is_reported_and_others_counted() {
+    let output = rwc(&["tests/files/missing.txt", "tests/files/mixed.txt"]);
+    assert_eq!(
+        stdout(&output),
+        " 3  8 38 tests/files/mixed.txt\n 3  8 38 total\n"
+    );
+    assert_eq!(
+        stderr(&output),
+        "rwc: tests/files/missing.txt: No such file or directory\n"
+    );
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn directory_is_reported() {
+    let output = rwc(&["-l", "tests/files", "tests/files/mixed.txt"]);
+    assert_eq!(
+        stdout(&output),
+        "      0 tests/files\n      3 tests/files/mixed.txt\n      3 total\n"
+    );
+    assert_eq!(stderr(&output), "rwc: tests/files: Is a directory\n");
+    assert_eq!(output.status.code(), Some(1));
+
+    // Alone, like GNU wc
+    let output = rwc(&["tests/files"]);
+    assert_eq!(stdout(&output), "      0       0       0 tests/files\n");
+    assert_eq!(stderr(&output), "rwc: tests/files: Is a directory\n");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn unreadable_file_is_reported() {
+    let path = std::env::temp_dir().join(format!("rwc-unreadable-{}", std::process::id()));
+    fs::write(&path, "some words\n").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o000)).unwrap();
+
+    // Permissions don't apply to root
+    if fs::File::open(&path).is_ok() {
+        fs::remove_file(&path).unwrap();
+        return;
+    }
+
+    let path_str = path.to_str().unwrap();
+    let output = rwc(&[path_str]);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(stdout(&output), "");
+    assert_eq!(
+        stderr(&output),
+        format!("rwc: {}: Permission denied\n", path_str)
+    );
+    assert_eq!(output.status.code(), Some(1));
+}
+
+/// Runs rwc with a `--files0-from` list, returning its stdout and stderr interleaved, and
+/// its exit code
+fn rwc_files0(list: &[u8]) -> (String, Option<i32>) {
+    let path = std::env::temp_dir().join(format!("rwc-files0-{}", std::process::id()));
+    fs::write(&path, list).unwrap();
+    let (mut reader, writer) = io::pipe().unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rwc"))
+        .arg(format!("--files0-from={}", path.to_str().unwrap()))
+        .env("LC_ALL", "C.UTF-8")
+        .stdout(writer.try_clone().unwrap())
+        .stderr(writer)
+        .spawn()
+        .unwrap();
+    let mut output = String::new();
+    reader.read_to_string(&mut output).unwrap();
+    let status = child.wait().unwrap();
+    fs::remove_file(&path).unwrap();
+    (
+        output.replace(path.to_str().unwrap(), "list"),
+        status.code(),
+    )
+}
+
+#[test]
+fn zero_length_names_are_reported_in_turn() {
+    let (output, code) = rwc_files0(b"tests/files/mixed.txt\0\0tests/files/mixed.txt\0");
+    assert_eq!(
+        output,
+        " 3  8 38 tests/files/mixed.txt\n\
+         rwc: list:2: invalid zero-length file name\n \
+         3  8 38 tests/files/mixed.txt\n \
+         6 16 76 total\n"
+    );
+    assert_eq!(code, Some(1));
+
+    // Like GNU wc, a list of a single NUL isn't empty
+    let (output, code) = rwc_files0(b"\0");
+    assert_eq!(output, "rwc: list:1: invalid zero-length file name\n");
+    assert_eq!(code, Some(1));
+}
+
+#[test]
+fn json_reports_errors_as_entries() {
+    let output = rwc(&["--format", "json", "-w", "tests/files/missing.txt"]);
+    assert_eq!(
+        stdout(&output),
+        "[\n  {\"kind\":\"error\",\"path\":\"tests/files/missing.txt\",\
+         \"error\":\"No such file or directory\"}\n]\n"
+    );
+    assert_eq!(stderr(&output), "");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn invalid_option_fails() {
+    let output = rwc(&["--total=sometimes", "tests/files/mixed.txt"]);
+    assert_eq!(stdout(&output), "");
+    assert_eq!(
+        stderr(&output),
+        "rwc: invalid argument 'sometimes' for '--total'\n"
+    );
+    assert_eq!(output.status.code(), Some(1));
+}
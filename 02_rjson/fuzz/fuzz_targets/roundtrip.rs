@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rjson::JsonObject;
+
+// Whatever the parser accepts must serialize to text that parses back to the same object
+fuzz_target!(|data: &[u8]| {
+    if let Ok(s) = std::str::from_utf8(data) {
+        if let Ok(object) = JsonObject::read(s) {
+            let text = object.to_string();
+            assert_eq!(JsonObject::read(&text), Ok(object));
+        }
+    }
+});
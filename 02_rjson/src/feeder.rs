@@ -0,0 +1,545 @@
+use crate::{JsonError, JsonObject, ReadOptions, MAX_DEPTH};
+
+/// Result of feeding a chunk of bytes to a [`Feeder`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Progress {
+    /// Values completed by this chunk, in stream order
+    pub values: Vec<JsonObject>,
+    /// Set when the stream turned out to be invalid. The feeder stops consuming input
+    /// after an error, and every following call reports it again.
+    pub error: Option<JsonError>,
+}
+
+/// Push parser for a stream of json documents received in arbitrary chunks
+/// (e.g. from a socket).
+///
+/// The feeder checks the syntax of RFC 8259 and the UTF-8 encoding one byte at a time,
+/// keeping its state between chunks, so a chunk can end anywhere: inside a string, a
+/// number, a `\u` escape, a literal like `tru` or even in the middle of a multi-byte
+/// UTF-8 character. Malformed input is reported as soon as its first invalid byte
+/// arrives, as [`JsonError::InvalidChar`] with the position of that byte in the whole
+/// stream, and nesting deeper than the parser accepts is rejected before it is buffered.
+/// When the closing bracket of a top-level document arrives, its bytes are parsed with
+/// the [`ReadOptions`] of the feeder and the value is emitted. Documents are arrays or
+/// objects, separated by any amount of whitespace (as in newline-delimited json).
+///
+/// Unlike [`JsonObject::read`], the feeder is strict: commas between values are required,
+/// numbers can't start with `+`, and the input must be UTF-8, so the raw surrogates of
+/// WTF-8 are rejected while escaped ones follow the surrogate policy of the options.
+#[derive(Debug, Default)]
+pub struct Feeder {
+    options: ReadOptions,
+    /// Bytes of the current document
+    buf: Vec<u8>,
+    state: State,
+    /// Arrays and objects opened and not closed yet, the innermost last
+    containers: Vec<Container>,
+    /// Position in the stream of the next byte
+    offset: usize,
+    /// Position in the stream of the first byte of the current document
+    start: usize,
+    error: Option<JsonError>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Container {
+    Array,
+    Object,
+}
+
+/// What the feeder expects next
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum State {
+    /// Whitespace or the start of a document
+    #[default]
+    Idle,
+    /// A value, or the end of the array just opened
+    ValueOrEnd,
+    Value,
+    /// A key, or the end of the object just opened
+    KeyOrEnd,
+    Key,
+    Colon,
+    /// A comma or the end of the innermost array or object
+    Separator,
+    String {
+        key: bool,
+        escape: Escape,
+    },
+    /// The rest of `true`, `false` or `null`
+    Literal(&'static [u8]),
+    /// The next character of a number, which ends at the first one that can't continue it
+    Number(NumberPart),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Escape {
+    None,
+    Backslash,
+    /// Number of hex digits left in a `\uXXXX` escape
+    Unicode(u8),
+    /// Continuation bytes left in a multi-byte UTF-8 character, the next one being in
+    /// `min..=max`
+    Utf8 {
+        left: u8,
+        min: u8,
+        max: u8,
+    },
+}
+
+/// Last part of a number read
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum NumberPart {
+    Minus,
+    Zero,
+    Integer,
+    Point,
+    Fraction,
+    Exponent,
+    ExponentSign,
+    ExponentDigits,
+}
+
+impl NumberPart {
+    /// Returns the part after `b`, `None` if `b` doesn't continue the number
+    fn next(self, b: u8) -> Option<NumberPart> {
+        use NumberPart::*;
+        match (self, b) {
+            (Minus, b'0') => Some(Zero),
+            (Minus | Integer, b'0'..=b'9') => Some(Integer),
+            (Zero | Integer, b'.') => Some(Point),
+            (Point | Fraction, b'0'..=b'9') => Some(Fraction),
+            (Zero | Integer | Fraction, b'e' | b'E') => Some(Exponent),
+            (Exponent, b'+' | b'-') => Some(ExponentSign),
+            (Exponent | ExponentSign | ExponentDigits, b'0'..=b'9') => Some(ExponentDigits),
+            _ => None,
+        }
+    }
+
+    /// Whether the number can end after this part
+    fn is_complete(self) -> bool {
+        matches!(
+            self,
+            NumberPart::Zero
+                | NumberPart::Integer
+                | NumberPart::Fraction
+                | NumberPart::ExponentDigits
+        )
+    }
+}
+
+fn is_whitespace(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\r' | b'\n')
+}
+
+/// Starts a multi-byte UTF-8 character with its first byte, `None` if it can't start one.
+/// The range of the second byte excludes overlong forms, surrogates and code points
+/// above U+10FFFF.
+fn utf8_start(b: u8) -> Option<Escape> {
+    let (left, min, max) = match b {
+        0xc2..=0xdf => (1, 0x80, 0xbf),
+        0xe0 => (2, 0xa0, 0xbf),
+        0xed => (2, 0x80, 0x9f),
+        0xe1..=0xef => (2, 0x80, 0xbf),
+        0xf0 => (3, 0x90, 0xbf),
+        0xf1..=0xf3 => (3, 0x80, 0xbf),
+        0xf4 => (3, 0x80, 0x8f),
+        _ => return None,
+    };
+    Some(Escape::Utf8 { left, min, max })
+}
+
+impl Feeder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: ReadOptions) -> Self {
+        Feeder {
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// Consumes a chunk of input, returning the documents it completed
+    pub fn feed(&mut self, chunk: &[u8]) -> Progress {
+        let mut values = Vec::new();
+
+        if self.error.is_none() {
+            for &b in chunk {
+                match self.scan(b) {
+                    Ok(Some(value)) => values.push(value),
+                    Ok(None) => {}
+                    Err(err) => {
+                        self.error = Some(err);
+                        break;
+                    }
+                }
+                self.offset += 1;
+            }
+        }
+
+        Progress {
+            values,
+            error: self.error.clone(),
+        }
+    }
+
+    /// Signals the end of the stream, failing if it stopped in the middle of a document
+    pub fn finish(self) -> Result<(), JsonError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        if let State::String { .. } = self.state {
+            return Err(JsonError::UnterminatedString);
+        }
+        match self.containers.first() {
+            None => Ok(()),
+            Some(Container::Object) => Err(JsonError::UnterminatedObject),
+            Some(Container::Array) => Err(JsonError::UnterminatedArray),
+        }
+    }
+
+    /// Returns true if the feeder is between documents
+    pub fn is_idle(&self) -> bool {
+        self.state == State::Idle
+    }
+
+    fn scan(&mut self, b: u8) -> Result<Option<JsonObject>, JsonError> {
+        if self.state == State::Idle {
+            if is_whitespace(b) {
+                return Ok(None);
+            }
+            self.start = self.offset;
+        }
+        self.buf.push(b);
+
+        match self.state {
+            State::Idle => match b {
+                b'{' | b'[' => self.open(b),
+                _ => Err(self.invalid(b)),
+            },
+            State::String { key, escape } => {
+                let escape = match (escape, b) {
+                    (Escape::None, b'"') => {
+                        self.state = if key { State::Colon } else { State::Separator };
+                        return Ok(None);
+                    }
+                    (Escape::None, b'\\') => Escape::Backslash,
+                    // Control characters must be escaped
+                    (Escape::None, 0x00..=0x1f) => return Err(self.invalid(b)),
+                    (Escape::None, 0x80..=0xff) => utf8_start(b).ok_or_else(|| self.invalid(b))?,
+                    (Escape::None, _) => Escape::None,
+                    (Escape::Utf8 { left: 1, min, max }, _) if (min..=max).contains(&b) => {
+                        Escape::None
+                    }
+                    (Escape::Utf8 { left, min, max }, _) if (min..=max).contains(&b) => {
+                        Escape::Utf8 {
+                            left: left - 1,
+                            min: 0x80,
+                            max: 0xbf,
+                        }
+                    }
+                    (Escape::Utf8 { .. }, _) => return Err(self.invalid(b)),
+                    (Escape::Backslash, b'u') => Escape::Unicode(4),
+                    (Escape::Backslash, b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't') => {
+                        Escape::None
+                    }
+                    (Escape::Backslash, _) => return Err(self.invalid(b)),
+                    (Escape::Unicode(left), _) if b.is_ascii_hexdigit() => match left {
+                        1 => Escape::None,
+                        _ => Escape::Unicode(left - 1),
+                    },
+                    (Escape::Unicode(_), _) => return Err(JsonError::InvalidCodepoint),
+                };
+                self.state = State::String { key, escape };
+                Ok(None)
+            }
+            State::Literal(rest) => {
+                if rest[0] != b {
+                    return Err(self.invalid(b));
+                }
+                self.state = match &rest[1..] {
+                    [] => State::Separator,
+                    rest => State::Literal(rest),
+                };
+                Ok(None)
+            }
+            State::Number(part) => match part.next(b) {
+                Some(part) => {
+                    self.state = State::Number(part);
+                    Ok(None)
+                }
+                None if part.is_complete() => {
+                    self.state = State::Separator;
+                    self.separator(b)
+                }
+                None => Err(self.invalid(b)),
+            },
+            _ if is_whitespace(b) => Ok(None),
+            State::ValueOrEnd if b == b']' => self.close(b),
+            State::ValueOrEnd | State::Value => self.value(b),
+            State::KeyOrEnd if b == b'}' => self.close(b),
+            State::KeyOrEnd | State::Key if b == b'"' => {
+                self.state = State::String {
+                    key: true,
+                    escape: Escape::None,
+                };
+                Ok(None)
+            }
+            State::Colon if b == b':' => {
+                self.state = State::Value;
+                Ok(None)
+            }
+            State::Separator => self.separator(b),
+            State::KeyOrEnd | State::Key | State::Colon => Err(self.invalid(b)),
+        }
+    }
+
+    /// Starts a value with its first byte
+    fn value(&mut self, b: u8) -> Result<Option<JsonObject>, JsonError> {
+        self.state = match b {
+            b'{' | b'[' => return self.open(b),
+            b'"' => State::String {
+                key: false,
+                escape: Escape::None,
+            },
+            b't' => State::Literal(b"rue"),
+            b'f' => State::Literal(b"alse"),
+            b'n' => State::Literal(b"ull"),
+            b'-' => State::Number(NumberPart::Minus),
+            b'0' => State::Number(NumberPart::Zero),
+            b'1'..=b'9' => State::Number(NumberPart::Integer),
+            _ => return Err(self.invalid(b)),
+        };
+        Ok(None)
+    }
+
+    /// Reads what follows a value: whitespace, a comma or a closing bracket
+    fn separator(&mut self, b: u8) -> Result<Option<JsonObject>, JsonError> {
+        match (b, self.containers.last()) {
+            _ if is_whitespace(b) => Ok(None),
+            (b',', Some(Container::Array)) => {
+                self.state = State::Value;
+                Ok(None)
+            }
+            (b',', Some(Container::Object)) => {
+                self.state = State::Key;
+                Ok(None)
+            }
+            (b']' | b'}', _) => self.close(b),
+            _ => Err(self.invalid(b)),
+        }
+    }
+
+    fn open(&mut self, b: u8) -> Result<Option<JsonObject>, JsonError> {
+        if self.containers.len() >= MAX_DEPTH {
+            return Err(JsonError::TooDeep);
+        }
+        if b == b'{' {
+            self.containers.push(Container::Object);
+            self.state = State::KeyOrEnd;
+        } else {
+            self.containers.push(Container::Array);
+            self.state = State::ValueOrEnd;
+        }
+        Ok(None)
+    }
+
+    /// Closes the innermost array or object, parsing the document if it was the last one
+    fn close(&mut self, b: u8) -> Result<Option<JsonObject>, JsonError> {
+        let expected = match b {
+            b']' => Container::Array,
+            _ => Container::Object,
+        };
+        if self.containers.last() != Some(&expected) {
+            return Err(self.invalid(b));
+        }
+        self.containers.pop();
+        if !self.containers.is_empty() {
+            self.state = State::Separator;
+            return Ok(None);
+        }
+
+        self.state = State::Idle;
+        let bytes = std::mem::take(&mut self.buf);
+        let text = String::from_utf8(bytes).map_err(|_| JsonError::InvalidUtf8)?;
+        JsonObject::read_with(&text, &self.options)
+            .map(Some)
+            .map_err(|err| match err {
+                // The parser counts the characters of the document
+                JsonError::InvalidChar(c, index) => {
+                    let position = text
+                        .char_indices()
+                        .nth(index)
+                        .map_or(text.len(), |(i, _)| i);
+                    JsonError::InvalidChar(c, self.start + position)
+                }
+                err => err,
+            })
+    }
+
+    /// Error for an unexpected byte at the current position of the stream
+    fn invalid(&self, b: u8) -> JsonError {
+        JsonError::InvalidChar(b as char, self.offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Feeder, JsonError, JsonObject, ReadOptions, SurrogatePolicy, MAX_DEPTH};
+
+    const DOCUMENT: &str = concat!(
+        "{\"name\": \"caf\u{e9} \\u00e4\\\"}\", ",
+        "\"values\": [true, false, null, -1.5e3, {\"nested\": [\"\\uD834\\uDD1E\"]}], ",
+        "\"empty\": {}}"
+    );
+
+    #[test]
+    fn test_feed_whole_document() {
+        let mut feeder = Feeder::new();
+        let progress = feeder.feed(DOCUMENT.as_bytes());
+        assert_eq!(progress.error, None);
+        assert_eq!(progress.values, vec![JsonObject::read(DOCUMENT).unwrap()]);
+        assert!(feeder.finish().is_ok());
+    }
+
+    #[test]
+    fn test_feed_split_everywhere() {
+        let expected = JsonObject::read(DOCUMENT).unwrap();
+        let bytes = DOCUMENT.as_bytes();
+
+        for split in 0..bytes.len() {
+            let mut feeder = Feeder::new();
+            let first = feeder.feed(&bytes[..split]);
+            assert!(first.values.is_empty());
+            assert_eq!(first.error, None);
+
+            let second = feeder.feed(&bytes[split..]);
+            assert_eq!(second.values, vec![expected.clone()]);
+            assert!(feeder.is_idle());
+        }
+    }
+
+    #[test]
+    fn test_feed_byte_by_byte_stream() {
+        let stream = "[1]\n{\"a\": \"]}\"}\n  [[], [true]]\n";
+        let mut feeder = Feeder::new();
+        let mut values = Vec::new();
+        for b in stream.bytes() {
+            values.extend(feeder.feed(&[b]).values);
+        }
+        assert_eq!(
+            values,
+            vec![
+                JsonObject::read("[1]").unwrap(),
+                JsonObject::read("{\"a\": \"]}\"}").unwrap(),
+                JsonObject::read("[[], [true]]").unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_feed_errors() {
+        // Positions are counted from the start of the stream
+        let mut feeder = Feeder::new();
+        let progress = feeder.feed(b"[1] x");
+        assert_eq!(progress.values.len(), 1);
+        assert_eq!(progress.error, Some(JsonError::InvalidChar('x', 4)));
+        assert_eq!(feeder.feed(b"[2]").values, vec![]);
+
+        let mut feeder = Feeder::new();
+        assert_eq!(feeder.feed(b"[1]\n[2, ").error, None);
+        assert_eq!(
+            feeder.feed(b"x]").error,
+            Some(JsonError::InvalidChar('x', 8))
+        );
+
+        // Invalid UTF-8 is found at its first invalid byte
+        let invalid: [(&[u8], JsonError); 5] = [
+            (b"[\"\xff", JsonError::InvalidChar('\u{ff}', 2)),
+            (b"[\"a\xc3(", JsonError::InvalidChar('(', 4)),
+            (b"[\"\xc0\x80", JsonError::InvalidChar('\u{c0}', 2)),
+            (b"[\"\xed\xa0\x80", JsonError::InvalidChar('\u{a0}', 3)),
+            (b"[\"\xf4\x90\x80\x80", JsonError::InvalidChar('\u{90}', 3)),
+        ];
+        for (chunk, err) in invalid {
+            let mut feeder = Feeder::new();
+            assert_eq!(feeder.feed(chunk).error, Some(err), "{:?}", chunk);
+        }
+        let mut feeder = Feeder::new();
+        assert_eq!(feeder.feed(b"[\"\xf0\x9f").error, None);
+        assert_eq!(
+            feeder.feed(b"\x98\x80\"]").values,
+            vec![JsonObject::read("[\"\u{1f600}\"]").unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_feed_with_options() {
+        let document = b"[\"\\uD800\"]";
+        let mut feeder = Feeder::new();
+        assert_eq!(
+            feeder.feed(document).error,
+            Some(JsonError::LoneSurrogate(0xD800))
+        );
+
+        let options = ReadOptions {
+            surrogates: SurrogatePolicy::Replace,
+            ..ReadOptions::default()
+        };
+        let mut feeder = Feeder::with_options(options);
+        let progress = feeder.feed(document);
+        assert_eq!(progress.error, None);
+        assert_eq!(
+            progress.values,
+            vec![JsonObject::Array(vec![JsonObject::JsonString(
+                "\u{fffd}".to_string()
+            )])]
+        );
+    }
+
+    #[test]
+    fn test_feed_rejects_before_the_end() {
+        let invalid: [(&[u8], JsonError); 8] = [
+            (b"[tru,", JsonError::InvalidChar(',', 4)),
+            (b"[1 2", JsonError::InvalidChar('2', 3)),
+            (b"[01", JsonError::InvalidChar('1', 2)),
+            (b"[1.]", JsonError::InvalidChar(']', 3)),
+            (b"{\"a\" 1", JsonError::InvalidChar('1', 5)),
+            (b"{\"a\": [}", JsonError::InvalidChar('}', 7)),
+            (b"[\"\\x", JsonError::InvalidChar('x', 3)),
+            (b"[\"\\u12g", JsonError::InvalidCodepoint),
+        ];
+        for (chunk, err) in invalid {
+            let mut feeder = Feeder::new();
+            let progress = feeder.feed(chunk);
+            assert!(progress.values.is_empty());
+            assert_eq!(progress.error, Some(err), "{:?}", chunk);
+        }
+    }
+
+    #[test]
+    fn test_feed_too_deep() {
+        let mut feeder = Feeder::new();
+        assert_eq!(feeder.feed(&[b'['; MAX_DEPTH]).error, None);
+        assert_eq!(feeder.feed(b"[").error, Some(JsonError::TooDeep));
+
+        let nested = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        let mut feeder = Feeder::new();
+        let progress = feeder.feed(nested.as_bytes());
+        assert_eq!(progress.error, None);
+        assert_eq!(progress.values.len(), 1);
+    }
+
+    #[test]
+    fn test_finish_incomplete() {
+        let mut feeder = Feeder::new();
+        feeder.feed(b"{\"a\": [1, 2");
+        assert_eq!(feeder.finish(), Err(JsonError::UnterminatedObject));
+
+        let mut feeder = Feeder::new();
+        feeder.feed(b"[\"abc");
+        assert_eq!(feeder.finish(), Err(JsonError::UnterminatedString));
+    }
+}
@@ -0,0 +1,787 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+use crate::{JsonMap, JsonObject};
+
+/// String formats recognized while inferring a schema
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StringFormat {
+    DateTime,
+    Uuid,
+    Email,
+}
+
+impl StringFormat {
+    fn detect(s: &str) -> Option<Self> {
+        static PATTERNS: OnceLock<[(Regex, StringFormat); 3]> = OnceLock::new();
+        let patterns = PATTERNS.get_or_init(|| {
+            [
+                (
+                    Regex::new(
+                        r"^\d{4}-\d{2}-\d{2}[Tt ]\d{2}:\d{2}:\d{2}(?:\.\d+)?(?:[Zz]|[+-]\d{2}:\d{2})$",
+                    )
+                    .unwrap(),
+                    StringFormat::DateTime,
+                ),
+                (
+                    Regex::new(
+                        r"^[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}$",
+                    )
+                    .unwrap(),
+                    StringFormat::Uuid,
+                ),
+                (
+                    Regex::new(r"^[^@\s]+@[^@\s]+\.[^@\s]+$").unwrap(),
+                    StringFormat::Email,
+                ),
+            ]
+        });
+
+        patterns
+            .iter()
+            .find(|(regex, _)| regex.is_match(s))
+            .map(|(_, format)| *format)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StringFormat::DateTime => "date-time",
+            StringFormat::Uuid => "uuid",
+            StringFormat::Email => "email",
+        }
+    }
+}
+
+/// Union of all the shapes observed for a value across the samples
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Shape {
+    pub null: bool,
+    pub boolean: bool,
+    pub integer: bool,
+    pub number: bool,
+    /// Set if strings were observed, with the format shared by all of them (if any)
+    pub string: Option<Option<StringFormat>>,
+    /// Set if arrays were observed, with the union of the shapes of their items
+    pub array: Option<Box<Shape>>,
+    /// Set if objects were observed
+    pub object: Option<ObjectShape>,
+}
+
+/// Fields observed in objects, in order of first appearance
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ObjectShape {
+    /// Number of objects merged in this shape
+    pub count: usize,
+    /// Shape of each field, with the number of objects it appeared in
+    pub fields: Vec<(String, Shape, usize)>,
+}
+
+impl ObjectShape {
+    /// A field is required if it appeared in every observed object
+    pub fn is_required(&self, field: &str) -> bool {
+        self.fields
+            .iter()
+            .any(|(name, _, seen)| name == field && *seen == self.count)
+    }
+}
+
+impl Shape {
+    /// Infers the shape describing all the given samples
+    pub fn infer<'a, I: IntoIterator<Item = &'a JsonObject>>(samples: I) -> Shape {
+        let mut shape = Shape::default();
+        for sample in samples {
+            shape.observe(sample);
+        }
+        shape
+    }
+
+    /// Merges a new observed value into the shape
+    pub fn observe(&mut self, value: &JsonObject) {
+        match value {
+            JsonObject::Null => self.null = true,
+            JsonObject::Bool(_) => self.boolean = true,
+            // Only numbers that fit in an i64 are integers. i64::MAX rounds up to 2^63 as
+            // an f64, which doesn't fit, hence the strict comparison.
+            JsonObject::Number(n)
+                if n.fract() == 0.0 && *n >= i64::MIN as f64 && *n < i64::MAX as f64 =>
+            {
+                self.integer = true
+            }
+            JsonObject::Number(_) => self.number = true,
+            JsonObject::JsonString(s) => {
+                let format = StringFormat::detect(s);
+                self.string = match self.string {
+                    None => Some(format),
+                    Some(previous) if previous == format => Some(format),
+                    Some(_) => Some(None),
+                };
+            }
+            // Strings with unpaired surrogates can't match any format
+            JsonObject::Wtf8String(_) => self.string = Some(None),
+            JsonObject::Array(elements) => {
+                let items = self.array.get_or_insert_with(Default::default);
+                for elem in elements {
+                    items.observe(elem);
+                }
+            }
+            JsonObject::Object(elements) => {
+                let object = self.object.get_or_insert_with(Default::default);
+                object.count += 1;
+                for (key, elem) in elements.iter() {
+                    match object.fields.iter_mut().find(|(name, _, _)| name == key) {
+                        Some((_, shape, seen)) => {
+                            shape.observe(elem);
+                            *seen += 1;
+                        }
+                        None => {
+                            let mut shape = Shape::default();
+                            shape.observe(elem);
+                            object.fields.push((key.clone(), shape, 1));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the json types observed, as named by JSON Schema.
+    /// Integers are folded into `number` when both were observed.
+    pub fn types(&self) -> Vec<&'static str> {
+        let mut types = Vec::new();
+        if self.object.is_some() {
+            types.push("object");
+        }
+        if self.array.is_some() {
+            types.push("array");
+        }
+        if self.string.is_some() {
+            types.push("string");
+        }
+        if self.number {
+            types.push("number");
+        } else if self.integer {
+            types.push("integer");
+        }
+        if self.boolean {
+            types.push("boolean");
+        }
+        if self.null {
+            types.push("null");
+        }
+        types
+    }
+
+    /// Builds a JSON Schema (draft 2020-12) document for the shape
+    pub fn to_json_schema(&self) -> JsonObject {
+        let mut schema = JsonMap::new();
+        schema.insert(
+            "$schema".to_string(),
+            JsonObject::JsonString("https://json-schema.org/draft/2020-12/schema".to_string()),
+        );
+        for (key, value) in self.schema_entries() {
+            schema.insert(key, value);
+        }
+        JsonObject::Object(schema)
+    }
+
+    fn schema_entries(&self) -> JsonMap {
+        let mut schema = JsonMap::new();
+
+        let types = self.types();
+        match types.as_slice() {
+            [] => {}
+            [t] => {
+                schema.insert("type".to_string(), JsonObject::JsonString(t.to_string()));
+            }
+            _ => {
+                let types = types
+                    .iter()
+                    .map(|t| JsonObject::JsonString(t.to_string()))
+                    .collect();
+                schema.insert("type".to_string(), JsonObject::Array(types));
+            }
+        }
+
+        if let Some(Some(format)) = self.string {
+            schema.insert(
+                "format".to_string(),
+                JsonObject::JsonString(format.name().to_string()),
+            );
+        }
+
+        if let Some(items) = &self.array {
+            if !items.types().is_empty() {
+                schema.insert(
+                    "items".to_string(),
+                    JsonObject::Object(items.schema_entries()),
+                );
+            }
+        }
+
+        if let Some(object) = &self.object {
+            let properties = object
+                .fields
+                .iter()
+                .map(|(name, shape, _)| (name.clone(), JsonObject::Object(shape.schema_entries())))
+                .collect();
+            schema.insert("properties".to_string(), JsonObject::Object(properties));
+
+            let required: Vec<JsonObject> = object
+                .fields
+                .iter()
+                .filter(|(_, _, seen)| *seen == object.count)
+                .map(|(name, _, _)| JsonObject::JsonString(name.clone()))
+                .collect();
+            if !required.is_empty() {
+                schema.insert("required".to_string(), JsonObject::Array(required));
+            }
+        }
+
+        schema
+    }
+
+    /// Generates Rust struct definitions (for serde) describing the shape.
+    /// Nested objects become their own structs. A root that is not an object becomes a
+    /// type alias, e.g. `pub type Root = Vec<i64>;`.
+    pub fn to_rust_structs(&self, root_name: &str) -> String {
+        let mut structs = Vec::new();
+        let mut names = Vec::new();
+        if self.types() == ["object"] {
+            self.rust_type(root_name, &mut structs, &mut names);
+        } else {
+            // The alias takes the root name, so the items are named after it
+            let alias = type_name(root_name, &mut names);
+            let target = self.rust_type(&format!("{} item", root_name), &mut structs, &mut names);
+            structs.insert(0, format!("pub type {} = {};\n", alias, target));
+        }
+        structs.join("\n")
+    }
+
+    /// Returns the rust type for the shape, pushing struct definitions for objects
+    fn rust_type(&self, name: &str, structs: &mut Vec<String>, names: &mut Vec<String>) -> String {
+        let nullable = self.null;
+        let mut non_null = self.clone();
+        non_null.null = false;
+
+        let inner = match non_null.types().as_slice() {
+            [] => "serde_json::Value".to_string(),
+            ["object"] => self.rust_struct(name, structs, names),
+            ["array"] => {
+                let items = self.array.as_ref().unwrap();
+                format!("Vec<{}>", items.rust_type(name, structs, names))
+            }
+            ["string"] => "String".to_string(),
+            ["number"] => "f64".to_string(),
+            ["integer"] => "i64".to_string(),
+            ["boolean"] => "bool".to_string(),
+            _ => "serde_json::Value".to_string(),
+        };
+
+        if nullable && inner != "serde_json::Value" {
+            format!("Option<{}>", inner)
+        } else {
+            inner
+        }
+    }
+
+    fn rust_struct(
+        &self,
+        name: &str,
+        structs: &mut Vec<String>,
+        names: &mut Vec<String>,
+    ) -> String {
+        let object = self.object.as_ref().unwrap();
+        let struct_name = type_name(name, names);
+
+        // Reserve the slot so that structs are listed parent first
+        let slot = structs.len();
+        structs.push(String::new());
+
+        let mut definition = format!(
+            "#[derive(Debug, Clone, Serialize, Deserialize)]\npub struct {} {{\n",
+            struct_name
+        );
+        let mut field_names: Vec<String> = Vec::new();
+        for (field, shape, seen) in &object.fields {
+            let mut field_type = shape.rust_type(field, structs, names);
+            if *seen < object.count && !field_type.starts_with("Option<") {
+                field_type = format!("Option<{}>", field_type);
+            }
+            // Keys like "userId" and "user_id" have the same name in snake case
+            let mut field_name = snake_case(field);
+            let base = field_name
+                .trim_start_matches("r#")
+                .trim_end_matches('_')
+                .to_string();
+            let mut suffix = 2;
+            while field_names.contains(&field_name) {
+                field_name = format!("{}_{}", base, suffix);
+                suffix += 1;
+            }
+            field_names.push(field_name.clone());
+            if field_name.trim_start_matches("r#") != field {
+                definition.push_str(&format!("    #[serde(rename = {:?})]\n", field));
+            }
+            definition.push_str(&format!("    pub {}: {},\n", field_name, field_type));
+        }
+        definition.push_str("}\n");
+        structs[slot] = definition;
+
+        struct_name
+    }
+}
+
+fn words(s: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut prev_lower = false;
+    for c in s.chars() {
+        if !c.is_alphanumeric() {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            prev_lower = false;
+            continue;
+        }
+        if c.is_uppercase() && prev_lower && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        prev_lower = c.is_lowercase() || c.is_numeric();
+        current.extend(c.to_lowercase());
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+/// Returns a new type name for `name`, with a numeric suffix if it is already taken or
+/// if it would shadow a type of the prelude
+fn type_name(name: &str, names: &mut Vec<String>) -> String {
+    let base = pascal_case(name);
+    let mut type_name = base.clone();
+    let mut suffix = 2;
+    while names.contains(&type_name) || is_reserved_type(&type_name) {
+        type_name = format!("{}{}", base, suffix);
+        suffix += 1;
+    }
+    names.push(type_name.clone());
+    type_name
+}
+
+fn pascal_case(s: &str) -> String {
+    let name: String = words(s)
+        .iter()
+        .map(|w| {
+            let mut chars = w.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect();
+    match name.chars().next() {
+        None => "Item".to_string(),
+        Some(c) if c.is_numeric() => format!("Item{}", name),
+        Some(_) => name,
+    }
+}
+
+fn snake_case(s: &str) -> String {
+    let name = words(s).join("_");
+    match name.chars().next() {
+        None => "field".to_string(),
+        Some(c) if c.is_numeric() => format!("field_{}", name),
+        // Keywords that can't be raw identifiers
+        Some(_) if matches!(name.as_str(), "crate" | "self" | "super" | "Self") => {
+            format!("{}_", name)
+        }
+        Some(_) if is_keyword(&name) => format!("r#{}", name),
+        Some(_) => name,
+    }
+}
+
+/// Strict and reserved keywords, which can be raw identifiers
+fn is_keyword(s: &str) -> bool {
+    matches!(
+        s,
+        "abstract"
+            | "as"
+            | "async"
+            | "await"
+            | "become"
+            | "box"
+            | "break"
+            | "const"
+            | "continue"
+            | "do"
+            | "dyn"
+            | "else"
+            | "enum"
+            | "extern"
+            | "false"
+            | "final"
+            | "fn"
+            | "for"
+            | "gen"
+            | "if"
+            | "impl"
+            | "in"
+            | "let"
+            | "loop"
+            | "macro"
+            | "match"
+            | "mod"
+            | "move"
+            | "mut"
+            | "override"
+            | "priv"
+            | "pub"
+            | "ref"
+            | "return"
+            | "static"
+            | "struct"
+            | "trait"
+            | "true"
+            | "try"
+            | "type"
+            | "typeof"
+            | "unsafe"
+            | "unsized"
+            | "use"
+            | "virtual"
+            | "where"
+            | "while"
+            | "yield"
+    )
+}
+
+/// Type names that a generated struct can't take: the `Self` keyword, the types and
+/// traits of the prelude, and the serde derives imported next to the structs
+fn is_reserved_type(s: &str) -> bool {
+    matches!(
+        s,
+        "Self"
+            | "AsMut"
+            | "AsRef"
+            | "Box"
+            | "Clone"
+            | "Copy"
+            | "Default"
+            | "Deserialize"
+            | "DoubleEndedIterator"
+            | "Drop"
+            | "Eq"
+            | "Err"
+            | "ExactSizeIterator"
+            | "Extend"
+            | "Fn"
+            | "FnMut"
+            | "FnOnce"
+            | "From"
+            | "FromIterator"
+            | "Into"
+            | "IntoIterator"
+            | "Iterator"
+            | "None"
+            | "Ok"
+            | "Option"
+            | "Ord"
+            | "PartialEq"
+            | "PartialOrd"
+            | "Result"
+            | "Send"
+            | "Serialize"
+            | "Sized"
+            | "Some"
+            | "String"
+            | "Sync"
+            | "ToOwned"
+            | "ToString"
+            | "TryFrom"
+            | "TryInto"
+            | "Unpin"
+            | "Vec"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process::Command};
+
+    use crate::infer::{Shape, StringFormat};
+    use crate::JsonObject;
+
+    /// Checks that rustc accepts the generated structs. The serde derives need the serde
+    /// crate, so they are removed, and the renames become doc attributes, which take a
+    /// string literal too.
+    fn assert_compiles(name: &str, code: &str) {
+        let code: String = code
+            .lines()
+            .map(|line| {
+                let line = line.replace(", Serialize, Deserialize", "");
+                match line.trim_start().strip_prefix("#[serde(rename = ") {
+                    Some(rename) => format!("#[doc = {}]\n", rename.strip_suffix(")]").unwrap()),
+                    None => line + "\n",
+                }
+            })
+            .collect();
+        let dir = std::env::temp_dir().join(format!("rjson-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("lib.rs");
+        fs::write(
+            &source,
+            format!(
+                "#![allow(dead_code)]\nmod serde_json {{ pub type Value = (); }}\n{}",
+                code
+            ),
+        )
+        .unwrap();
+        let output = Command::new("rustc")
+            .args([
+                "--edition",
+                "2021",
+                "--crate-type",
+                "lib",
+                "--emit",
+                "metadata",
+            ])
+            .arg("--out-dir")
+            .arg(&dir)
+            .arg(&source)
+            .output()
+            .expect("rustc is not available");
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(
+            output.status.success(),
+            "{}\n{}",
+            code,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    fn infer(samples: &[&str]) -> Shape {
+        let objects: Vec<JsonObject> = samples
+            .iter()
+            .map(|s| JsonObject::read(s).unwrap())
+            .collect();
+        Shape::infer(&objects)
+    }
+
+    #[test]
+    fn test_detect_formats() {
+        assert_eq!(
+            StringFormat::detect("2023-08-14T10:00:00Z"),
+            Some(StringFormat::DateTime)
+        );
+        assert_eq!(
+            StringFormat::detect("2023-08-14T10:00:00.123+02:00"),
+            Some(StringFormat::DateTime)
+        );
+        assert_eq!(
+            StringFormat::detect("123e4567-e89b-12d3-a456-426614174000"),
+            Some(StringFormat::Uuid)
+        );
+        assert_eq!(
+            StringFormat::detect("john@example.com"),
+            Some(StringFormat::Email)
+        );
+        assert_eq!(StringFormat::detect("hello"), None);
+    }
+
+    #[test]
+    fn test_infer_schema() {
+        let shape = infer(&[
+            r#"{"id": 1, "name": "a", "tags": ["x"], "created": "2023-08-14T10:00:00Z"}"#,
+            r#"{"id": 2.5, "name": null, "tags": [], "extra": true}"#,
+        ]);
+
+        let expected = JsonObject::read(
+            r#"{
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "type": "object",
+                "properties": {
+                    "id": {"type": "number"},
+                    "name": {"type": ["string", "null"]},
+                    "tags": {"type": "array", "items": {"type": "string"}},
+                    "created": {"type": "string", "format": "date-time"},
+                    "extra": {"type": "boolean"}
+                },
+                "required": ["id", "name", "tags"]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(shape.to_json_schema(), expected);
+    }
+
+    #[test]
+    fn test_infer_mixed_formats() {
+        let shape = infer(&[r#"["a@b.com", "2023-08-14T10:00:00Z"]"#]);
+        assert_eq!(shape.array.unwrap().string, Some(None));
+    }
+
+    #[test]
+    fn test_rust_structs() {
+        let shape = infer(&[
+            r#"{"userId": 1, "type": "x", "address": {"city": "Rome"}}"#,
+            r#"{"userId": 2, "address": {"city": "Milan", "zip": null}}"#,
+        ]);
+        assert_eq!(
+            shape.to_rust_structs("root"),
+            "#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Root {
+    #[serde(rename = \"userId\")]
+    pub user_id: i64,
+    pub r#type: Option<String>,
+    pub address: Address,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Address {
+    pub city: String,
+    pub zip: Option<serde_json::Value>,
+}
+"
+        );
+        assert_compiles("structs", &shape.to_rust_structs("root"));
+    }
+
+    #[test]
+    fn test_rust_structs_reserved_names() {
+        let shape =
+            infer(&[r#"{"crate": 1, "self": 2, "Super": 3, "Self": 4, "match": 5, "fn": 6}"#]);
+        let code = shape.to_rust_structs("root");
+        assert_eq!(
+            code,
+            "#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Root {
+    #[serde(rename = \"crate\")]
+    pub crate_: i64,
+    #[serde(rename = \"self\")]
+    pub self_: i64,
+    #[serde(rename = \"Super\")]
+    pub super_: i64,
+    #[serde(rename = \"Self\")]
+    pub self_2: i64,
+    pub r#match: i64,
+    pub r#fn: i64,
+}
+"
+        );
+        assert_compiles("reserved", &code);
+    }
+
+    #[test]
+    fn test_rust_structs_colliding_names() {
+        let shape = infer(&[r#"{"userId": 1, "user_id": "a", "UserID": true, "user_id_2": null}"#]);
+        let code = shape.to_rust_structs("root");
+        assert_eq!(
+            code,
+            "#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Root {
+    #[serde(rename = \"userId\")]
+    pub user_id: i64,
+    #[serde(rename = \"user_id\")]
+    pub user_id_2: String,
+    #[serde(rename = \"UserID\")]
+    pub user_id_3: bool,
+    #[serde(rename = \"user_id_2\")]
+    pub user_id_2_2: serde_json::Value,
+}
+"
+        );
+        assert_compiles("colliding", &code);
+    }
+
+    #[test]
+    fn test_rust_structs_escaped_renames() {
+        let shape = infer(&[r#"{"a\"b": 1, "c\\d": 2, "e f\u0001": 3}"#]);
+        let code = shape.to_rust_structs("root");
+        assert_eq!(
+            code,
+            r#"#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Root {
+    #[serde(rename = "a\"b")]
+    pub a_b: i64,
+    #[serde(rename = "c\\d")]
+    pub c_d: i64,
+    #[serde(rename = "e f\u{1}")]
+    pub e_f: i64,
+}
+"#
+        );
+        assert_compiles("escaped", &code);
+    }
+
+    #[test]
+    fn test_rust_structs_reserved_types() {
+        let shape = infer(&[
+            r#"{"self": {"a": 1}, "string": {"b": "x"}, "option": {"c": [null, 1]}, "name": "n"}"#,
+        ]);
+        let code = shape.to_rust_structs("string");
+        assert_eq!(
+            code,
+            "#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct String2 {
+    #[serde(rename = \"self\")]
+    pub self_: Self2,
+    pub string: String3,
+    pub option: Option2,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Self2 {
+    pub a: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct String3 {
+    pub b: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Option2 {
+    pub c: Vec<Option<i64>>,
+}
+"
+        );
+        assert_compiles("types", &code);
+    }
+
+    #[test]
+    fn test_rust_root_alias() {
+        let cases = [
+            (infer(&["[1, 2]"]), "pub type Root = Vec<i64>;\n"),
+            (
+                Shape::infer(&[JsonObject::JsonString("a".to_string())]),
+                "pub type Root = String;\n",
+            ),
+            (
+                infer(&[r#"[{"id": 1}]"#]),
+                "pub type Root = Vec<RootItem>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RootItem {
+    pub id: i64,
+}
+",
+            ),
+        ];
+        for (shape, expected) in cases {
+            let code = shape.to_rust_structs("root");
+            assert_eq!(code, expected);
+            assert_compiles("alias", &code);
+        }
+    }
+
+    #[test]
+    fn test_large_numbers_are_not_integers() {
+        let shape = infer(&[r#"[1, -9007199254740992, 1e300, 9223372036854775808]"#]);
+        let items = shape.array.unwrap();
+        assert!(items.integer);
+        assert!(items.number);
+        assert_eq!(infer(&["[1e300]"]).array.unwrap().types(), ["number"]);
+        assert_eq!(infer(&["[-1e3]"]).array.unwrap().types(), ["integer"]);
+    }
+}
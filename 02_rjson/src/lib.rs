@@ -4,15 +4,29 @@ Copied in (most) part from Matthias Kaak implementation (https://github.com/zvav
 */
 
 use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
 use std::str::FromStr;
 use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
+
+mod feeder;
+pub mod infer;
+mod map;
+mod wtf8;
+
+pub use feeder::{Feeder, Progress};
+pub use map::JsonMap;
 
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub enum JsonObject {
     Array(Vec<Self>),
-    Object(Vec<(String, Self)>),
+    Object(JsonMap),
     Number(f64),
     JsonString(String),
+    /// String holding unpaired surrogates, encoded as WTF-8. Only produced when reading
+    /// with [`SurrogatePolicy::Wtf8`].
+    Wtf8String(Vec<u8>),
     Bool(bool),
     Null,
 }
@@ -33,17 +47,57 @@ pub enum JsonError {
     NonStringAsKey,
     #[error("invalid code point specified")]
     InvalidCodepoint,
+    #[error("unpaired surrogate in string")]
+    LoneSurrogate(u16),
     #[error("invalid number")]
     InvalidNumber,
     #[error("json ended without closing the corresponding array bracket")]
     UnterminatedArray,
     #[error("json ended without closing the corresponding object bracket")]
     UnterminatedObject,
+    #[error("arrays and objects are nested too deeply")]
+    TooDeep,
+    #[error("input is not valid UTF-8")]
+    InvalidUtf8,
+}
+
+/// Maximum nesting of arrays and objects accepted by the parser, to avoid
+/// overflowing the stack on adversarial inputs
+const MAX_DEPTH: usize = 256;
+
+/// How to handle `\uXXXX` escapes encoding a surrogate without its pair
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SurrogatePolicy {
+    /// Fail with [`JsonError::LoneSurrogate`]
+    #[default]
+    Error,
+    /// Replace the surrogate with U+FFFD REPLACEMENT CHARACTER
+    Replace,
+    /// Keep the surrogate, reading the string as a [`JsonObject::Wtf8String`].
+    /// Keys can't hold surrogates and still fail.
+    Wtf8,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ReadOptions {
+    pub surrogates: SurrogatePolicy,
+    /// Normalize object keys to Unicode Normalization Form C
+    pub normalize_keys: bool,
 }
 
 impl JsonObject {
     pub fn read(s: &str) -> Result<Self, JsonError> {
-        let mut parser = Parser::new(s);
+        Self::read_with(s, &ReadOptions::default())
+    }
+
+    pub fn read_with(s: &str, options: &ReadOptions) -> Result<Self, JsonError> {
+        let mut parser = Parser::with_options(s, options.clone());
+        parser.partial_read(true)
+    }
+
+    /// Reads json text encoded as WTF-8, where surrogates may also appear unescaped
+    pub fn read_wtf8(bytes: &[u8], options: &ReadOptions) -> Result<Self, JsonError> {
+        let mut parser = Parser::from_wtf8(bytes, options.clone())?;
         parser.partial_read(true)
     }
 
@@ -51,21 +105,225 @@ impl JsonObject {
         let contents = std::fs::read_to_string(path)?;
         Ok(Self::read(&contents)?)
     }
+
+    /// Serializes the object as WTF-8 json text, writing the unpaired surrogates of
+    /// [`JsonObject::Wtf8String`]s unescaped. Without those it is the same as `to_string`.
+    pub fn to_wtf8(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_wtf8(&mut out);
+        out
+    }
+
+    fn write_wtf8(&self, out: &mut Vec<u8>) {
+        match self {
+            JsonObject::Array(elements) => {
+                out.push(b'[');
+                for (i, elem) in elements.iter().enumerate() {
+                    if i > 0 {
+                        out.push(b',');
+                    }
+                    elem.write_wtf8(out);
+                }
+                out.push(b']');
+            }
+            JsonObject::Object(elements) => {
+                out.push(b'{');
+                for (i, (key, elem)) in elements.iter().enumerate() {
+                    if i > 0 {
+                        out.push(b',');
+                    }
+                    out.extend_from_slice(
+                        JsonObject::JsonString(key.clone()).to_string().as_bytes(),
+                    );
+                    out.push(b':');
+                    elem.write_wtf8(out);
+                }
+                out.push(b'}');
+            }
+            JsonObject::Wtf8String(bytes) => match wtf8::code_points(bytes) {
+                Ok(code_points) => {
+                    out.push(b'"');
+                    let mut escaped = String::new();
+                    for cp in code_points {
+                        match char::from_u32(cp) {
+                            Some(c) => {
+                                escaped.clear();
+                                // Writing to a String can't fail
+                                let _ = write_escaped_char(&mut escaped, c);
+                                out.extend_from_slice(escaped.as_bytes());
+                            }
+                            None => wtf8::push_code_point(out, cp),
+                        }
+                    }
+                    out.push(b'"');
+                }
+                Err(_) => out.extend_from_slice(self.to_string().as_bytes()),
+            },
+            _ => out.extend_from_slice(self.to_string().as_bytes()),
+        }
+    }
+
+    /// Serializes the object as json text indented by two spaces per level
+    pub fn to_string_pretty(&self) -> String {
+        let mut out = String::new();
+        self.write_pretty(&mut out, 0);
+        out
+    }
+
+    fn write_pretty(&self, out: &mut String, level: usize) {
+        let indent = "  ".repeat(level + 1);
+        match self {
+            JsonObject::Array(elements) if !elements.is_empty() => {
+                out.push_str("[\n");
+                for (i, elem) in elements.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(",\n");
+                    }
+                    out.push_str(&indent);
+                    elem.write_pretty(out, level + 1);
+                }
+                out.push('\n');
+                out.push_str(&"  ".repeat(level));
+                out.push(']');
+            }
+            JsonObject::Object(elements) if !elements.is_empty() => {
+                out.push_str("{\n");
+                for (i, (key, elem)) in elements.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(",\n");
+                    }
+                    out.push_str(&indent);
+                    out.push_str(&JsonObject::JsonString(key.clone()).to_string());
+                    out.push_str(": ");
+                    elem.write_pretty(out, level + 1);
+                }
+                out.push('\n');
+                out.push_str(&"  ".repeat(level));
+                out.push('}');
+            }
+            _ => out.push_str(&self.to_string()),
+        }
+    }
+}
+
+/// Serializes the object as compact json text, which [`JsonObject::read`] can parse back.
+/// Non finite numbers, which [`JsonObject::read`] never produces, have no json
+/// representation and are written as `null`.
+impl fmt::Display for JsonObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonObject::Array(elements) => {
+                write!(f, "[")?;
+                for (i, elem) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", elem)?;
+                }
+                write!(f, "]")
+            }
+            JsonObject::Object(elements) => {
+                write!(f, "{{")?;
+                for (i, (key, elem)) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", elem)?;
+                }
+                write!(f, "}}")
+            }
+            JsonObject::Number(n) if n.is_finite() => write!(f, "{}", n),
+            JsonObject::Number(_) => write!(f, "null"),
+            JsonObject::JsonString(s) => write_string(f, s),
+            JsonObject::Wtf8String(bytes) => match wtf8::code_points(bytes) {
+                Ok(code_points) => {
+                    write!(f, "\"")?;
+                    for cp in code_points {
+                        match char::from_u32(cp) {
+                            Some(c) => write_escaped_char(f, c)?,
+                            None => write!(f, "\\u{:04x}", cp)?,
+                        }
+                    }
+                    write!(f, "\"")
+                }
+                Err(_) => write_string(f, &String::from_utf8_lossy(bytes)),
+            },
+            JsonObject::Bool(b) => write!(f, "{}", b),
+            JsonObject::Null => write!(f, "null"),
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        write_escaped_char(f, c)?;
+    }
+    write!(f, "\"")
+}
+
+fn write_escaped_char<W: fmt::Write>(w: &mut W, c: char) -> fmt::Result {
+    match c {
+        '"' => write!(w, "\\\""),
+        '\\' => write!(w, "\\\\"),
+        '\n' => write!(w, "\\n"),
+        '\r' => write!(w, "\\r"),
+        '\t' => write!(w, "\\t"),
+        '\u{0008}' => write!(w, "\\b"),
+        '\u{000c}' => write!(w, "\\f"),
+        c if (c as u32) < 0x20 => write!(w, "\\u{:04x}", c as u32),
+        c => write!(w, "{}", c),
+    }
 }
 
 struct Parser {
     buf: Vec<char>,
     index: usize,
+    depth: usize,
+    options: ReadOptions,
+    /// Unescaped surrogates of WTF-8 input by their index in `buf`, where they are
+    /// replaced with U+FFFD. They are only valid as characters of strings.
+    raw_surrogates: HashMap<usize, u16>,
 }
 
 impl Parser {
+    #[cfg(test)]
     fn new(str: &str) -> Self {
+        Self::with_options(str, ReadOptions::default())
+    }
+
+    fn with_options(str: &str, options: ReadOptions) -> Self {
         Parser {
             buf: str.chars().collect(),
             index: 0,
+            depth: 0,
+            options,
+            raw_surrogates: HashMap::new(),
         }
     }
 
+    fn from_wtf8(bytes: &[u8], options: ReadOptions) -> Result<Self, JsonError> {
+        let mut buf = Vec::with_capacity(bytes.len());
+        let mut raw_surrogates = HashMap::new();
+        for cp in wtf8::code_points(bytes)? {
+            match char::from_u32(cp) {
+                Some(c) => buf.push(c),
+                None => {
+                    raw_surrogates.insert(buf.len(), cp as u16);
+                    buf.push(char::REPLACEMENT_CHARACTER);
+                }
+            }
+        }
+        Ok(Parser {
+            buf,
+            index: 0,
+            depth: 0,
+            options,
+            raw_surrogates,
+        })
+    }
+
     pub fn skip_whitespace(&mut self) {
         while self.buf.len() > self.index && self.buf[self.index].is_whitespace() {
             self.index += 1;
@@ -114,10 +372,10 @@ impl Parser {
                 return Err(JsonError::InvalidChar(self.buf[self.index], self.index));
             }
             let result = match c {
-                '{' => self.partial_read_object(),
-                '[' => self.partial_read_array(),
+                '{' => self.partial_read_nested(Self::partial_read_object),
+                '[' => self.partial_read_nested(Self::partial_read_array),
                 '0'..='9' | '+' | '-' => self.partial_read_number(),
-                '"' => self.partial_read_string(),
+                '"' => self.partial_read_string(false),
                 'f' => self.partial_read_false(),
                 't' => self.partial_read_true(),
                 'n' => self.partial_read_null(),
@@ -137,6 +395,20 @@ impl Parser {
         }
     }
 
+    /// Reads an array or an object, keeping track of the nesting depth
+    fn partial_read_nested(
+        &mut self,
+        read: fn(&mut Self) -> Result<JsonObject, JsonError>,
+    ) -> Result<JsonObject, JsonError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(JsonError::TooDeep);
+        }
+        self.depth += 1;
+        let result = read(self);
+        self.depth -= 1;
+        result
+    }
+
     // Reads a jsoon number object from a given index
     fn partial_read_number(&mut self) -> Result<JsonObject, JsonError> {
         let mut token = String::new();
@@ -196,10 +468,10 @@ impl Parser {
             return Err(JsonError::InvalidNumber);
         }
 
-        let num = f64::from_str(&token);
-        match num {
-            Ok(num) => Ok(JsonObject::Number(num)),
-            Err(_) => Err(JsonError::InvalidNumber),
+        // Numbers too large for a f64 would be read as infinite, which json cannot write back
+        match f64::from_str(&token) {
+            Ok(num) if num.is_finite() => Ok(JsonObject::Number(num)),
+            _ => Err(JsonError::InvalidNumber),
         }
     }
 
@@ -235,7 +507,9 @@ impl Parser {
         self.partial_read_given_string("null", JsonObject::Null)
     }
 
-    fn partial_read_string(&mut self) -> Result<JsonObject, JsonError> {
+    /// Reads a json string. Keys can't be WTF-8 strings, so for them
+    /// [`SurrogatePolicy::Wtf8`] behaves as [`SurrogatePolicy::Error`].
+    fn partial_read_string(&mut self, key: bool) -> Result<JsonObject, JsonError> {
         self.skip_whitespace();
         let mut utf16: Vec<u16> = Vec::new();
 
@@ -279,24 +553,45 @@ impl Parser {
                             utf16.push(u);
                         }
                         Some(c) => return Err(JsonError::InvalidChar(c, self.index)),
-                        None => return Err(JsonError::Empty),
+                        None => return Err(JsonError::EndedOnEscape),
                     };
                 }
                 '"' => {
                     self.next_no_skip();
-                    return Ok(JsonObject::JsonString(String::from_utf16(&utf16).unwrap()));
-                }
-                '\n' | '\r' | '\t' => return Err(JsonError::InvalidChar(c, self.index)),
-                _ => {
-                    let mut buf = [0u16; 2];
-                    utf16.extend_from_slice(c.encode_utf16(&mut buf));
+                    return self.decode_utf16(&utf16, key);
                 }
+                // Control characters must be escaped
+                '\u{0000}'..='\u{001f}' => return Err(JsonError::InvalidChar(c, self.index)),
+                _ => match self.raw_surrogates.get(&self.index) {
+                    Some(&surrogate) => utf16.push(surrogate),
+                    None => {
+                        let mut buf = [0u16; 2];
+                        utf16.extend_from_slice(c.encode_utf16(&mut buf));
+                    }
+                },
             }
         }
 
         Err(JsonError::UnterminatedString)
     }
 
+    fn decode_utf16(&self, utf16: &[u16], key: bool) -> Result<JsonObject, JsonError> {
+        if let Ok(s) = String::from_utf16(utf16) {
+            return Ok(JsonObject::JsonString(s));
+        }
+        match self.options.surrogates {
+            SurrogatePolicy::Replace => Ok(JsonObject::JsonString(String::from_utf16_lossy(utf16))),
+            SurrogatePolicy::Wtf8 if !key => Ok(JsonObject::Wtf8String(wtf8::from_utf16(utf16))),
+            _ => {
+                let surrogate = char::decode_utf16(utf16.iter().copied())
+                    .find_map(|c| c.err())
+                    .map(|err| err.unpaired_surrogate())
+                    .unwrap_or_default();
+                Err(JsonError::LoneSurrogate(surrogate))
+            }
+        }
+    }
+
     fn partial_read_array(&mut self) -> Result<JsonObject, JsonError> {
         let mut elements: Vec<JsonObject> = Vec::new();
         self.next_no_skip();
@@ -333,14 +628,19 @@ impl Parser {
     }
 
     fn partial_read_object(&mut self) -> Result<JsonObject, JsonError> {
-        let mut elements: Vec<(String, JsonObject)> = Vec::new();
+        let mut elements = JsonMap::new();
         self.next();
 
         loop {
             match self.peek() {
                 Some('"') => {
                     // Parse "key": val
-                    if let JsonObject::JsonString(key) = self.partial_read_string()? {
+                    if let JsonObject::JsonString(key) = self.partial_read_string(true)? {
+                        let key = if self.options.normalize_keys {
+                            key.nfc().collect()
+                        } else {
+                            key
+                        };
                         if let Some(_c @ (' ' | '\t' | '\r' | '\n')) = self.peek() {
                             self.next();
                         }
@@ -348,9 +648,8 @@ impl Parser {
                             // Parse element
                             self.next();
                             let element = self.partial_read(false)?;
-                            elements.push((key, element));
+                            elements.insert(key, element);
                         } else {
-                            println!("error key1 : \"{:?}\"", self.peek());
                             return Err(JsonError::NonStringAsKey);
                         }
                     } else {
@@ -381,9 +680,9 @@ impl Parser {
 
 #[cfg(test)]
 mod tests {
-    use crate::JsonError;
     use crate::JsonObject::{self, Array, Bool, JsonString, Null, Number, Object};
     use crate::Parser;
+    use crate::{JsonError, JsonMap, ReadOptions, SurrogatePolicy};
 
     fn test_read(s: &str) -> Result<JsonObject, JsonError> {
         let mut parser = Parser::new(s);
@@ -411,6 +710,12 @@ mod tests {
             Number(0.123456789e-12)
         );
         assert_eq!(test_read("4324. 6234"), Err(JsonError::InvalidNumber));
+
+        // Out of the range of f64
+        assert_eq!(test_read("1e400"), Err(JsonError::InvalidNumber));
+        assert_eq!(test_read("-1e400"), Err(JsonError::InvalidNumber));
+        assert_eq!(test_read("[1e400]"), Err(JsonError::InvalidNumber));
+        assert_eq!(test_read("1e-400").unwrap(), Number(0.0));
     }
 
     #[test]
@@ -469,26 +774,163 @@ mod tests {
 
     #[test]
     fn test_read_object() {
-        assert_eq!(test_read("{}").unwrap(), Object(Vec::new()));
+        assert_eq!(test_read("{}").unwrap(), Object(JsonMap::new()));
         assert_eq!(
             test_read("{\"test\": true}").unwrap(),
-            Object(vec![("test".to_string(), Bool(true))])
+            Object(vec![("test".to_string(), Bool(true))].into())
         );
         assert_eq!(
             test_read("{\"test\": true, \"other\": 42.13, \"testnull\": null}").unwrap(),
-            Object(vec![
-                ("test".to_string(), Bool(true)),
-                ("other".to_string(), Number(42.13)),
-                ("testnull".to_string(), Null)
-            ])
+            Object(
+                vec![
+                    ("test".to_string(), Bool(true)),
+                    ("other".to_string(), Number(42.13)),
+                    ("testnull".to_string(), Null)
+                ]
+                .into()
+            )
         );
 
         assert_eq!(
             test_read("{\"object with 1 member\":[\"array with 1 element\"]}").unwrap(),
-            Object(vec![(
-                "object with 1 member".to_string(),
-                Array(vec![JsonString("array with 1 element".to_string())])
-            )])
+            Object(
+                vec![(
+                    "object with 1 member".to_string(),
+                    Array(vec![JsonString("array with 1 element".to_string())])
+                )]
+                .into()
+            )
+        )
+    }
+
+    #[test]
+    fn test_read_invalid_surrogate() {
+        assert_eq!(
+            test_read("\"\\uD800\""),
+            Err(JsonError::LoneSurrogate(0xD800))
+        );
+        assert_eq!(
+            test_read("\"\\uDD1E\\uD834\""),
+            Err(JsonError::LoneSurrogate(0xDD1E))
+        );
+    }
+
+    #[test]
+    fn test_read_too_deep() {
+        let deep = "[".repeat(100_000);
+        assert_eq!(test_read(&deep), Err(JsonError::TooDeep));
+
+        let nested = format!("{}{}", "[".repeat(200), "]".repeat(200));
+        assert!(test_read(&nested).is_ok());
+    }
+
+    #[test]
+    fn test_display() {
+        let object = JsonObject::read(
+            "{\"a\": [1, 2.5, -3e2], \"b\": {\"c\": null, \"d\": true}, \"e\": \"x\\\"\\n\\u0001\"}",
         )
+        .unwrap();
+        assert_eq!(
+            object.to_string(),
+            "{\"a\":[1,2.5,-300],\"b\":{\"c\":null,\"d\":true},\"e\":\"x\\\"\\n\\u0001\"}"
+        );
+        assert_eq!(Number(f64::NAN).to_string(), "null");
+    }
+
+    #[test]
+    fn test_to_string_pretty() {
+        let object = JsonObject::read("{\"a\": [1, {}], \"b\": {\"c\": []}}").unwrap();
+        assert_eq!(
+            object.to_string_pretty(),
+            "{\n  \"a\": [\n    1,\n    {}\n  ],\n  \"b\": {\n    \"c\": []\n  }\n}"
+        );
+    }
+
+    #[test]
+    fn test_read_unescaped_control_chars() {
+        assert_eq!(
+            test_read("\"a\u{0}b\""),
+            Err(JsonError::InvalidChar('\u{0}', 2))
+        );
+        assert_eq!(
+            test_read("\"\u{1f}\""),
+            Err(JsonError::InvalidChar('\u{1f}', 1))
+        );
+        assert_eq!(
+            test_read("\"\u{7f}\"").unwrap(),
+            JsonString("\u{7f}".to_string())
+        );
+        assert_eq!(test_read("\"\\"), Err(JsonError::EndedOnEscape));
+    }
+
+    #[test]
+    fn test_surrogate_policies() {
+        let replace = ReadOptions {
+            surrogates: SurrogatePolicy::Replace,
+            ..ReadOptions::default()
+        };
+        assert_eq!(
+            JsonObject::read_with("[\"a\\uD800b\"]", &replace).unwrap(),
+            Array(vec![JsonString("a\u{fffd}b".to_string())])
+        );
+
+        let wtf8 = ReadOptions {
+            surrogates: SurrogatePolicy::Wtf8,
+            ..ReadOptions::default()
+        };
+        let object = JsonObject::read_with("[\"a\\uD800\", \"\\uD834\\uDD1E\"]", &wtf8).unwrap();
+        assert_eq!(
+            object,
+            Array(vec![
+                JsonObject::Wtf8String(vec![b'a', 0xED, 0xA0, 0x80]),
+                JsonString("𝄞".to_string())
+            ])
+        );
+        assert_eq!(object.to_string(), "[\"a\\ud800\",\"𝄞\"]");
+        assert_eq!(
+            JsonObject::read_with(&object.to_string(), &wtf8),
+            Ok(object.clone())
+        );
+
+        let bytes = object.to_wtf8();
+        assert_eq!(bytes, b"[\"a\xED\xA0\x80\",\"\xF0\x9D\x84\x9E\"]");
+        assert_eq!(JsonObject::read_wtf8(&bytes, &wtf8), Ok(object));
+
+        // Raw surrogates are characters of strings, not part of escapes or of the syntax
+        for invalid in [&b"[\"\\\xED\xA0\x80\"]"[..], b"[\xED\xA0\x80]"] {
+            assert!(
+                JsonObject::read_wtf8(invalid, &wtf8).is_err(),
+                "{:?}",
+                invalid
+            );
+        }
+        assert_eq!(
+            JsonObject::read_wtf8(b"[\"\\\\\xED\xA0\x80\"]", &wtf8),
+            Ok(Array(vec![JsonObject::Wtf8String(vec![
+                b'\\', 0xED, 0xA0, 0x80
+            ])]))
+        );
+        assert_eq!(
+            JsonObject::read_wtf8(b"[\"\xED\xA0\x80\"]", &ReadOptions::default()),
+            Err(JsonError::LoneSurrogate(0xD800))
+        );
+
+        assert_eq!(
+            JsonObject::read_with("{\"\\uD800\": 1}", &wtf8),
+            Err(JsonError::LoneSurrogate(0xD800))
+        );
+    }
+
+    #[test]
+    fn test_normalize_keys() {
+        let options = ReadOptions {
+            normalize_keys: true,
+            ..ReadOptions::default()
+        };
+        let object = JsonObject::read_with("{\"e\\u0301\": \"e\\u0301\"}", &options).unwrap();
+        assert_eq!(
+            object,
+            Object(vec![("\u{e9}".to_string(), JsonString("e\u{301}".to_string()))].into())
+        );
     }
 }
@@ -0,0 +1,59 @@
+use std::process::exit;
+
+use clap::{Parser, Subcommand};
+use rjson::{infer::Shape, JsonObject};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Infer a JSON Schema describing all the given sample files
+    Infer {
+        /// Print Rust struct definitions instead of a JSON Schema
+        #[arg(long)]
+        rust: bool,
+
+        /// Name of the root struct, when printing Rust definitions
+        #[arg(long, default_value = "Root")]
+        root_name: String,
+
+        /// Sample json files
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+}
+
+fn main() {
+    let args = Args::parse();
+
+    match args.command {
+        Commands::Infer {
+            rust,
+            root_name,
+            files,
+        } => {
+            let mut samples = Vec::new();
+            for path in files {
+                match JsonObject::read_file(&path) {
+                    Ok(sample) => samples.push(sample),
+                    Err(err) => {
+                        eprintln!("rjson: {}: {}", path, err);
+                        exit(1);
+                    }
+                }
+            }
+
+            let shape = Shape::infer(&samples);
+            if rust {
+                print!("{}", shape.to_rust_structs(&root_name));
+            } else {
+                println!("{}", shape.to_json_schema().to_string_pretty());
+            }
+        }
+    }
+}
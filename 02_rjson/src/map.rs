@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::ops::Index;
+
+use crate::JsonObject;
+
+/// Insertion-ordered map used as the content of a [`JsonObject::Object`].
+///
+/// Entries are kept in a vector (so that serialization follows the order of the
+/// source document), while a hash index from key to position gives O(1) lookups.
+/// Keys are unique: a duplicate key replaces the value of the first one, see
+/// [`JsonMap::insert`].
+#[derive(Clone, Debug, Default)]
+pub struct JsonMap {
+    entries: Vec<(String, JsonObject)>,
+    index: HashMap<String, usize>,
+}
+
+impl JsonMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        JsonMap {
+            entries: Vec::with_capacity(capacity),
+            index: HashMap::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.index.contains_key(key)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&JsonObject> {
+        self.index.get(key).map(|&i| &self.entries[i].1)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut JsonObject> {
+        match self.index.get(key) {
+            Some(&i) => Some(&mut self.entries[i].1),
+            None => None,
+        }
+    }
+
+    /// Inserts a value, returning the previous one if the key was already present.
+    /// An existing key keeps its original position but takes the new value, so an object
+    /// whose key appears twice reads as the key at its first position with its last value.
+    pub fn insert(&mut self, key: String, value: JsonObject) -> Option<JsonObject> {
+        if let Some(&i) = self.index.get(&key) {
+            return Some(std::mem::replace(&mut self.entries[i].1, value));
+        }
+        self.index.insert(key.clone(), self.entries.len());
+        self.entries.push((key, value));
+        None
+    }
+
+    /// Removes a key, preserving the order of the remaining entries.
+    /// This shifts the following entries, so it is O(n) in the size of the map.
+    pub fn remove(&mut self, key: &str) -> Option<JsonObject> {
+        let i = self.index.remove(key)?;
+        let (_, value) = self.entries.remove(i);
+        for (k, _) in &self.entries[i..] {
+            if let Some(pos) = self.index.get_mut(k) {
+                *pos -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &JsonObject)> {
+        self.entries.iter().map(|(k, v)| (k, v))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.entries.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &JsonObject> {
+        self.entries.iter().map(|(_, v)| v)
+    }
+
+    /// Entries sorted by key, so that comparisons do not depend on the insertion order
+    fn sorted_entries(&self) -> Vec<(&String, &JsonObject)> {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        entries
+    }
+}
+
+/// Two maps are equal when they have the same keys with equal values,
+/// regardless of the order in which the keys appear.
+impl PartialEq for JsonMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+/// Maps are ordered by their entries in key order, consistently with `PartialEq`.
+impl PartialOrd for JsonMap {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.sorted_entries().partial_cmp(&other.sorted_entries())
+    }
+}
+
+impl Index<&str> for JsonMap {
+    type Output = JsonObject;
+
+    fn index(&self, key: &str) -> &JsonObject {
+        self.get(key).expect("key not found in JsonMap")
+    }
+}
+
+impl FromIterator<(String, JsonObject)> for JsonMap {
+    fn from_iter<I: IntoIterator<Item = (String, JsonObject)>>(iter: I) -> Self {
+        let mut map = JsonMap::new();
+        for (k, v) in iter {
+            map.insert(k, v);
+        }
+        map
+    }
+}
+
+impl From<Vec<(String, JsonObject)>> for JsonMap {
+    fn from(entries: Vec<(String, JsonObject)>) -> Self {
+        entries.into_iter().collect()
+    }
+}
+
+impl IntoIterator for JsonMap {
+    type Item = (String, JsonObject);
+    type IntoIter = std::vec::IntoIter<(String, JsonObject)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use crate::JsonMap;
+    use crate::JsonObject::{self, Bool, Null, Number};
+
+    fn sample() -> JsonMap {
+        vec![
+            ("a".to_string(), Number(1.0)),
+            ("b".to_string(), Bool(true)),
+            ("c".to_string(), Null),
+        ]
+        .into()
+    }
+
+    #[test]
+    fn test_get_and_order() {
+        let map = sample();
+        assert_eq!(map.get("b"), Some(&Bool(true)));
+        assert_eq!(map.get("z"), None);
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_insert_existing_keeps_position() {
+        let mut map = sample();
+        assert_eq!(map.insert("a".to_string(), Null), Some(Number(1.0)));
+        assert_eq!(map.insert("d".to_string(), Null), None);
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec!["a", "b", "c", "d"]);
+        assert_eq!(map["a"], Null);
+    }
+
+    #[test]
+    fn test_duplicate_keys() {
+        let object = JsonObject::read(r#"{"a": 1, "b": 2, "a": 3}"#).unwrap();
+        let JsonObject::Object(map) = &object else {
+            panic!("not an object: {:?}", object);
+        };
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(map["a"], Number(3.0));
+        assert_eq!(object.to_string(), r#"{"a":3,"b":2}"#);
+    }
+
+    #[test]
+    fn test_remove_reindexes() {
+        let mut map = sample();
+        assert_eq!(map.remove("a"), Some(Number(1.0)));
+        assert_eq!(map.remove("a"), None);
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec!["b", "c"]);
+        assert_eq!(map.get("c"), Some(&Null));
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn test_eq_ignores_order() {
+        let reordered: JsonMap = vec![
+            ("c".to_string(), Null),
+            ("a".to_string(), Number(1.0)),
+            ("b".to_string(), Bool(true)),
+        ]
+        .into();
+        assert_eq!(sample(), reordered);
+        assert_eq!(sample().partial_cmp(&reordered), Some(Ordering::Equal));
+
+        let mut different = sample();
+        different.insert("b".to_string(), Bool(false));
+        assert_ne!(sample(), different);
+        assert_eq!(different.partial_cmp(&sample()), Some(Ordering::Less));
+        assert_eq!(reordered.partial_cmp(&different), Some(Ordering::Greater));
+    }
+}
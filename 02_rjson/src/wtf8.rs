@@ -0,0 +1,112 @@
+//! Helpers for WTF-8, the superset of UTF-8 that can also encode unpaired surrogates
+//! (https://simonsapin.github.io/wtf-8/). It allows strings read from json escapes
+//! like `"\uD800"` to be kept and written back without losing information.
+
+use crate::JsonError;
+
+/// Appends the generalized UTF-8 encoding of a code point, surrogates included
+pub(crate) fn push_code_point(out: &mut Vec<u8>, cp: u32) {
+    match cp {
+        0..=0x7F => out.push(cp as u8),
+        0x80..=0x7FF => out.extend_from_slice(&[0xC0 | (cp >> 6) as u8, 0x80 | (cp & 0x3F) as u8]),
+        0x800..=0xFFFF => out.extend_from_slice(&[
+            0xE0 | (cp >> 12) as u8,
+            0x80 | ((cp >> 6) & 0x3F) as u8,
+            0x80 | (cp & 0x3F) as u8,
+        ]),
+        _ => out.extend_from_slice(&[
+            0xF0 | (cp >> 18) as u8,
+            0x80 | ((cp >> 12) & 0x3F) as u8,
+            0x80 | ((cp >> 6) & 0x3F) as u8,
+            0x80 | (cp & 0x3F) as u8,
+        ]),
+    }
+}
+
+/// Converts UTF-16 code units to WTF-8, pairing surrogates where possible
+pub(crate) fn from_utf16(utf16: &[u16]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(utf16.len());
+    for unit in char::decode_utf16(utf16.iter().copied()) {
+        match unit {
+            Ok(c) => push_code_point(&mut out, c as u32),
+            Err(err) => push_code_point(&mut out, err.unpaired_surrogate() as u32),
+        }
+    }
+    out
+}
+
+/// Decodes WTF-8 bytes into code points, failing on malformed sequences. A surrogate pair
+/// must be encoded as the 4 bytes of its supplementary code point, not as two surrogates.
+pub(crate) fn code_points(bytes: &[u8]) -> Result<Vec<u32>, JsonError> {
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        let (len, init, min) = match b {
+            0x00..=0x7F => (1, b as u32, 0),
+            0xC0..=0xDF => (2, (b & 0x1F) as u32, 0x80),
+            0xE0..=0xEF => (3, (b & 0x0F) as u32, 0x800),
+            0xF0..=0xF4 => (4, (b & 0x07) as u32, 0x10000),
+            _ => return Err(JsonError::InvalidUtf8),
+        };
+        if i + len > bytes.len() {
+            return Err(JsonError::InvalidUtf8);
+        }
+        let mut cp = init;
+        for &cont in &bytes[i + 1..i + len] {
+            if cont & 0xC0 != 0x80 {
+                return Err(JsonError::InvalidUtf8);
+            }
+            cp = (cp << 6) | (cont & 0x3F) as u32;
+        }
+        if cp < min || cp > 0x10FFFF {
+            return Err(JsonError::InvalidUtf8);
+        }
+        if (0xDC00..=0xDFFF).contains(&cp)
+            && result
+                .last()
+                .is_some_and(|prev| (0xD800..=0xDBFF).contains(prev))
+        {
+            return Err(JsonError::InvalidUtf8);
+        }
+        result.push(cp);
+        i += len;
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wtf8::{code_points, from_utf16};
+    use crate::JsonError;
+
+    #[test]
+    fn test_from_utf16() {
+        assert_eq!(from_utf16(&[0x61, 0xD834, 0xDD1E]), "a𝄞".as_bytes());
+        assert_eq!(from_utf16(&[0xD800]), vec![0xED, 0xA0, 0x80]);
+        assert_eq!(from_utf16(&[0xDD1E, 0x61]), vec![0xED, 0xB4, 0x9E, 0x61]);
+    }
+
+    #[test]
+    fn test_code_points() {
+        assert_eq!(code_points("aä𝄞".as_bytes()), Ok(vec![0x61, 0xE4, 0x1D11E]));
+        assert_eq!(code_points(&[0xED, 0xA0, 0x80]), Ok(vec![0xD800]));
+        assert_eq!(code_points(&[0xC0, 0x80]), Err(JsonError::InvalidUtf8));
+        assert_eq!(code_points(&[0xE2, 0x82]), Err(JsonError::InvalidUtf8));
+        assert_eq!(code_points(&[0xFF]), Err(JsonError::InvalidUtf8));
+
+        // Surrogates that would form a pair
+        assert_eq!(
+            code_points(&[0xED, 0xA0, 0xB4, 0xED, 0xB4, 0x9E]),
+            Err(JsonError::InvalidUtf8)
+        );
+        assert_eq!(
+            code_points(&[0xED, 0xB4, 0x9E, 0xED, 0xA0, 0xB4]),
+            Ok(vec![0xDD1E, 0xD834])
+        );
+        assert_eq!(
+            code_points(&[0xED, 0xA0, 0xB4, 0x61, 0xED, 0xB4, 0x9E]),
+            Ok(vec![0xD834, 0x61, 0xDD1E])
+        );
+    }
+}
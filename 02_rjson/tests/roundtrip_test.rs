@@ -0,0 +1,50 @@
+use proptest::prelude::*;
+use rjson::{JsonMap, JsonObject};
+
+fn arb_leaf() -> impl Strategy<Value = JsonObject> {
+    prop_oneof![
+        Just(JsonObject::Null),
+        any::<bool>().prop_map(JsonObject::Bool),
+        any::<f64>()
+            .prop_filter("json numbers are finite", |n| n.is_finite())
+            .prop_map(JsonObject::Number),
+        any::<String>().prop_map(JsonObject::JsonString),
+    ]
+}
+
+fn arb_json() -> impl Strategy<Value = JsonObject> {
+    arb_leaf().prop_recursive(6, 64, 8, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..8).prop_map(JsonObject::Array),
+            prop::collection::vec((any::<String>(), inner), 0..8)
+                .prop_map(|entries| JsonObject::Object(JsonMap::from_iter(entries))),
+        ]
+    })
+}
+
+/// The parser only accepts arrays and objects at the root
+fn arb_root() -> impl Strategy<Value = JsonObject> {
+    prop_oneof![
+        prop::collection::vec(arb_json(), 0..8).prop_map(JsonObject::Array),
+        prop::collection::vec((any::<String>(), arb_json()), 0..8)
+            .prop_map(|entries| JsonObject::Object(JsonMap::from_iter(entries))),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn parse_serialize_roundtrip(object in arb_root()) {
+        let text = object.to_string();
+        prop_assert_eq!(JsonObject::read(&text), Ok(object));
+    }
+
+    #[test]
+    fn read_never_panics(s in any::<String>()) {
+        let _ = JsonObject::read(&s);
+    }
+
+    #[test]
+    fn read_never_panics_on_json_like_input(s in r#"[\[\]{}",:0-9.eE+\-tfrueanl\\ u]{0,64}"#) {
+        let _ = JsonObject::read(&s);
+    }
+}
@@ -0,0 +1,42 @@
+use std::{fs, hint::black_box};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rcompress::Options;
+
+const GUTENBERG: &str = "../gutenberg.org_files_135_135-0.txt";
+
+fn compress(input: &[u8], level: u32) -> Vec<u8> {
+    let mut output = Vec::new();
+    let options = Options {
+        level,
+        ..Options::default()
+    };
+    rcompress::compress_with(&mut &input[..], &mut output, options).unwrap();
+    output
+}
+
+fn decompress(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    rcompress::decompress(&mut &input[..], &mut output).unwrap();
+    output
+}
+
+fn bench_huffman(c: &mut Criterion) {
+    let text = fs::read(GUTENBERG).expect("missing gutenberg text");
+    let compressed = compress(&text, Options::default().level);
+
+    let mut group = c.benchmark_group("gutenberg");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    for level in [1, 6, 9] {
+        group.bench_with_input(BenchmarkId::new("compress", level), &level, |b, &level| {
+            b.iter(|| compress(black_box(&text), level))
+        });
+    }
+    group.bench_function("decompress", |b| {
+        b.iter(|| decompress(black_box(&compressed)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_huffman);
+criterion_main!(benches);
@@ -1,80 +1,224 @@
-use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::io::{self, BufRead, BufReader, Read, Write};
 
+/// Size of the blocks written to the sink
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Reads bits from the least significant bit of each byte, buffered in a 64-bit integer
 pub struct BitReader<R: Read> {
-    index: usize,
-    current: u8,
+    /// Bits read from the source and not consumed yet, the next one being the lowest
+    bits: u64,
+    /// Number of valid bits in `bits`
+    count: u32,
     source: BufReader<R>,
 }
 
 impl<R: Read> BitReader<R> {
     pub fn new(source: R) -> Self {
-        let reader = BitReader {
-            index: 8,
-            current: 0,
+        BitReader {
+            bits: 0,
+            count: 0,
             source: BufReader::new(source),
-        };
-        reader
+        }
     }
 
+    #[cfg(test)]
     pub fn read(&mut self, out: &mut [u8]) -> io::Result<u8> {
         let mut bits_read: u8 = 0;
-        for _ in 0..out.len() {
-            if self.index > 7 {
-                // Read next byte
-                let mut buf = [0];
-                match self.source.read(&mut buf) {
-                    Ok(0) => return Ok(bits_read),
-                    Ok(_) => {
-                        self.current = buf[0];
-                        self.index = 0;
-                    }
-                    Err(e) => return Err(e),
+        for bit in out.iter_mut() {
+            if self.count == 0 {
+                self.refill()?;
+                if self.count == 0 {
+                    break;
                 }
             }
-            let val = (self.current >> self.index) & 0x01;
-            self.index += 1;
-            out[bits_read as usize] = val;
+            *bit = self.peek(1) as u8;
+            self.consume(1);
             bits_read += 1;
         }
         Ok(bits_read)
     }
+
+    /// Buffers at least 56 bits, unless the source ends before
+    pub fn refill(&mut self) -> io::Result<()> {
+        // Bytes are loaded 8 at once, and the ones that don't fit entirely are loaded
+        // again by the next refill, at the same position
+        if let Some(word) = self.source.buffer().first_chunk::<8>() {
+            let bytes = (63 - self.count) / 8;
+            self.bits |= u64::from_le_bytes(*word) << self.count;
+            self.count += 8 * bytes;
+            self.source.consume(bytes as usize);
+            return Ok(());
+        }
+
+        while self.count < 56 {
+            let buf = match self.source.fill_buf() {
+                Ok(buf) => buf,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            if buf.is_empty() {
+                break;
+            }
+            let n = buf.len().min(((64 - self.count) / 8) as usize);
+            for &byte in &buf[..n] {
+                self.bits |= (byte as u64) << self.count;
+                self.count += 8;
+            }
+            self.source.consume(n);
+        }
+        Ok(())
+    }
+
+    /// Reads `count` bits, at most 56, the first one being the lowest. Fails if the
+    /// source ends before.
+    #[inline]
+    pub fn read_bits(&mut self, count: u32) -> io::Result<u64> {
+        if self.count < count {
+            self.refill()?;
+            if self.count < count {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "compressed data ends early",
+                ));
+            }
+        }
+        let bits = self.peek(count);
+        self.consume(count);
+        Ok(bits)
+    }
+
+    /// Skips the bits left in the current byte
+    pub fn align_to_byte(&mut self) {
+        self.consume(self.count % 8);
+    }
+
+    /// Reads whole bytes, after the bits are aligned to a byte
+    pub fn read_aligned(&mut self, out: &mut [u8]) -> io::Result<()> {
+        let mut filled = 0;
+        while filled < out.len() && self.count >= 8 {
+            out[filled] = self.bits as u8;
+            self.consume(8);
+            filled += 1;
+        }
+        if filled < out.len() {
+            // The bytes past the buffered bits may be in `bits` already, and would be
+            // loaded again over the ones read here
+            self.bits = 0;
+            self.source.read_exact(&mut out[filled..])?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the source ends, ignoring the bits left in the current byte
+    pub fn at_end(&mut self) -> io::Result<bool> {
+        self.refill()?;
+        Ok(self.count < 8)
+    }
+
+    /// Number of bits that can be peeked without reading from the source
+    pub fn available(&self) -> u32 {
+        self.count
+    }
+
+    /// Returns the next `count` bits without consuming them, the first one being the
+    /// lowest. Bits past the available ones may have any value. `count` is less than 64.
+    pub fn peek(&self, count: u32) -> u64 {
+        self.bits & ((1 << count) - 1)
+    }
+
+    /// Consumes `count` bits, at most the available ones
+    pub fn consume(&mut self, count: u32) {
+        self.bits >>= count;
+        self.count -= count;
+    }
 }
 
+/// Writes bits from the least significant bit of each byte, buffered in a 64-bit integer
 pub struct BitWriter<W: Write> {
-    sink: BufWriter<W>,
-    current: u8,
-    index: usize,
+    sink: W,
+    /// Bytes not written to the sink yet
+    buffer: Vec<u8>,
+    /// Bits not added to the buffer yet, the first one being the lowest
+    bits: u64,
+    /// Number of valid bits in `bits`
+    count: u32,
 }
 
 impl<W: Write> BitWriter<W> {
     pub fn new(sink: W) -> Self {
         BitWriter {
-            sink: BufWriter::new(sink),
-            current: 0,
-            index: 0,
+            sink,
+            buffer: Vec::new(),
+            bits: 0,
+            count: 0,
         }
     }
 
+    /// Writes bits given one per byte
+    #[cfg(test)]
     pub fn write(&mut self, bits: &[u8]) -> io::Result<usize> {
-        for bit in bits {
-            if self.index > 7 {
-                self.sink.write(&[self.current])?;
-                self.current = 0;
-                self.index = 0;
-            }
-            self.current = self.current | ((bit & 0x01) << self.index);
-            self.index += 1;
+        for &bit in bits {
+            self.bits |= ((bit & 0x01) as u64) << self.count;
+            self.count += 1;
+            self.write_bytes()?;
         }
         Ok(bits.len())
     }
 
-    pub fn flush(&mut self) -> io::Result<()> {
-        if self.index > 0 {
-            self.sink.write(&[self.current])?;
-            return self.sink.flush();
+    /// Writes the `count` lowest bits of `bits`, from the lowest, `count` being at most
+    /// 32. Codes are given with their bits reversed.
+    #[inline]
+    pub fn write_bits(&mut self, bits: u64, count: u32) -> io::Result<()> {
+        self.bits |= bits << self.count;
+        self.count += count;
+        if self.count >= 32 {
+            self.write_bytes()?;
+        }
+        Ok(())
+    }
+
+    /// Pads the current byte with zeros
+    pub fn align_to_byte(&mut self) -> io::Result<()> {
+        self.count = self.count.next_multiple_of(8);
+        self.write_bytes()
+    }
+
+    /// Writes whole bytes, after the bits are aligned to a byte
+    pub fn write_aligned(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.buffer.extend_from_slice(bytes);
+        if self.buffer.len() >= BUFFER_SIZE {
+            self.sink.write_all(&self.buffer)?;
+            self.buffer.clear();
         }
         Ok(())
     }
+
+    /// Adds the bytes whose bits are all known to the buffer, and writes it if it is full
+    fn write_bytes(&mut self) -> io::Result<()> {
+        let bytes = self.count / 8;
+        self.buffer
+            .extend_from_slice(&self.bits.to_le_bytes()[..bytes as usize]);
+        self.bits = self.bits.checked_shr(8 * bytes).unwrap_or(0);
+        self.count -= 8 * bytes;
+        if self.buffer.len() >= BUFFER_SIZE {
+            self.sink.write_all(&self.buffer)?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered bits, the last byte being padded with zeros
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_bytes()?;
+        if self.count > 0 {
+            self.buffer.push(self.bits as u8);
+            self.bits = 0;
+            self.count = 0;
+        }
+        self.sink.write_all(&self.buffer)?;
+        self.buffer.clear();
+        self.sink.flush()
+    }
 }
 
 #[cfg(test)]
@@ -197,4 +341,71 @@ mod tests {
 
         assert_eq!(output, input);
     }
+
+    #[test]
+    fn write_and_read_many_bits() {
+        // Codes of 1 to 32 bits, across the 64 bits of the buffers
+        let codes: Vec<(u64, u32)> = (0..500u64)
+            .map(|i| {
+                let length = i as u32 % 32 + 1;
+                ((i * 0x2F1_2F1) & ((1 << length) - 1), length)
+            })
+            .collect();
+        let mut buffer = Vec::new();
+        {
+            let mut writer = BitWriter::new(&mut buffer);
+            for &(bits, length) in &codes {
+                writer.write_bits(bits, length).unwrap();
+            }
+            writer.flush().unwrap();
+        }
+        let total: u32 = codes.iter().map(|&(_, length)| length).sum();
+        assert_eq!(buffer.len() as u32, total.div_ceil(8));
+
+        let mut reader = BitReader::new(&buffer[..]);
+        for &(bits, length) in &codes {
+            assert_eq!(reader.read_bits(length).unwrap(), bits);
+        }
+        // Only the padding of the last byte is left
+        reader.refill().unwrap();
+        assert!(reader.available() < 8);
+        assert_eq!(reader.peek(reader.available()), 0);
+        let err = reader.read_bits(8).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn write_and_read_aligned_bytes() {
+        let mut buffer = Vec::new();
+        {
+            let mut writer = BitWriter::new(&mut buffer);
+            writer.write_bits(0b101, 3).unwrap();
+            writer.align_to_byte().unwrap();
+            writer.write_aligned(b"abc").unwrap();
+            writer.write_bits(0b1, 1).unwrap();
+            writer.flush().unwrap();
+        }
+        assert_eq!(buffer, [0b101, b'a', b'b', b'c', 0b1]);
+
+        let mut reader = BitReader::new(&buffer[..]);
+        assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+        reader.align_to_byte();
+        let mut bytes = [0; 3];
+        reader.read_aligned(&mut bytes).unwrap();
+        assert_eq!(&bytes, b"abc");
+        assert!(!reader.at_end().unwrap());
+        assert_eq!(reader.read_bits(1).unwrap(), 1);
+        assert!(reader.at_end().unwrap());
+        let err = reader.read_aligned(&mut bytes).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+
+        // More bytes than the buffered bits, then bits again
+        let bytes: Vec<u8> = (0..20).collect();
+        let mut reader = BitReader::new(&bytes[..]);
+        assert_eq!(reader.read_bits(8).unwrap(), 0);
+        let mut aligned = [0; 10];
+        reader.read_aligned(&mut aligned).unwrap();
+        assert_eq!(aligned[..], bytes[1..11]);
+        assert_eq!(reader.read_bits(16).unwrap(), 0x0C0B);
+    }
 }
@@ -0,0 +1,448 @@
+use std::io::{self, Read};
+
+use crate::{bitmanipulation::BitReader, tree::HuffmanTree};
+
+/// Longest code, so that each length fits in a nibble of the header
+pub(crate) const MAX_CODE_LENGTH: usize = 15;
+
+/// Canonical Huffman code of an alphabet of symbols numbered from 0.
+///
+/// The code is derived from the code length of each symbol only: symbols are sorted by
+/// length then by value, and each code is the previous one plus one, shifted left when
+/// the length grows, as in DEFLATE. Encoder and decoder build the same code from the
+/// lengths stored in the header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HuffmanCode {
+    lengths: Vec<u8>,
+    codes: Vec<u16>,
+}
+
+/// Bits looked up in the first decoding table
+const PRIMARY_BITS: u32 = 11;
+
+/// Flag of the entries linking to a table of longer codes
+const LINK: u32 = 1 << 31;
+
+/// Symbols decoded in pairs, the byte values of the literals
+const PAIRED_SYMBOLS: u32 = 256;
+
+/// Tables giving the symbol of the code at the start of a sequence of bits, so that a
+/// code is decoded with one or two lookups.
+///
+/// The bits are read from the lowest, so the index of a code in a table is the code with
+/// its bits reversed, followed by any bits. The entries of the first table also give the
+/// symbol of a second code when both codes fit in its bits and both symbols are byte
+/// values, as the codes of the literals of text are short.
+///
+/// Codes longer than `PRIMARY_BITS` share their first bits with other long codes: the
+/// entry of these bits in the first table links to a table of the other bits of these
+/// codes, which follows the first table in `entries`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DecodingTable {
+    /// Length of the longest code
+    longest: u32,
+    /// Bits looked up in the first table
+    primary: u32,
+    /// Symbols of the codes in bits 0 to 8 and 9 to 17, length of the first code in bits
+    /// 18 to 21, or 0 for the bits that start no code, and length of both codes from bit
+    /// 22, or 0 if there is only one. Links have the `LINK` flag, the offset of their
+    /// table in the two low bytes and its number of bits in bits 18 to 21.
+    entries: Vec<u32>,
+}
+
+/// Entry of a decoding table, for the codes at the start of some bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Entry(u32);
+
+impl HuffmanCode {
+    /// Builds the code of the symbols with the given number of occurrences, with codes of
+    /// at most `MAX_CODE_LENGTH` bits
+    pub fn from_counts(counts: &[usize]) -> HuffmanCode {
+        Self::from_counts_limited(counts, MAX_CODE_LENGTH)
+    }
+
+    /// Builds the code of the symbols with the given number of occurrences, with codes of
+    /// at most `max_length` bits, enough for all the symbols
+    pub fn from_counts_limited(counts: &[usize], max_length: usize) -> HuffmanCode {
+        let symbols: Vec<(u16, usize)> = counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(symbol, &count)| (symbol as u16, count))
+            .collect();
+        let lengths = match HuffmanTree::create(&symbols) {
+            Some(tree) => limit_lengths(&tree.lengths(counts.len()), max_length),
+            None => vec![0; counts.len()],
+        };
+        Self::from_lengths(&lengths).expect("limited code lengths are valid")
+    }
+
+    /// Builds the code from the length of the code of each symbol, 0 for the symbols
+    /// without a code. Fails if the lengths don't form a prefix code.
+    pub fn from_lengths(lengths: &[u8]) -> io::Result<HuffmanCode> {
+        let mut length_counts = [0; MAX_CODE_LENGTH + 1];
+        for &length in lengths.iter().filter(|&&length| length > 0) {
+            if length as usize > MAX_CODE_LENGTH {
+                return Err(invalid_data("code length too long"));
+            }
+            length_counts[length as usize] += 1;
+        }
+
+        // Each code of length n uses 2^-n of the code space
+        let mut available: usize = 1;
+        for &count in &length_counts[1..] {
+            available <<= 1;
+            available = available
+                .checked_sub(count)
+                .ok_or_else(|| invalid_data("over-subscribed code lengths"))?;
+        }
+
+        let mut symbols: Vec<usize> = (0..lengths.len()).filter(|&s| lengths[s] > 0).collect();
+        // The sort is stable, so symbols with the same length stay sorted by value
+        symbols.sort_by_key(|&s| lengths[s]);
+
+        let mut codes = vec![0; lengths.len()];
+        let mut code: u16 = 0;
+        let mut length = 0;
+        for &symbol in &symbols {
+            code <<= lengths[symbol] - length;
+            length = lengths[symbol];
+            codes[symbol] = code;
+            code += 1;
+        }
+
+        Ok(HuffmanCode {
+            lengths: lengths.to_vec(),
+            codes,
+        })
+    }
+
+    pub fn lengths(&self) -> &[u8] {
+        &self.lengths
+    }
+
+    /// Returns the code of a symbol and its length, or None if the symbol has no code
+    pub fn encode(&self, symbol: u16) -> Option<(u16, u8)> {
+        match self.lengths.get(symbol as usize) {
+            None | Some(0) => None,
+            Some(&length) => Some((self.codes[symbol as usize], length)),
+        }
+    }
+
+    pub fn decoding_table(&self) -> DecodingTable {
+        let longest = self.lengths.iter().copied().max().unwrap_or(0) as u32;
+        let primary = longest.min(PRIMARY_BITS);
+        let primary_mask = (1 << primary) - 1;
+        let codes: Vec<(u32, u32, u32)> = (0..self.lengths.len() as u16)
+            .filter_map(|symbol| {
+                let (code, length) = self.encode(symbol)?;
+                Some((symbol as u32, reverse(code, length) as u32, length as u32))
+            })
+            .collect();
+
+        // The table of the long codes starting with some bits is as large as needed
+        // for the longest of them
+        let mut entries = vec![0; 1 << primary];
+        for &(_, code, length) in codes.iter().filter(|&&(_, _, length)| length > primary) {
+            let link = &mut entries[(code & primary_mask) as usize];
+            *link = LINK | (length - primary).max((*link >> 18) & 0xF) << 18;
+        }
+        let mut offset = entries.len() as u32;
+        for link in entries.iter_mut().filter(|&&mut link| link & LINK != 0) {
+            *link |= offset;
+            offset += 1 << ((*link >> 18) & 0xF);
+        }
+        entries.resize(offset as usize, 0);
+
+        for (symbol, code, length) in codes {
+            let entry = length << 18 | symbol;
+            // Every sequence of bits starting with the code
+            let (start, code, length, bits) = if length <= primary {
+                (0, code, length, primary)
+            } else {
+                let link = entries[(code & primary_mask) as usize];
+                let start = (link & 0xFFFF) as usize;
+                (start, code >> primary, length - primary, (link >> 18) & 0xF)
+            };
+            for suffix in 0..1 << (bits - length) {
+                entries[start + (suffix << length | code) as usize] = entry;
+            }
+        }
+
+        // The bits after a short code may contain the whole next code
+        for index in 0..1 << primary {
+            let entry = entries[index];
+            let first = (entry >> 18) & 0xF;
+            if entry & LINK != 0 || first == 0 || first == primary {
+                continue;
+            }
+            let next = entries[index >> first];
+            let second = (next >> 18) & 0xF;
+            let paired = (entry & 0x1FF) < PAIRED_SYMBOLS && (next & 0x1FF) < PAIRED_SYMBOLS;
+            if next & LINK == 0 && second != 0 && first + second <= primary && paired {
+                entries[index] |= (first + second) << 22 | (next & 0x1FF) << 9;
+            }
+        }
+
+        DecodingTable {
+            longest,
+            primary,
+            entries,
+        }
+    }
+}
+
+impl DecodingTable {
+    /// Returns the entry of the next code without consuming it, refilling the reader if
+    /// needed. Fails if the bits start no code or the source ends within the code.
+    #[inline]
+    pub fn peek<R: Read>(&self, reader: &mut BitReader<R>) -> io::Result<Entry> {
+        if reader.available() < self.longest {
+            reader.refill()?;
+        }
+        let entry = self.lookup(reader.peek(self.longest));
+        if entry.length() == 0 {
+            return Err(invalid_data("invalid Huffman code"));
+        }
+        if entry.length() > reader.available() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "compressed data ends early",
+            ));
+        }
+        Ok(entry)
+    }
+
+    /// Reads a code, the bits of which are from the most significant, and returns its
+    /// symbol
+    #[inline]
+    pub fn read_symbol<R: Read>(&self, reader: &mut BitReader<R>) -> io::Result<u16> {
+        let entry = self.peek(reader)?;
+        reader.consume(entry.length());
+        Ok(entry.symbol())
+    }
+
+    /// Returns the entry of the codes at the start of `bits`
+    #[inline]
+    fn lookup(&self, bits: u64) -> Entry {
+        let entry = self.entries[(bits & ((1 << self.primary) - 1)) as usize];
+        if entry & LINK == 0 {
+            return Entry(entry);
+        }
+        let table_bits = (entry >> 18) & 0xF;
+        let start = (entry & 0xFFFF) as usize;
+        Entry(self.entries[start + ((bits >> self.primary) & ((1 << table_bits) - 1)) as usize])
+    }
+}
+
+impl Entry {
+    /// Symbol of the first code
+    #[inline]
+    pub fn symbol(self) -> u16 {
+        (self.0 & 0x1FF) as u16
+    }
+
+    /// Symbol of the second code, if the entry has two
+    #[inline]
+    pub fn second_symbol(self) -> u16 {
+        ((self.0 >> 9) & 0x1FF) as u16
+    }
+
+    /// Length of the first code
+    #[inline]
+    pub fn length(self) -> u32 {
+        (self.0 >> 18) & 0xF
+    }
+
+    /// Length of both codes, 0 if the entry has only one
+    #[inline]
+    pub fn pair_length(self) -> u32 {
+        self.0 >> 22
+    }
+}
+
+/// Shortens the codes longer than `limit`, keeping a complete prefix code.
+///
+/// Like the procedure of JPEG (ITU T.81, Annex K.3), two leaves at the deepest level are
+/// replaced by their parent, and one of them becomes a sibling of a shallower leaf, which
+/// moves one level down. The lengths are then given again to the symbols in the order of
+/// their original lengths, so that shorter codes stay with more frequent symbols.
+fn limit_lengths(lengths: &[usize], limit: usize) -> Vec<u8> {
+    let max_length = lengths.iter().copied().max().unwrap_or(0);
+    let mut length_counts = vec![0usize; max_length.max(limit) + 1];
+    for &length in lengths.iter().filter(|&&length| length > 0) {
+        length_counts[length] += 1;
+    }
+
+    for length in (limit + 1..=max_length).rev() {
+        while length_counts[length] > 0 {
+            let mut shallower = length - 2;
+            while length_counts[shallower] == 0 {
+                shallower -= 1;
+            }
+            length_counts[length] -= 2;
+            length_counts[length - 1] += 1;
+            length_counts[shallower + 1] += 2;
+            length_counts[shallower] -= 1;
+        }
+    }
+
+    let mut symbols: Vec<usize> = (0..lengths.len()).filter(|&s| lengths[s] > 0).collect();
+    symbols.sort_by_key(|&s| lengths[s]);
+    let mut limited = vec![0; lengths.len()];
+    let mut symbols = symbols.into_iter();
+    for (length, &count) in length_counts.iter().enumerate().skip(1) {
+        for symbol in symbols.by_ref().take(count) {
+            limited[symbol] = length as u8;
+        }
+    }
+    limited
+}
+
+/// Reverses the order of the `length` lowest bits of a code, so that it can be written
+/// from the lowest bit
+pub(crate) fn reverse(code: u16, length: u8) -> u16 {
+    code.reverse_bits() >> (16 - length)
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{
+        bitmanipulation::{BitReader, BitWriter},
+        code::{HuffmanCode, MAX_CODE_LENGTH},
+    };
+
+    fn kraft_sum(code: &HuffmanCode) -> usize {
+        code.lengths()
+            .iter()
+            .filter(|&&length| length > 0)
+            .map(|&length| 1 << (MAX_CODE_LENGTH - length as usize))
+            .sum()
+    }
+
+    fn byte_counts(text: &[u8]) -> Vec<usize> {
+        let mut counts = vec![0; 256];
+        for &byte in text {
+            counts[byte as usize] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_canonical_codes() {
+        let code = HuffmanCode::from_counts(&byte_counts(b"abacba"));
+        assert_eq!(code.encode(b'a' as u16), Some((0b0, 1)));
+        assert_eq!(code.encode(b'b' as u16), Some((0b10, 2)));
+        assert_eq!(code.encode(b'c' as u16), Some((0b11, 2)));
+        assert_eq!(code.encode(b'd' as u16), None);
+        assert_eq!(code.encode(256), None);
+
+        // Only the lengths are needed to get the same code
+        assert_eq!(HuffmanCode::from_lengths(code.lengths()).unwrap(), code);
+    }
+
+    #[test]
+    fn test_invalid_lengths() {
+        let mut lengths = [0; 256];
+        lengths[..3].copy_from_slice(&[1, 1, 1]);
+        assert!(HuffmanCode::from_lengths(&lengths).is_err());
+        lengths[..3].copy_from_slice(&[1, 16, 0]);
+        assert!(HuffmanCode::from_lengths(&lengths).is_err());
+    }
+
+    #[test]
+    fn test_lengths_are_limited() {
+        // Fibonacci counts give the deepest trees
+        let mut counts = vec![1, 1];
+        for symbol in 2..40 {
+            counts.push(counts[symbol - 1] + counts[symbol - 2]);
+        }
+        let code = HuffmanCode::from_counts(&counts);
+        let lengths = code.lengths();
+        assert_eq!(lengths.iter().copied().max(), Some(MAX_CODE_LENGTH as u8));
+        assert_eq!(kraft_sum(&code), 1 << MAX_CODE_LENGTH);
+        // More frequent symbols don't get longer codes
+        assert!(lengths.windows(2).all(|pair| pair[0] >= pair[1]));
+
+        let code = HuffmanCode::from_counts_limited(&counts[..19], 7);
+        assert_eq!(code.lengths().iter().copied().max(), Some(7));
+        assert_eq!(kraft_sum(&code), 1 << MAX_CODE_LENGTH);
+    }
+
+    /// Writes the codes of the symbols bit by bit, then decodes them with the table, one
+    /// at a time and in pairs
+    fn round_trip(code: &HuffmanCode, symbols: &[u16]) {
+        let mut encoded = Vec::new();
+        {
+            let mut writer = BitWriter::new(&mut encoded);
+            for &symbol in symbols {
+                let (bits, length) = code.encode(symbol).unwrap();
+                let bits: Vec<u8> = (0..length).rev().map(|i| (bits >> i) as u8 & 1).collect();
+                writer.write(&bits).unwrap();
+            }
+            writer.flush().unwrap();
+        }
+        let table = code.decoding_table();
+        let mut reader = BitReader::new(&encoded[..]);
+        let decoded: Vec<u16> = symbols
+            .iter()
+            .map(|_| table.read_symbol(&mut reader).unwrap())
+            .collect();
+        assert_eq!(decoded, symbols);
+
+        let mut reader = BitReader::new(&encoded[..]);
+        let mut decoded = Vec::new();
+        while decoded.len() < symbols.len() {
+            let entry = table.peek(&mut reader).unwrap();
+            decoded.push(entry.symbol());
+            if entry.pair_length() != 0 && decoded.len() < symbols.len() {
+                assert!(entry.symbol() < 256 && entry.second_symbol() < 256);
+                decoded.push(entry.second_symbol());
+                reader.consume(entry.pair_length());
+            } else {
+                reader.consume(entry.length());
+            }
+        }
+        assert_eq!(decoded, symbols);
+    }
+
+    #[test]
+    fn test_all_symbols() {
+        // The literals and lengths of DEFLATE, with symbols of 9 bits
+        let counts: Vec<usize> = (0..286).map(|s| s + 1).collect();
+        let code = HuffmanCode::from_counts(&counts);
+        assert_eq!(kraft_sum(&code), 1 << MAX_CODE_LENGTH);
+        round_trip(&code, &(0..286).collect::<Vec<u16>>());
+        round_trip(&code, &(0..286).rev().collect::<Vec<u16>>());
+    }
+
+    #[test]
+    fn test_long_codes() {
+        // Lengths from 1 to 15 bits, decoded with the tables of long codes
+        let mut counts: Vec<usize> = (0..15).map(|s| 1 << (15 - s)).collect();
+        counts.extend([1; 6]);
+        let code = HuffmanCode::from_counts(&counts);
+        assert_eq!(code.lengths()[0], 1);
+        assert_eq!(code.lengths()[20], MAX_CODE_LENGTH as u8);
+        let symbols: Vec<u16> = (0..=20).rev().chain(0..=20).collect();
+        round_trip(&code, &symbols);
+
+        // Bits that start no code of an incomplete code
+        let code = HuffmanCode::from_lengths(&[1, 12, 12]).unwrap();
+        round_trip(&code, &[2, 0, 1]);
+        let mut reader = BitReader::new(&[0xFF, 0xFF][..]);
+        let err = code.decoding_table().read_symbol(&mut reader).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // A code cut by the end of the data
+        let mut reader = BitReader::new(&[0x01][..]);
+        let err = code.decoding_table().read_symbol(&mut reader).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
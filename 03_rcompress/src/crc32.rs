@@ -0,0 +1,77 @@
+/// Reversed polynomial of the CRC-32 of ISO 3309 and ITU-T V.42, used by gzip
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+/// CRC of each byte value
+const TABLE: [u32; 256] = table();
+
+const fn table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = byte as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[byte] = crc;
+        byte += 1;
+    }
+    table
+}
+
+/// CRC-32 of data given in parts, as in the trailer of gzip (RFC 1952, section 8)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Crc32 {
+    /// CRC of the data so far, with its bits inverted
+    state: u32,
+}
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        Crc32 { state: !0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        let mut state = self.state;
+        for &byte in data {
+            state = TABLE[((state ^ byte as u32) & 0xFF) as usize] ^ (state >> 8);
+        }
+        self.state = state;
+    }
+
+    pub fn value(&self) -> u32 {
+        !self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crc32::Crc32;
+
+    fn crc32(data: &[u8]) -> u32 {
+        let mut crc = Crc32::new();
+        crc.update(data);
+        crc.value()
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(
+            crc32(b"The quick brown fox jumps over the lazy dog"),
+            0x414F_A339
+        );
+
+        // The data can be given in parts
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.value(), 0xCBF4_3926);
+    }
+}
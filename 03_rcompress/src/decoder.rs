@@ -1,83 +1,196 @@
-use std::io::{Read, Result, Write};
+use std::io::{Read, Result};
 
-use crate::{bitmanipulation::BitReader, header::Header, tree::HuffmanTree};
+use crate::{
+    bitmanipulation::BitReader,
+    code::{invalid_data, DecodingTable, HuffmanCode},
+    header::Header,
+    lz77::{distance_base, length_base, END_OF_BLOCK},
+};
 
 pub(crate) struct HuffmanDecoder {
-    tree: HuffmanTree,
-    filesize: usize,
+    literal_lengths: DecodingTable,
+    distances: DecodingTable,
 }
 
 impl HuffmanDecoder {
-    pub fn new(header: &Header) -> HuffmanDecoder {
-        HuffmanDecoder {
-            tree: HuffmanTree::create(&header.counts),
-            filesize: header.filesize,
+    pub fn new(header: &Header) -> Result<HuffmanDecoder> {
+        Self::from_lengths(&header.literal_lengths, &header.distance_lengths)
+    }
+
+    /// Builds the decoder of the codes with the given lengths of the literals and lengths,
+    /// and of the distances
+    pub fn from_lengths(literal_lengths: &[u8], distances: &[u8]) -> Result<HuffmanDecoder> {
+        Ok(HuffmanDecoder {
+            literal_lengths: HuffmanCode::from_lengths(literal_lengths)?.decoding_table(),
+            distances: HuffmanCode::from_lengths(distances)?.decoding_table(),
+        })
+    }
+
+    /// Decodes a compressed block of `size` bytes, adding them to `out`, which ends with
+    /// the bytes of the previous blocks that the matches can copy
+    pub fn decode(&self, block: &[u8], size: usize, out: &mut Vec<u8>) -> Result<()> {
+        let end = out.len() + size;
+        let mut reader = BitReader::new(block);
+        // Decoding stops after the size of the block is passed
+        let ended = self.decode_tokens(&mut reader, out, end + 1)?;
+        if !ended || out.len() != end {
+            return Err(invalid_data("block size does not match its data"));
         }
+        Ok(())
     }
 
-    pub fn decode<R: Read, W: Write>(&self, source: &mut R, writer: &mut W) -> Result<()> {
-        let mut reader = BitReader::new(source);
-
-        let mut buf = vec![0];
-        let mut out_buf = vec![0];
-        let mut bytes_written = 0;
-        let mut current = &self.tree;
-
-        loop {
-            match reader.read(&mut buf) {
-                Ok(0) => break,
-                Err(_) => break,
-                Ok(_) => {
-                    let bit = buf[0];
-
-                    // Move on the tree based on bit value
-                    match current {
-                        HuffmanTree::Node(_, left, right) => {
-                            if bit == 0 {
-                                current = left;
-                            } else {
-                                current = right;
-                            }
-                        }
-                        HuffmanTree::Leaf(_, _) => current = &self.tree,
-                    }
-
-                    // Write to output buffer if we catched a value
-                    if let HuffmanTree::Leaf(_, value) = current {
-                        current = &self.tree;
-                        out_buf[0] = *value;
-                        writer.write(&out_buf)?;
-                        bytes_written += 1;
-                        if bytes_written >= self.filesize {
-                            break;
-                        }
-                    }
-                }
+    /// Decodes tokens until the end of the block, adding their bytes to `out`, and
+    /// returns true. Returns false if `out` reaches `pause` bytes before, at most
+    /// `MAX_MATCH` bytes more, so that decoding can resume after some are taken out.
+    pub fn decode_tokens<R: Read>(
+        &self,
+        reader: &mut BitReader<R>,
+        out: &mut Vec<u8>,
+        pause: usize,
+    ) -> Result<bool> {
+        while out.len() < pause {
+            let entry = self.literal_lengths.peek(reader)?;
+            // Two literals at once, the most common case in text
+            let pair_length = entry.pair_length();
+            if pair_length != 0 && pair_length <= reader.available() {
+                out.push(entry.symbol() as u8);
+                out.push(entry.second_symbol() as u8);
+                reader.consume(pair_length);
+                continue;
+            }
+            reader.consume(entry.length());
+
+            let symbol = entry.symbol();
+            if symbol < END_OF_BLOCK {
+                out.push(symbol as u8);
+                continue;
+            }
+            if symbol == END_OF_BLOCK {
+                return Ok(true);
             }
+
+            let (base, extra_bits) =
+                length_base(symbol).ok_or_else(|| invalid_data("invalid length symbol"))?;
+            let length = base + reader.read_bits(extra_bits)? as usize;
+            let symbol = self.distances.read_symbol(reader)?;
+            let (base, extra_bits) =
+                distance_base(symbol).ok_or_else(|| invalid_data("invalid distance symbol"))?;
+            let distance = base + reader.read_bits(extra_bits)? as usize;
+
+            if distance > out.len() {
+                return Err(invalid_data("match distance too far back"));
+            }
+            copy_match(out, length, distance);
         }
-        writer.flush()?;
-        Ok(())
+        Ok(false)
+    }
+}
+
+/// Appends the `length` bytes starting `distance` bytes before the end of `out`, which
+/// repeat when the match overlaps the bytes it adds
+fn copy_match(out: &mut Vec<u8>, length: usize, distance: usize) {
+    let from = out.len() - distance;
+    let mut remaining = length;
+    // The bytes from `from` repeat every `distance` bytes, so each copy of what is
+    // already there doubles the bytes that can be copied at once
+    while remaining > 0 {
+        let n = remaining.min(out.len() - from);
+        out.extend_from_within(from..from + n);
+        remaining -= n;
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{count_frequencies, decoder::HuffmanDecoder, header::Header};
+    use std::io;
+
+    use crate::{
+        code::HuffmanCode, count_frequencies, decoder::HuffmanDecoder, encoder::HuffmanEncoder,
+        header::Header, lz77::Token,
+    };
+
+    /// Returns the header and compressed bytes of the tokens of a block of `size` bytes
+    fn encode(tokens: &[Token], size: u32) -> (Header, Vec<u8>) {
+        let (literal_lengths, distances) = count_frequencies(tokens);
+        let literal_lengths = HuffmanCode::from_counts(&literal_lengths);
+        let distances = HuffmanCode::from_counts(&distances);
+        let mut encoded = Vec::new();
+        HuffmanEncoder::new(&literal_lengths, &distances)
+            .encode(tokens, &mut encoded)
+            .unwrap();
+        let header = Header {
+            size,
+            compressed_size: encoded.len() as u32,
+            literal_lengths: literal_lengths.lengths().to_vec(),
+            distance_lengths: distances.lengths().to_vec(),
+        };
+        (header, encoded)
+    }
+
+    fn decode(tokens: &[Token], size: u32, history: &[u8]) -> io::Result<Vec<u8>> {
+        let (header, encoded) = encode(tokens, size);
+        let mut out = history.to_vec();
+        HuffmanDecoder::new(&header)?.decode(&encoded, size as usize, &mut out)?;
+        Ok(out)
+    }
 
     #[test]
     fn test_decode() {
-        let mut buf = "abacba".as_bytes();
-        let header = Header {
-            counts: count_frequencies(&mut buf),
-            filesize: 3,
+        let tokens = [
+            Token::Literal(b'a'),
+            Token::Literal(b'b'),
+            Token::Match {
+                length: 4,
+                distance: 2,
+            },
+            Token::Literal(b'c'),
+        ];
+        assert_eq!(decode(&tokens, 7, b"").unwrap(), b"abababc");
+
+        // Matches copy the end of the previous blocks
+        let tokens = [
+            Token::Match {
+                length: 3,
+                distance: 3,
+            },
+            Token::Literal(b'a'),
+        ];
+        assert_eq!(decode(&tokens, 4, b"xyz").unwrap(), b"xyzxyza");
+        let long = Token::Match {
+            length: 258,
+            distance: 1,
         };
-        let decoder = HuffmanDecoder::new(&header);
+        assert_eq!(decode(&[long], 258, b"x").unwrap(), [b'x'; 259]);
+    }
 
-        let input: [u8; 1] = [0b00001110];
-        let mut output: Vec<u8> = Vec::new();
+    #[test]
+    fn test_decode_invalid() {
+        let tokens = [
+            Token::Literal(b'a'),
+            Token::Match {
+                length: 3,
+                distance: 2,
+            },
+        ];
+        let err = decode(&tokens, 4, b"").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(decode(&tokens, 4, b"x").unwrap(), b"xaxax");
 
-        decoder.decode(&mut input.as_ref(), &mut output).unwrap();
+        // The size of the block is checked
+        for size in [3, 5] {
+            let err = decode(&tokens, size, b"x").unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", size);
+        }
+    }
 
-        assert_eq!("abc", std::str::from_utf8(&output).unwrap());
+    #[test]
+    fn test_decode_truncated() {
+        let tokens: Vec<Token> = b"hello, world".iter().map(|&b| Token::Literal(b)).collect();
+        let (header, encoded) = encode(&tokens, 12);
+        let decoder = HuffmanDecoder::new(&header).unwrap();
+        let err = decoder
+            .decode(&encoded[..encoded.len() - 2], 12, &mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
     }
 }
@@ -0,0 +1,494 @@
+use std::io::{Read, Result, Write};
+
+use crate::{
+    bitmanipulation::{BitReader, BitWriter},
+    code::{invalid_data, reverse, HuffmanCode},
+    count_frequencies,
+    crc32::Crc32,
+    decoder::HuffmanDecoder,
+    encoder::HuffmanEncoder,
+    keep_history,
+    lz77::{
+        distance_base, length_base, Token, DISTANCE_SYMBOLS, END_OF_BLOCK, LITERAL_LENGTH_SYMBOLS,
+    },
+    BLOCK_SIZE, HISTORY_SIZE,
+};
+
+/// Types of block, after the bit of the last block
+const STORED: u64 = 0;
+const FIXED: u64 = 1;
+const DYNAMIC: u64 = 2;
+
+/// Largest stored block, whose size is written with 16 bits
+const MAX_STORED: usize = 65535;
+
+/// Symbols of the code of the code lengths: the lengths from 0 to 15, then the repeats
+/// of the previous length, of a few zeros and of many zeros
+const CODE_LENGTH_SYMBOLS: usize = 19;
+
+/// Longest code of the code lengths, whose lengths are written with 3 bits
+const MAX_CODE_LENGTH_LENGTH: usize = 7;
+
+/// Order of the lengths of the code of the code lengths, the least used ones last
+const CODE_LENGTH_ORDER: [usize; CODE_LENGTH_SYMBOLS] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Lengths of the fixed code of the literals and lengths, with 2 symbols never used
+fn fixed_literal_lengths() -> Vec<u8> {
+    let mut lengths = vec![8; 288];
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths
+}
+
+/// Lengths of the fixed code of the distances, with 2 symbols never used
+fn fixed_distance_lengths() -> Vec<u8> {
+    vec![5; 32]
+}
+
+/// Writes the tokens of `block` as DEFLATE blocks (RFC 1951): stored, with the fixed
+/// codes, or with codes of their own, whichever is the shortest
+pub(crate) fn write_block<W: Write>(
+    writer: &mut BitWriter<W>,
+    block: &[u8],
+    tokens: &[Token],
+    last: bool,
+) -> Result<()> {
+    let (literal_counts, distance_counts) = count_frequencies(tokens);
+    let literal_lengths = HuffmanCode::from_counts(&at_least_two(literal_counts.clone()));
+    let distances = HuffmanCode::from_counts(&at_least_two(distance_counts.clone()));
+    let header = DynamicHeader::new(literal_lengths.lengths(), distances.lengths());
+    let dynamic_bits = header.bits()
+        + data_bits(&literal_counts, literal_lengths.lengths())
+        + data_bits(&distance_counts, distances.lengths());
+
+    let fixed_literal_lengths = HuffmanCode::from_lengths(&fixed_literal_lengths())?;
+    let fixed_distances = HuffmanCode::from_lengths(&fixed_distance_lengths())?;
+    let fixed_bits = data_bits(&literal_counts, fixed_literal_lengths.lengths())
+        + data_bits(&distance_counts, fixed_distances.lengths());
+
+    // Each stored block is aligned to a byte, and starts with its size and its complement
+    let stored_bits = block.len().div_ceil(MAX_STORED).max(1) * (7 + 32) + 8 * block.len();
+
+    if stored_bits <= fixed_bits.min(dynamic_bits) {
+        return write_stored(writer, block, last);
+    }
+    if fixed_bits <= dynamic_bits {
+        writer.write_bits(last as u64 | FIXED << 1, 3)?;
+        HuffmanEncoder::new(&fixed_literal_lengths, &fixed_distances).write_tokens(tokens, writer)
+    } else {
+        writer.write_bits(last as u64 | DYNAMIC << 1, 3)?;
+        header.write(writer)?;
+        HuffmanEncoder::new(&literal_lengths, &distances).write_tokens(tokens, writer)
+    }
+}
+
+fn write_stored<W: Write>(writer: &mut BitWriter<W>, block: &[u8], last: bool) -> Result<()> {
+    let count = block.len().div_ceil(MAX_STORED).max(1);
+    for index in 0..count {
+        let part = &block[index * MAX_STORED..block.len().min((index + 1) * MAX_STORED)];
+        let last = last && index == count - 1;
+        writer.write_bits(last as u64 | STORED << 1, 3)?;
+        writer.align_to_byte()?;
+        let length = part.len() as u16;
+        writer.write_aligned(&length.to_le_bytes())?;
+        writer.write_aligned(&(!length).to_le_bytes())?;
+        writer.write_aligned(part)?;
+    }
+    Ok(())
+}
+
+/// Gives a code to unused symbols until two symbols have one, as decoders reject codes of
+/// a single symbol
+fn at_least_two(mut counts: Vec<usize>) -> Vec<usize> {
+    for symbol in 0..2 {
+        if counts.iter().filter(|&&count| count > 0).count() < 2 && counts[symbol] == 0 {
+            counts[symbol] = 1;
+        }
+    }
+    counts
+}
+
+/// Bits of the codes of the symbols and of their extra bits. The symbols from 257 are
+/// lengths when there are more than `DISTANCE_SYMBOLS`, otherwise they are distances.
+fn data_bits(counts: &[usize], lengths: &[u8]) -> usize {
+    counts
+        .iter()
+        .enumerate()
+        .map(|(symbol, &count)| {
+            let extra_bits = if counts.len() > DISTANCE_SYMBOLS {
+                length_base(symbol as u16).map_or(0, |(_, bits)| bits)
+            } else {
+                distance_base(symbol as u16).map_or(0, |(_, bits)| bits)
+            };
+            count * (lengths[symbol] as usize + extra_bits as usize)
+        })
+        .sum()
+}
+
+/// Number of extra bits of a symbol of the code lengths
+fn repeat_extra_bits(symbol: u8) -> u32 {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0,
+    }
+}
+
+/// Code lengths of a block with codes of its own, which are written with a code too
+struct DynamicHeader {
+    /// Number of lengths of the literals and lengths written
+    literals: usize,
+    /// Number of lengths of the distances written
+    distances: usize,
+    /// Symbols of the code lengths, and the value of their extra bits
+    symbols: Vec<(u8, u8)>,
+    code: HuffmanCode,
+    /// Number of lengths of `code` written
+    code_lengths: usize,
+}
+
+impl DynamicHeader {
+    fn new(literal_lengths: &[u8], distance_lengths: &[u8]) -> DynamicHeader {
+        let used = |lengths: &[u8]| lengths.iter().rposition(|&length| length > 0);
+        let literals = used(literal_lengths).map_or(0, |last| last + 1).max(257);
+        let distances = used(distance_lengths).map_or(0, |last| last + 1).max(1);
+        let lengths = [&literal_lengths[..literals], &distance_lengths[..distances]].concat();
+
+        let symbols = run_lengths(&lengths);
+        let mut counts = vec![0; CODE_LENGTH_SYMBOLS];
+        for &(symbol, _) in &symbols {
+            counts[symbol as usize] += 1;
+        }
+        let code = HuffmanCode::from_counts_limited(&at_least_two(counts), MAX_CODE_LENGTH_LENGTH);
+        let code_lengths = CODE_LENGTH_ORDER
+            .iter()
+            .rposition(|&symbol| code.lengths()[symbol] > 0)
+            .map_or(0, |last| last + 1)
+            .max(4);
+
+        DynamicHeader {
+            literals,
+            distances,
+            symbols,
+            code,
+            code_lengths,
+        }
+    }
+
+    /// Bits of the header, with the type of the block
+    fn bits(&self) -> usize {
+        let symbols: usize = self
+            .symbols
+            .iter()
+            .map(|&(symbol, _)| {
+                (self.code.lengths()[symbol as usize] as u32 + repeat_extra_bits(symbol)) as usize
+            })
+            .sum();
+        3 + 5 + 5 + 4 + 3 * self.code_lengths + symbols
+    }
+
+    fn write<W: Write>(&self, writer: &mut BitWriter<W>) -> Result<()> {
+        writer.write_bits(self.literals as u64 - 257, 5)?;
+        writer.write_bits(self.distances as u64 - 1, 5)?;
+        writer.write_bits(self.code_lengths as u64 - 4, 4)?;
+        for &symbol in &CODE_LENGTH_ORDER[..self.code_lengths] {
+            writer.write_bits(self.code.lengths()[symbol] as u64, 3)?;
+        }
+        for &(symbol, extra) in &self.symbols {
+            let (code, length) = self
+                .code
+                .encode(symbol as u16)
+                .expect("used code length symbols have a code");
+            writer.write_bits(reverse(code, length) as u64, length as u32)?;
+            writer.write_bits(extra as u64, repeat_extra_bits(symbol))?;
+        }
+        Ok(())
+    }
+}
+
+/// Replaces the runs of code lengths by repeats: symbol 16 repeats the previous length 3
+/// to 6 times, 17 gives 3 to 10 zeros and 18 gives 11 to 138 zeros
+fn run_lengths(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut symbols = Vec::new();
+    let mut start = 0;
+    while start < lengths.len() {
+        let length = lengths[start];
+        let run = lengths[start..]
+            .iter()
+            .take_while(|&&other| other == length)
+            .count();
+        let mut left = run;
+        if length == 0 {
+            while left >= 11 {
+                let count = left.min(138);
+                symbols.push((18, (count - 11) as u8));
+                left -= count;
+            }
+            if left >= 3 {
+                symbols.push((17, (left - 3) as u8));
+                left = 0;
+            }
+        } else {
+            symbols.push((length, 0));
+            left -= 1;
+            while left >= 3 {
+                let count = left.min(6);
+                symbols.push((16, (count - 3) as u8));
+                left -= count;
+            }
+        }
+        symbols.extend(std::iter::repeat_n((length, 0), left));
+        start += run;
+    }
+    symbols
+}
+
+/// Reads the code lengths of a block with codes of its own
+fn read_dynamic_header<R: Read>(reader: &mut BitReader<R>) -> Result<HuffmanDecoder> {
+    let literals = reader.read_bits(5)? as usize + 257;
+    let distances = reader.read_bits(5)? as usize + 1;
+    let code_lengths = reader.read_bits(4)? as usize + 4;
+    if literals > LITERAL_LENGTH_SYMBOLS || distances > DISTANCE_SYMBOLS {
+        return Err(invalid_data("too many length or distance symbols"));
+    }
+
+    let mut code = [0; CODE_LENGTH_SYMBOLS];
+    for &symbol in &CODE_LENGTH_ORDER[..code_lengths] {
+        code[symbol] = reader.read_bits(3)? as u8;
+    }
+    let table = HuffmanCode::from_lengths(&code)?.decoding_table();
+
+    let mut lengths: Vec<u8> = Vec::with_capacity(literals + distances);
+    while lengths.len() < literals + distances {
+        let (length, count) = match table.read_symbol(reader)? {
+            16 => {
+                let previous = lengths
+                    .last()
+                    .ok_or_else(|| invalid_data("repeat of no code length"))?;
+                (*previous, 3 + reader.read_bits(2)?)
+            }
+            17 => (0, 3 + reader.read_bits(3)?),
+            18 => (0, 11 + reader.read_bits(7)?),
+            length => (length as u8, 1),
+        };
+        if lengths.len() + count as usize > literals + distances {
+            return Err(invalid_data("too many code lengths"));
+        }
+        lengths.extend(std::iter::repeat_n(length, count as usize));
+    }
+    if lengths[END_OF_BLOCK as usize] == 0 {
+        return Err(invalid_data("no code for the end of block"));
+    }
+
+    HuffmanDecoder::from_lengths(&lengths[..literals], &lengths[literals..])
+}
+
+/// Bytes decoded: the end of the ones written, which matches can copy, then the ones not
+/// written yet
+struct Window<'a, W: Write> {
+    data: Vec<u8>,
+    written: usize,
+    sink: &'a mut W,
+    crc: Crc32,
+    size: u64,
+}
+
+impl<W: Write> Window<'_, W> {
+    /// Size of the data from which the bytes not written are written
+    const FULL: usize = HISTORY_SIZE + BLOCK_SIZE;
+
+    fn decode<R: Read>(
+        &mut self,
+        reader: &mut BitReader<R>,
+        decoder: &HuffmanDecoder,
+    ) -> Result<()> {
+        while !decoder.decode_tokens(reader, &mut self.data, Self::FULL)? {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn read_stored<R: Read>(&mut self, reader: &mut BitReader<R>) -> Result<()> {
+        reader.align_to_byte();
+        let length = reader.read_bits(16)? as u16;
+        if reader.read_bits(16)? as u16 != !length {
+            return Err(invalid_data("invalid length of stored block"));
+        }
+        if self.data.len() >= Self::FULL {
+            self.flush()?;
+        }
+        let start = self.data.len();
+        self.data.resize(start + length as usize, 0);
+        reader.read_aligned(&mut self.data[start..])
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let new = &self.data[self.written..];
+        self.sink.write_all(new)?;
+        self.crc.update(new);
+        self.size += new.len() as u64;
+        keep_history(&mut self.data);
+        self.written = self.data.len();
+        Ok(())
+    }
+}
+
+/// Decodes DEFLATE blocks until the last one, writing their bytes to `output`. Returns
+/// the CRC-32 and the size of the bytes.
+pub(crate) fn inflate<R: Read, W: Write>(
+    reader: &mut BitReader<R>,
+    output: &mut W,
+) -> Result<(u32, u64)> {
+    let mut window = Window {
+        data: Vec::with_capacity(Window::<W>::FULL + MAX_STORED),
+        written: 0,
+        sink: output,
+        crc: Crc32::new(),
+        size: 0,
+    };
+
+    loop {
+        let last = reader.read_bits(1)? == 1;
+        match reader.read_bits(2)? {
+            STORED => window.read_stored(reader)?,
+            FIXED => {
+                let decoder = HuffmanDecoder::from_lengths(
+                    &fixed_literal_lengths(),
+                    &fixed_distance_lengths(),
+                )?;
+                window.decode(reader, &decoder)?;
+            }
+            DYNAMIC => {
+                let decoder = read_dynamic_header(reader)?;
+                window.decode(reader, &decoder)?;
+            }
+            _ => return Err(invalid_data("invalid block type")),
+        }
+        if last {
+            break;
+        }
+    }
+    window.flush()?;
+
+    Ok((window.crc.value(), window.size))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{
+        bitmanipulation::{BitReader, BitWriter},
+        deflate::{inflate, run_lengths, write_block},
+        lz77::MatchFinder,
+    };
+
+    /// Compresses `content` as DEFLATE blocks of `block_size` bytes, and checks that it is
+    /// decompressed back. Returns the compressed bytes.
+    fn round_trip(content: &[u8], block_size: usize) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        {
+            let mut writer = BitWriter::new(&mut compressed);
+            let mut finder = MatchFinder::new(6, 15);
+            let mut start = 0;
+            loop {
+                let end = content.len().min(start + block_size);
+                let mut tokens = Vec::new();
+                finder.tokens(&content[..end], start, &mut tokens);
+                let last = end == content.len();
+                write_block(&mut writer, &content[start..end], &tokens, last).unwrap();
+                if last {
+                    break;
+                }
+                start = end;
+            }
+            writer.flush().unwrap();
+        }
+
+        let mut decompressed = Vec::new();
+        let mut reader = BitReader::new(&compressed[..]);
+        let (_, size) = inflate(&mut reader, &mut decompressed).unwrap();
+        assert_eq!(decompressed, content);
+        assert_eq!(size, content.len() as u64);
+        compressed
+    }
+
+    /// Bytes below `range` that do not repeat much
+    fn random(size: usize, range: u32) -> Vec<u8> {
+        let mut state: u32 = 1;
+        (0..size)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                ((state >> 16) % range) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_block_types() {
+        // Only the end of block, with the fixed code
+        assert_eq!(round_trip(b"", 100), [0x03, 0x00]);
+        assert_eq!(round_trip(b"a", 100)[0] & 0x07, 0b011);
+
+        // Few different bytes, coded shorter with codes of their own
+        assert_eq!(round_trip(&random(10_000, 4), 1000)[0] & 0x07, 0b100);
+        round_trip("hello, I'm testing: ünïcödé\n".repeat(100).as_bytes(), 1000);
+
+        // Bytes that do not repeat are stored, in blocks of at most 65535 bytes
+        let random = random(150_000, 256);
+        let compressed = round_trip(&random, 140_000);
+        assert_eq!(compressed[0] & 0x07, 0b000);
+        assert_eq!(compressed.len(), random.len() + 4 * 5);
+    }
+
+    #[test]
+    fn test_run_lengths() {
+        let mut lengths = vec![3, 3, 3, 3, 3, 3, 3, 3, 0, 0, 5, 5, 5];
+        lengths.extend([0; 150]);
+        lengths.extend([0; 5]);
+        lengths.push(1);
+        assert_eq!(
+            run_lengths(&lengths),
+            [
+                (3, 0),
+                (16, 3),
+                (3, 0),
+                (0, 0),
+                (0, 0),
+                (5, 0),
+                (5, 0),
+                (5, 0),
+                (18, 127),
+                (18, 6),
+                (1, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_blocks() {
+        let invalid = [
+            // Block type 3
+            &[0x07][..],
+            // Stored block whose length is not followed by its complement
+            &[0x01, 0x05, 0x00, 0xFA, 0xFE],
+            // Codes of 287 literals and lengths
+            &[0xF5, 0x00, 0x00],
+            // Fixed block with the distance symbol 30
+            &[0x03, 0x3E],
+        ];
+        for data in invalid {
+            let mut reader = BitReader::new(data);
+            let err = inflate(&mut reader, &mut Vec::new()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{:x?}", data);
+        }
+
+        // Stored block shorter than its length
+        let mut reader = BitReader::new(&[0x01, 0x05, 0x00, 0xFA, 0xFF, b'a'][..]);
+        let err = inflate(&mut reader, &mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -1,34 +1,85 @@
-use std::io::{Read, Result, Write};
+use std::io::{self, Result, Write};
 
-use crate::{bitmanipulation::BitWriter, header::Header, tree::HuffmanTree};
+use crate::{
+    bitmanipulation::BitWriter,
+    code::{reverse, HuffmanCode},
+    lz77::{distance_symbol, length_symbol, Token, END_OF_BLOCK},
+};
 
 pub(crate) struct HuffmanEncoder {
-    tree: HuffmanTree,
+    /// Code of each symbol of the literals and lengths with its bits reversed, and its
+    /// length, 0 for the symbols without a code
+    literal_lengths: Vec<(u64, u32)>,
+    /// Code of each symbol of the distances, the same way
+    distances: Vec<(u64, u32)>,
 }
 
 impl HuffmanEncoder {
-    pub fn new(header: &Header) -> Self {
+    pub fn new(literal_lengths: &HuffmanCode, distances: &HuffmanCode) -> Self {
         HuffmanEncoder {
-            tree: HuffmanTree::create(&header.counts),
+            literal_lengths: reversed_codes(literal_lengths),
+            distances: reversed_codes(distances),
         }
     }
 
-    pub fn encode<R: Read, W: Write>(&self, source: &mut R, sink: &mut W) -> Result<()> {
+    /// Writes the codes of the tokens and of the end of the block, the last byte being
+    /// padded with zeros. Fails if a symbol has no code.
+    pub fn encode<W: Write>(&self, tokens: &[Token], sink: &mut W) -> Result<()> {
         let mut writer = BitWriter::new(sink);
-        let mut buf = vec![0];
-
-        loop {
-            match source.read(&mut buf) {
-                Ok(0) => break,
-                Err(_) => break,
-                Ok(_) => {
-                    let encoded = self.tree.encode(buf[0]).unwrap();
-                    writer.write(encoded.as_slice())?;
+        self.write_tokens(tokens, &mut writer)?;
+        writer.flush()
+    }
+
+    /// Writes the codes of the tokens and of the end of the block after the bits already
+    /// written. Fails if a symbol has no code.
+    pub fn write_tokens<W: Write>(
+        &self,
+        tokens: &[Token],
+        writer: &mut BitWriter<W>,
+    ) -> Result<()> {
+        let mut missing = false;
+
+        for &token in tokens {
+            match token {
+                Token::Literal(byte) => {
+                    let (code, length) = self.literal_lengths[byte as usize];
+                    missing |= length == 0;
+                    writer.write_bits(code, length)?;
+                }
+                Token::Match { length, distance } => {
+                    // Each code is followed by its extra bits, at most 28 bits in all
+                    let (symbol, extra_bits, extra) = length_symbol(length);
+                    let (code, length) = self.literal_lengths[symbol as usize];
+                    missing |= length == 0;
+                    writer.write_bits(code | (extra as u64) << length, length + extra_bits)?;
+
+                    let (symbol, extra_bits, extra) = distance_symbol(distance);
+                    let (code, length) = self.distances[symbol as usize];
+                    missing |= length == 0;
+                    writer.write_bits(code | (extra as u64) << length, length + extra_bits)?;
                 }
             }
         }
-        writer.flush()?;
+        let (code, length) = self.literal_lengths[END_OF_BLOCK as usize];
+        missing |= length == 0;
+        writer.write_bits(code, length)?;
 
+        // The tokens are not the ones the codes were built for if a symbol has no code
+        if missing {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "symbol without a code",
+            ));
+        }
         Ok(())
     }
 }
+
+fn reversed_codes(code: &HuffmanCode) -> Vec<(u64, u32)> {
+    (0..code.lengths().len() as u16)
+        .map(|symbol| match code.encode(symbol) {
+            Some((bits, length)) => (reverse(bits, length) as u64, length as u32),
+            None => (0, 0),
+        })
+        .collect()
+}
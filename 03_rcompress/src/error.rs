@@ -0,0 +1,96 @@
+use std::io;
+
+use thiserror::Error;
+
+/// Errors of the decompression. They are converted to and from `io::Error`, so that the
+/// ones found deep in the decoding are kept, and so that the callers using `io::Result`
+/// can still use `?`.
+#[derive(Error, Debug)]
+pub enum RcompressError {
+    #[error("not an rcompress file")]
+    NotRcompressFile,
+
+    #[error("unsupported format version {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("checksum of the {part} does not match: expected {expected:08x}, found {actual:08x}")]
+    ChecksumMismatch {
+        part: &'static str,
+        expected: u32,
+        actual: u32,
+    },
+
+    #[error("compressed data ends early")]
+    Truncated,
+
+    #[error("invalid compressed data: {0}")]
+    InvalidData(#[source] io::Error),
+
+    #[error(transparent)]
+    Io(io::Error),
+}
+
+impl From<io::Error> for RcompressError {
+    fn from(err: io::Error) -> Self {
+        if err
+            .get_ref()
+            .is_some_and(|inner| inner.is::<RcompressError>())
+        {
+            return *err.into_inner().unwrap().downcast().unwrap();
+        }
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => RcompressError::Truncated,
+            io::ErrorKind::InvalidData => RcompressError::InvalidData(err),
+            _ => RcompressError::Io(err),
+        }
+    }
+}
+
+impl From<RcompressError> for io::Error {
+    fn from(err: RcompressError) -> Self {
+        match err {
+            RcompressError::Io(err) | RcompressError::InvalidData(err) => err,
+            RcompressError::Truncated => io::Error::new(io::ErrorKind::UnexpectedEof, err),
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::RcompressError;
+
+    #[test]
+    fn test_io_conversions() {
+        let err = io::Error::from(RcompressError::UnsupportedVersion(2));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "unsupported format version 2");
+        assert!(matches!(
+            RcompressError::from(err),
+            RcompressError::UnsupportedVersion(2)
+        ));
+
+        let err = io::Error::from(RcompressError::Truncated);
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        let eof = io::Error::new(io::ErrorKind::UnexpectedEof, "end");
+        assert!(matches!(
+            RcompressError::from(eof),
+            RcompressError::Truncated
+        ));
+
+        let invalid = io::Error::new(io::ErrorKind::InvalidData, "invalid code lengths");
+        let err = RcompressError::from(invalid);
+        assert_eq!(
+            err.to_string(),
+            "invalid compressed data: invalid code lengths"
+        );
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::InvalidData);
+
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied, "denied");
+        let err = RcompressError::from(denied);
+        assert_eq!(err.to_string(), "denied");
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::PermissionDenied);
+    }
+}
@@ -0,0 +1,227 @@
+use std::io::{Read, Result, Write};
+
+use crate::{
+    bitmanipulation::{BitReader, BitWriter},
+    code::invalid_data,
+    crc32::Crc32,
+    deflate, keep_history,
+    lz77::MatchFinder,
+    read_block, Options, BLOCK_SIZE, HISTORY_SIZE,
+};
+
+/// First bytes of a gzip member
+const MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+/// Compression method of DEFLATE, the only one
+const DEFLATE: u8 = 8;
+
+/// Flags of the optional fields of the header. The first flag only says that the data is
+/// probably text.
+const FHCRC: u8 = 1 << 1;
+const FEXTRA: u8 = 1 << 2;
+const FNAME: u8 = 1 << 3;
+const FCOMMENT: u8 = 1 << 4;
+const RESERVED: u8 = 0xE0;
+
+/// Operating system of the header: unknown
+const OS_UNKNOWN: u8 = 255;
+
+/// Compresses the data read from `input` as one gzip member (RFC 1952), without a name or
+/// a modification time
+pub(crate) fn compress<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: Options,
+) -> Result<()> {
+    // Extra flags: 2 for the smallest output, 4 for the fastest compression
+    let extra_flags = match options.level {
+        9 => 2,
+        1 => 4,
+        _ => 0,
+    };
+    output.write_all(&MAGIC)?;
+    // No flags, and no modification time
+    output.write_all(&[DEFLATE, 0, 0, 0, 0, 0])?;
+    output.write_all(&[extra_flags, OS_UNKNOWN])?;
+
+    let mut finder = MatchFinder::new(options.level, options.window_bits);
+    // The end of the previous block, then the block
+    let mut data = Vec::with_capacity(HISTORY_SIZE + BLOCK_SIZE);
+    let mut tokens = Vec::new();
+    let mut crc = Crc32::new();
+    let mut size: u64 = 0;
+
+    let mut writer = BitWriter::new(&mut *output);
+    loop {
+        let start = data.len();
+        data.resize(start + BLOCK_SIZE, 0);
+        let read = read_block(input, &mut data[start..])?;
+        data.truncate(start + read);
+        crc.update(&data[start..]);
+        size += read as u64;
+
+        tokens.clear();
+        finder.tokens(&data, start, &mut tokens);
+        // Only the last block is short, and it can be empty
+        let last = read < BLOCK_SIZE;
+        deflate::write_block(&mut writer, &data[start..], &tokens, last)?;
+        if last {
+            break;
+        }
+        keep_history(&mut data);
+    }
+    writer.flush()?;
+
+    // The size is modulo 2^32
+    output.write_all(&crc.value().to_le_bytes())?;
+    output.write_all(&(size as u32).to_le_bytes())?;
+    output.flush()
+}
+
+/// Decompresses the gzip members read from `input`, writing their data to `output` one
+/// after the other
+pub(crate) fn decompress<R: Read, W: Write>(input: &mut R, output: &mut W) -> Result<()> {
+    let mut reader = BitReader::new(input);
+    loop {
+        read_header(&mut reader)?;
+        let (crc, size) = deflate::inflate(&mut reader, output)?;
+
+        reader.align_to_byte();
+        let mut trailer = [0; 8];
+        reader.read_aligned(&mut trailer)?;
+        if u32::from_le_bytes(trailer[..4].try_into().unwrap()) != crc {
+            return Err(invalid_data("CRC-32 does not match the data"));
+        }
+        if u32::from_le_bytes(trailer[4..].try_into().unwrap()) != size as u32 {
+            return Err(invalid_data("size does not match the data"));
+        }
+
+        if reader.at_end()? {
+            break;
+        }
+    }
+
+    output.flush()
+}
+
+/// Reads the header of a member, checking its CRC if it has one
+fn read_header<R: Read>(reader: &mut BitReader<R>) -> Result<()> {
+    let mut header = vec![0; 10];
+    reader.read_aligned(&mut header)?;
+    if header[..2] != MAGIC {
+        return Err(invalid_data("not in gzip format"));
+    }
+    if header[2] != DEFLATE {
+        return Err(invalid_data("unknown compression method"));
+    }
+    let flags = header[3];
+    if flags & RESERVED != 0 {
+        return Err(invalid_data("unknown header flags"));
+    }
+
+    if flags & FEXTRA != 0 {
+        let start = header.len();
+        header.resize(start + 2, 0);
+        reader.read_aligned(&mut header[start..])?;
+        let length = u16::from_le_bytes([header[start], header[start + 1]]) as usize;
+        header.resize(start + 2 + length, 0);
+        reader.read_aligned(&mut header[start + 2..])?;
+    }
+    // The file name, then the comment, each ended by a zero
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let mut byte = [1];
+            while byte[0] != 0 {
+                reader.read_aligned(&mut byte)?;
+                header.push(byte[0]);
+            }
+        }
+    }
+    if flags & FHCRC != 0 {
+        let mut expected = [0; 2];
+        reader.read_aligned(&mut expected)?;
+        let mut crc = Crc32::new();
+        crc.update(&header);
+        if crc.value() as u16 != u16::from_le_bytes(expected) {
+            return Err(invalid_data("CRC of the header does not match"));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::gzip::{compress, decompress};
+    use crate::Options;
+
+    fn compressed(content: &[u8]) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        compress(&mut &content[..], &mut compressed, Options::default()).unwrap();
+        compressed
+    }
+
+    fn decompressed(compressed: &[u8]) -> io::Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        decompress(&mut &compressed[..], &mut decompressed)?;
+        Ok(decompressed)
+    }
+
+    #[test]
+    fn test_empty() {
+        // Header, a fixed block with only the end of block, CRC and size
+        assert_eq!(
+            compressed(b""),
+            [0x1F, 0x8B, 8, 0, 0, 0, 0, 0, 0, 255, 0x03, 0x00, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(decompressed(&compressed(b"")).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_optional_fields() {
+        // All the optional fields, with the CRC of the header
+        let mut member = vec![0x1F, 0x8B, 8, 0x1E, 0, 0, 0, 0, 0, 3];
+        member.extend_from_slice(&[3, 0, b'a', b'b', b'c']);
+        member.extend_from_slice(b"name\0comment\0");
+        let mut crc = crate::crc32::Crc32::new();
+        crc.update(&member);
+        member.extend_from_slice(&(crc.value() as u16).to_le_bytes());
+        member.extend_from_slice(&compressed(b"data")[10..]);
+        assert_eq!(decompressed(&member).unwrap(), b"data");
+
+        member[12] = b'x';
+        let err = decompressed(&member).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_members() {
+        let mut members = compressed(b"hello, ");
+        members.extend(compressed(b"world"));
+        assert_eq!(decompressed(&members).unwrap(), b"hello, world");
+
+        // Anything else after a member is an error
+        members.extend_from_slice(b"\n");
+        let err = decompressed(&members).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        members.extend_from_slice(&[0; 10]);
+        let err = decompressed(&members).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_corrupted_trailer() {
+        let member = compressed(b"hello, world");
+        let end = member.len();
+        for position in [end - 8, end - 1] {
+            let mut corrupted = member.clone();
+            corrupted[position] ^= 1;
+            let err = decompressed(&corrupted).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", position);
+        }
+        let err = decompressed(&member[..end - 2]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -1,97 +1,251 @@
 use std::io::{self, Read, Write};
 
+use crate::{
+    crc32::Crc32,
+    lz77::{DISTANCE_SYMBOLS, LITERAL_LENGTH_SYMBOLS},
+    read_block, RcompressError,
+};
+
+/// First bytes of an rcompress stream, the first one not being text
+pub const MAGIC: [u8; 4] = [0x89, b'R', b'C', b'Z'];
+
+/// Version of the format after the magic bytes, the only one read
+const VERSION: u8 = 1;
+
+/// Bytes of the code lengths, a nibble per symbol
+const PACKED_LENGTHS: usize = (LITERAL_LENGTH_SYMBOLS + DISTANCE_SYMBOLS) / 2;
+
+/// Bytes of a header after the block size, without its checksum
+const HEADER_REST: usize = 4 + PACKED_LENGTHS;
+
+/// Writes the magic bytes and the version, which start the stream
+pub(crate) fn write_start<W: Write>(writer: &mut W) -> io::Result<()> {
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&[VERSION])
+}
+
+/// Reads the magic bytes and the version, failing if they are not the ones written
+pub(crate) fn read_start<R: Read>(reader: &mut R) -> io::Result<()> {
+    let mut start = [0; 5];
+    let read = read_block(reader, &mut start)?;
+    if read < MAGIC.len() || start[..4] != MAGIC {
+        return Err(RcompressError::NotRcompressFile.into());
+    }
+    if read < start.len() {
+        return Err(RcompressError::Truncated.into());
+    }
+    if start[4] != VERSION {
+        return Err(RcompressError::UnsupportedVersion(start[4]).into());
+    }
+    Ok(())
+}
+
+/// Reads the CRC-32 of the data, which follows the end of the stream
+pub(crate) fn read_checksum<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut checksum = [0; 4];
+    reader.read_exact(&mut checksum)?;
+    Ok(u32::from_le_bytes(checksum))
+}
+
+/// Header of a compressed block: the size of the block before and after compression,
+/// then the length of the code of each symbol of the literals and lengths, then of the
+/// distances, packed two per byte with the first one in the low nibble, then the CRC-32
+/// of these bytes. A block size of 0 ends the stream, followed only by the CRC-32 of the
+/// data.
 #[derive(Debug, PartialEq, Eq)]
 pub(crate) struct Header {
-    pub counts: Vec<(u8, usize)>,
-    pub filesize: usize,
+    pub size: u32,
+    pub compressed_size: u32,
+    pub literal_lengths: Vec<u8>,
+    pub distance_lengths: Vec<u8>,
 }
 
 impl Header {
     pub(crate) fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
-        let size_bytes = self.filesize.to_le_bytes();
-        writer.write(&size_bytes)?;
-
-        writer.write(&[self.counts.len() as u8])?;
-        for (c, count) in self.counts.iter() {
-            writer.write(&[*c as u8])?;
-            let count_bytes = (*count as u32).to_le_bytes();
-            writer.write(&count_bytes)?;
-        }
-        Ok(())
+        let mut bytes = Vec::with_capacity(4 + HEADER_REST);
+        bytes.extend_from_slice(&self.size.to_le_bytes());
+        bytes.extend_from_slice(&self.compressed_size.to_le_bytes());
+
+        let lengths: Vec<u8> = [&self.literal_lengths[..], &self.distance_lengths].concat();
+        bytes.extend(
+            lengths
+                .chunks(2)
+                .map(|pair| (pair[0] & 0x0F) | (pair[1] << 4)),
+        );
+
+        let mut crc = Crc32::new();
+        crc.update(&bytes);
+        writer.write_all(&bytes)?;
+        writer.write_all(&crc.value().to_le_bytes())
+    }
+
+    /// Writes the marker of the end of the stream, then the CRC-32 of the data
+    pub(crate) fn write_end<W: Write>(writer: &mut W, checksum: u32) -> io::Result<()> {
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(&checksum.to_le_bytes())
     }
 
-    pub(crate) fn read<R: Read>(reader: &mut R) -> io::Result<Header> {
-        let mut counts: Vec<(u8, usize)> = Vec::new();
-
-        // Read file size
-        let mut filesizebuf: [u8; 8] = [0; 8];
-        reader.read(&mut filesizebuf)?;
-        let filesize = usize::from_le_bytes(filesizebuf);
-
-        // Read array size
-        let mut sizebuf: [u8; 1] = [0];
-        reader.read(&mut sizebuf)?;
-        let size = sizebuf[0];
-
-        let mut buf = [0; 5];
-        for _ in 0..size {
-            // Read symbol and count
-            reader.read(&mut buf)?;
-            let symbol = buf[0];
-            let count = u32::from_le_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize;
-            counts.push((symbol, count));
+    /// Reads the header of the next block, `None` at the end of the stream
+    pub(crate) fn read<R: Read>(reader: &mut R) -> io::Result<Option<Header>> {
+        let mut bytes = [0; 4 + HEADER_REST];
+        reader.read_exact(&mut bytes[..4])?;
+        let size = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+        if size == 0 {
+            return Ok(None);
+        }
+        reader.read_exact(&mut bytes[4..])?;
+
+        // Nothing is trusted before the checksum is
+        let expected = read_checksum(reader)?;
+        let mut crc = Crc32::new();
+        crc.update(&bytes);
+        if crc.value() != expected {
+            return Err(RcompressError::ChecksumMismatch {
+                part: "block header",
+                expected,
+                actual: crc.value(),
+            }
+            .into());
         }
 
-        Ok(Self { filesize, counts })
+        let compressed_size = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        let mut lengths: Vec<u8> = bytes[8..]
+            .iter()
+            .flat_map(|byte| [byte & 0x0F, byte >> 4])
+            .collect();
+        let distance_lengths = lengths.split_off(LITERAL_LENGTH_SYMBOLS);
+
+        Ok(Some(Self {
+            size,
+            compressed_size,
+            literal_lengths: lengths,
+            distance_lengths,
+        }))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::BufReader;
+    use std::io::{self, BufReader};
+
+    use crate::{
+        crc32::Crc32,
+        header::{read_start, write_start, Header},
+        lz77::{DISTANCE_SYMBOLS, LITERAL_LENGTH_SYMBOLS},
+        RcompressError,
+    };
+
+    /// Appends the CRC-32 of the bytes
+    fn with_checksum(mut bytes: Vec<u8>) -> Vec<u8> {
+        let mut crc = Crc32::new();
+        crc.update(&bytes);
+        bytes.extend_from_slice(&crc.value().to_le_bytes());
+        bytes
+    }
 
-    use crate::{count_frequencies, header::Header};
+    fn lengths(symbols: usize, pairs: &[(usize, u8)]) -> Vec<u8> {
+        let mut lengths = vec![0; symbols];
+        for &(symbol, length) in pairs {
+            lengths[symbol] = length;
+        }
+        lengths
+    }
 
     #[test]
     fn test_encode_header() {
-        let mut buf = "abacba".as_bytes();
         let header = Header {
-            counts: count_frequencies(&mut buf),
-            filesize: 10,
+            size: 10,
+            compressed_size: 3,
+            literal_lengths: lengths(
+                LITERAL_LENGTH_SYMBOLS,
+                &[(0x61, 1), (0x62, 2), (0x63, 2), (285, 15)],
+            ),
+            distance_lengths: lengths(DISTANCE_SYMBOLS, &[(0, 3), (29, 4)]),
         };
 
         let mut bytes = Vec::new();
 
         header.write(&mut bytes).unwrap();
 
-        assert_eq!(
-            bytes,
-            vec![
-                0x0A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // File Size
-                0x03, // Number of frequencies
-                b'a', 0x03, 0x00, 0x00, 0x00, b'b', 0x02, 0x00, 0x00, 0x00, b'c', 0x01, 0x00, 0x00,
-                0x00
-            ]
-        )
+        let mut expected = vec![0x0A, 0x00, 0x00, 0x00]; // Block size
+        expected.extend_from_slice(&[0x03, 0x00, 0x00, 0x00]); // Compressed size
+        expected.extend_from_slice(&[0; 158]); // Code lengths
+        expected[8 + 0x30] = 0x10; // 'a' = 0x61
+        expected[8 + 0x31] = 0x22; // 'b' = 0x62, 'c' = 0x63
+        expected[8 + 142] = 0xF0; // Length symbol 285
+        expected[8 + 143] = 0x03; // Distance symbol 0
+        expected[8 + 157] = 0x40; // Distance symbol 29
+        assert_eq!(bytes, with_checksum(expected));
+
+        let mut end = Vec::new();
+        Header::write_end(&mut end, 0x0403_0201).unwrap();
+        assert_eq!(end, [0, 0, 0, 0, 1, 2, 3, 4]);
     }
 
     #[test]
     fn test_decode_header() {
-        let encoded = vec![
-            0x75, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // File size
-            0x03, // Number of frequencies
-            b'a', 0x03, 0x00, 0x00, 0x00, b'b', 0x02, 0x00, 0x00, 0x00, b'c', 0x01, 0x00, 0x00,
-            0x00,
-        ];
+        let mut encoded = vec![0x75, 0x0A, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00]; // Sizes
+        encoded.extend_from_slice(&[0; 158]);
+        encoded[8 + 0x30] = 0x10;
+        encoded[8 + 0x31] = 0x22;
+        encoded[8 + 143] = 0x21;
+        let mut encoded = with_checksum(encoded);
 
         let mut reader = BufReader::new(&encoded[..]);
-        let counts = Header::read(&mut reader).unwrap();
+        let header = Header::read(&mut reader).unwrap();
         assert_eq!(
-            counts,
-            Header {
-                counts: vec![(b'a', 3), (b'b', 2), (b'c', 1)],
-                filesize: 2677
-            }
+            header,
+            Some(Header {
+                size: 2677,
+                compressed_size: 2,
+                literal_lengths: lengths(
+                    LITERAL_LENGTH_SYMBOLS,
+                    &[(0x61, 1), (0x62, 2), (0x63, 2)]
+                ),
+                distance_lengths: lengths(DISTANCE_SYMBOLS, &[(0, 1), (1, 2)]),
+            })
         );
+
+        // A block size of 0 ends the stream
+        assert_eq!(Header::read(&mut &[0u8; 4][..]).unwrap(), None);
+
+        // A truncated header is an error
+        let mut reader = BufReader::new(&encoded[..100]);
+        let err = Header::read(&mut reader).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        let err = Header::read(&mut &[][..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        // So is a corrupted one
+        encoded[8 + 0x31] = 0x21;
+        let err = Header::read(&mut &encoded[..]).unwrap_err();
+        assert!(matches!(
+            RcompressError::from(err),
+            RcompressError::ChecksumMismatch {
+                part: "block header",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_start() {
+        let mut start = Vec::new();
+        write_start(&mut start).unwrap();
+        assert_eq!(start, [0x89, b'R', b'C', b'Z', 1]);
+        read_start(&mut &start[..]).unwrap();
+
+        let check = |bytes: &[u8]| RcompressError::from(read_start(&mut &bytes[..]).unwrap_err());
+        assert!(matches!(check(b""), RcompressError::NotRcompressFile));
+        assert!(matches!(check(b"\x89RC"), RcompressError::NotRcompressFile));
+        assert!(matches!(
+            check(b"plain text"),
+            RcompressError::NotRcompressFile
+        ));
+        assert!(matches!(check(b"\x89RCZ"), RcompressError::Truncated));
+        assert!(matches!(
+            check(b"\x89RCZ\x02"),
+            RcompressError::UnsupportedVersion(2)
+        ));
     }
 }
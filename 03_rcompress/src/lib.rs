@@ -1,117 +1,461 @@
+use code::{invalid_data, HuffmanCode};
+use crc32::Crc32;
 use encoder::HuffmanEncoder;
+pub use error::RcompressError;
 use header::Header;
-use std::{collections::HashMap, fs::File, os::unix::prelude::MetadataExt};
-use std::{
-    fs,
-    io::{BufReader, BufWriter, Read, Result, Seek, SeekFrom},
+pub use header::MAGIC;
+use lz77::{
+    distance_symbol, length_symbol, MatchFinder, Token, DISTANCE_SYMBOLS, END_OF_BLOCK,
+    LITERAL_LENGTH_SYMBOLS, MAX_WINDOW_BITS,
 };
+use std::io::{self, Read, Result, Write};
 
 use crate::decoder::HuffmanDecoder;
 
 mod bitmanipulation;
+mod code;
+mod crc32;
 mod decoder;
+mod deflate;
 mod encoder;
+mod error;
+mod gzip;
 mod header;
+mod lz77;
 mod tree;
 
-fn count_frequencies<R: Read>(source: &mut R) -> Vec<(u8, usize)> {
-    let mut counts_map: HashMap<u8, usize> = HashMap::new();
-    let mut counts: Vec<(u8, usize)> = Vec::new();
+/// Size of the blocks compressed with their own code, the last one being shorter
+pub const BLOCK_SIZE: usize = 128 * 1024;
 
-    let mut buf = vec![0];
-    loop {
-        match source.read(&mut buf) {
-            Ok(0) => break,
-            Err(_) => break,
-            Ok(_) => {
-                let c = buf[0];
-                if !counts_map.contains_key(&c) {
-                    counts.push((c, 0));
-                    counts_map.insert(c, counts.len() - 1);
-                }
-                let idx = counts_map[&c];
-                let (_, count) = counts[idx];
-                counts[idx] = (c, count + 1);
+/// Bytes of the previous blocks kept for the matches, the largest window
+const HISTORY_SIZE: usize = 1 << MAX_WINDOW_BITS;
+
+/// Format of the compressed data
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Blocks with the code lengths in a header of their own, described in the README
+    #[default]
+    Rcompress,
+    /// gzip (RFC 1952) of DEFLATE blocks (RFC 1951), read by gzip, zlib and most tools
+    Gzip,
+}
+
+/// Settings of the compression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    /// From 1, the fastest, to 9, the smallest output
+    pub level: u32,
+    /// Largest distance of the repeated sequences, as a power of 2 from 8 to 15
+    pub window_bits: u32,
+    pub format: Format,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            level: 6,
+            window_bits: MAX_WINDOW_BITS,
+            format: Format::default(),
+        }
+    }
+}
+
+/// Counts the symbols of the literals and lengths, with one end of block, and of the
+/// distances of the tokens of a block
+fn count_frequencies(tokens: &[Token]) -> (Vec<usize>, Vec<usize>) {
+    let mut literal_lengths = vec![0; LITERAL_LENGTH_SYMBOLS];
+    let mut distances = vec![0; DISTANCE_SYMBOLS];
+    for &token in tokens {
+        match token {
+            Token::Literal(byte) => literal_lengths[byte as usize] += 1,
+            Token::Match { length, distance } => {
+                literal_lengths[length_symbol(length).0 as usize] += 1;
+                distances[distance_symbol(distance).0 as usize] += 1;
             }
         }
     }
+    literal_lengths[END_OF_BLOCK as usize] += 1;
+    (literal_lengths, distances)
+}
 
-    counts
+/// Fills `buf` from `source`, returning fewer bytes only at the end of the input
+fn read_block<R: Read>(source: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match source.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
 }
 
-pub fn compress(input_path: &str, output_path: &str) -> Result<()> {
-    // Read input
-    let filedata = fs::metadata(input_path)?;
-    let mut input_file = BufReader::new(File::open(input_path)?);
+/// Keeps the end of `data` that the matches of the next block can copy
+fn keep_history(data: &mut Vec<u8>) {
+    data.drain(..data.len().saturating_sub(HISTORY_SIZE));
+}
 
-    // Create header
-    let counts = count_frequencies(&mut input_file);
-    let header = Header {
-        counts: counts.clone(),
-        filesize: filedata.size() as usize,
-    };
+/// Compresses the data read from `input` with the default options, writing it to
+/// `output`
+pub fn compress<R: Read, W: Write>(input: &mut R, output: &mut W) -> Result<()> {
+    compress_with(input, output, Options::default())
+}
+
+/// Compresses the data read from `input`, writing it to `output`. The input is read
+/// once, a block at a time, so it can be a pipe.
+pub fn compress_with<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: Options,
+) -> Result<()> {
+    if !(1..=9).contains(&options.level) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "compression level not between 1 and 9",
+        ));
+    }
+    if !(8..=MAX_WINDOW_BITS).contains(&options.window_bits) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "window bits not between 8 and 15",
+        ));
+    }
+
+    match options.format {
+        Format::Rcompress => compress_blocks(input, output, options),
+        Format::Gzip => gzip::compress(input, output, options),
+    }
+}
+
+fn compress_blocks<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: Options,
+) -> Result<()> {
+    let mut finder = MatchFinder::new(options.level, options.window_bits);
+    // The end of the previous block, then the block
+    let mut data = Vec::with_capacity(HISTORY_SIZE + BLOCK_SIZE);
+    let mut tokens = Vec::new();
+    let mut encoded = Vec::new();
+    let mut crc = Crc32::new();
 
-    // Seek back to start, as the encoder needs to read the file again
-    input_file.seek(SeekFrom::Start(0));
+    header::write_start(output)?;
+    loop {
+        let start = data.len();
+        data.resize(start + BLOCK_SIZE, 0);
+        let size = read_block(input, &mut data[start..])?;
+        data.truncate(start + size);
+        crc.update(&data[start..]);
 
-    // Write output
-    let mut output_file = BufWriter::new(File::create(output_path)?);
-    header.write(&mut output_file)?;
+        if size > 0 {
+            tokens.clear();
+            finder.tokens(&data, start, &mut tokens);
+            let (literal_lengths, distances) = count_frequencies(&tokens);
+            let literal_lengths = HuffmanCode::from_counts(&literal_lengths);
+            let distances = HuffmanCode::from_counts(&distances);
+            encoded.clear();
+            HuffmanEncoder::new(&literal_lengths, &distances).encode(&tokens, &mut encoded)?;
 
-    let encoder = HuffmanEncoder::new(&header);
-    encoder.encode(&mut input_file, &mut output_file)?;
+            let header = Header {
+                size: size as u32,
+                compressed_size: encoded.len() as u32,
+                literal_lengths: literal_lengths.lengths().to_vec(),
+                distance_lengths: distances.lengths().to_vec(),
+            };
+            header.write(output)?;
+            output.write_all(&encoded)?;
+        }
+        // Only the last block is short: reading again would wait for more from a terminal
+        if size < BLOCK_SIZE {
+            break;
+        }
+        keep_history(&mut data);
+    }
+    Header::write_end(output, crc.value())?;
+
+    output.flush()
+}
+
+/// Decompresses the data read from `input`, writing it to `output` as each block is
+/// decoded
+pub fn decompress<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+) -> std::result::Result<(), RcompressError> {
+    decompress_with(input, output, Format::default())
+}
 
+/// Decompresses the data in `format` read from `input`, writing it to `output` as each
+/// block is decoded. The data was written when the checksums are found not to match, so
+/// an error means that the output must be discarded.
+pub fn decompress_with<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    format: Format,
+) -> std::result::Result<(), RcompressError> {
+    match format {
+        Format::Rcompress => decompress_blocks(input, output)?,
+        Format::Gzip => gzip::decompress(input, output)?,
+    }
     Ok(())
 }
 
-pub fn decompress(input_path: &str, output_path: &str) -> Result<()> {
-    let mut input_file = BufReader::new(File::open(input_path)?);
-    let mut output_file = BufWriter::new(File::create(output_path)?);
+fn decompress_blocks<R: Read, W: Write>(input: &mut R, output: &mut W) -> Result<()> {
+    // The end of the previous block, then the block
+    let mut data = Vec::with_capacity(HISTORY_SIZE + BLOCK_SIZE);
+    let mut encoded = Vec::new();
+    let mut crc = Crc32::new();
 
-    let header = Header::read(&mut input_file)?;
+    header::read_start(input)?;
+    while let Some(header) = Header::read(input)? {
+        let size = header.size as usize;
+        let compressed_size = header.compressed_size as usize;
+        if size > BLOCK_SIZE {
+            return Err(invalid_data("block too large"));
+        }
+        // The codes of a match of 3 bytes take at most 48 bits, of the end of block 15
+        if compressed_size > 2 * size + 2 {
+            return Err(invalid_data("compressed block too large"));
+        }
 
-    let decoder = HuffmanDecoder::new(&header);
-    decoder.decode(&mut input_file, &mut output_file)?;
+        encoded.resize(compressed_size, 0);
+        input.read_exact(&mut encoded)?;
+        let start = data.len();
+        let decoder = HuffmanDecoder::new(&header)?;
+        decoder.decode(&encoded, size, &mut data)?;
+        output.write_all(&data[start..])?;
+        crc.update(&data[start..]);
+        keep_history(&mut data);
+    }
+    let expected = header::read_checksum(input)?;
+    if crc.value() != expected {
+        return Err(RcompressError::ChecksumMismatch {
+            part: "data",
+            expected,
+            actual: crc.value(),
+        }
+        .into());
+    }
 
-    Ok(())
+    output.flush()
 }
 
 #[cfg(test)]
 mod tests {
 
+    use std::io::{self, Read};
+
     use crate::{
-        count_frequencies,
-        HuffmanTree::{self},
+        compress, compress_with, count_frequencies, decompress,
+        header::{self, Header},
+        lz77::{Token, DISTANCE_SYMBOLS, LITERAL_LENGTH_SYMBOLS},
+        Options, RcompressError, BLOCK_SIZE,
     };
 
-    fn test_frequencies(s: &str, expected: Vec<(u8, usize)>) {
-        let mut buf = s.as_bytes();
-        let counts = count_frequencies(&mut buf);
-        assert_eq!(counts, expected);
-    }
-
     #[test]
     fn test_count_frequencies() {
-        test_frequencies("aa", vec![(b'a', 2)]);
-        test_frequencies("abcdbca", vec![(b'a', 2), (b'b', 2), (b'c', 2), (b'd', 1)]);
-        test_frequencies(
-            "hello, I'm testing",
-            vec![
-                (b'h', 1),
-                (b'e', 2),
-                (b'l', 2),
-                (b'o', 1),
-                (b',', 1),
-                (b' ', 2),
-                (b'I', 1),
-                (b'\'', 1),
-                (b'm', 1),
-                (b't', 2),
-                (b's', 1),
-                (b'i', 1),
-                (b'n', 1),
-                (b'g', 1),
-            ],
+        let tokens = [
+            Token::Literal(b'a'),
+            Token::Literal(b'a'),
+            Token::Match {
+                length: 3,
+                distance: 1,
+            },
+            Token::Match {
+                length: 10,
+                distance: 300,
+            },
+        ];
+        let (literal_lengths, distances) = count_frequencies(&tokens);
+        let used = |counts: &[usize]| -> Vec<(usize, usize)> {
+            (0..counts.len())
+                .filter(|&s| counts[s] > 0)
+                .map(|s| (s, counts[s]))
+                .collect()
+        };
+        assert_eq!(
+            used(&literal_lengths),
+            [(b'a' as usize, 2), (256, 1), (257, 1), (264, 1)]
         );
+        assert_eq!(used(&distances), [(0, 1), (16, 1)]);
+        assert_eq!(literal_lengths.len(), 286);
+        assert_eq!(distances.len(), 30);
+    }
+
+    /// Compresses and decompresses `content`, returning the size of the compressed data
+    fn round_trip(content: &[u8]) -> usize {
+        let mut compressed = Vec::new();
+        compress(&mut &content[..], &mut compressed).unwrap();
+        let mut decompressed = Vec::new();
+        decompress(&mut &compressed[..], &mut decompressed).unwrap();
+        assert_eq!(decompressed, content);
+        compressed.len()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Magic bytes and version, end of stream marker and CRC-32
+        assert_eq!(round_trip(b""), 13);
+        // Then block sizes, 316 nibbles of code lengths, CRC-32 and one byte of codes
+        assert_eq!(round_trip(b"aaaa"), 184);
+        round_trip("hello, I'm testing: ünïcödé\n".repeat(100).as_bytes());
+    }
+
+    #[test]
+    fn test_round_trip_all_bytes() {
+        let content: Vec<u8> = (0..=255u8)
+            .flat_map(|b| std::iter::repeat_n(b, b as usize + 1))
+            .collect();
+        round_trip(&content);
+    }
+
+    /// Reads a few bytes at a time, like a pipe
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(1000);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_multiple_blocks() {
+        // Blocks with different bytes, the last one short
+        let content: Vec<u8> = (0..BLOCK_SIZE * 5 / 2)
+            .map(|i| b'a' + (i / BLOCK_SIZE) as u8 * 4 + (i % 7 % 4) as u8)
+            .collect();
+        let mut compressed = Vec::new();
+        compress(&mut Trickle(&content), &mut compressed).unwrap();
+        let mut decompressed = Vec::new();
+        decompress(&mut Trickle(&compressed), &mut decompressed).unwrap();
+        assert_eq!(decompressed, content);
+
+        // The whole blocks do not end the stream
+        round_trip(&content[..BLOCK_SIZE * 2]);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut compressed = Vec::new();
+        compress(&mut &b"hello, world"[..], &mut compressed).unwrap();
+        for end in [compressed.len() - 1, compressed.len() - 4, 100, 5] {
+            let err = decompress(&mut &compressed[..end], &mut Vec::new()).unwrap_err();
+            assert!(matches!(err, RcompressError::Truncated), "{}", end);
+        }
+    }
+
+    #[test]
+    fn test_not_rcompress() {
+        let err = decompress(&mut &b"plain text"[..], &mut Vec::new()).unwrap_err();
+        assert!(matches!(err, RcompressError::NotRcompressFile));
+
+        let mut compressed = Vec::new();
+        compress(&mut &b"hello, world"[..], &mut compressed).unwrap();
+        compressed[4] = 2;
+        let err = decompress(&mut &compressed[..], &mut Vec::new()).unwrap_err();
+        assert!(matches!(err, RcompressError::UnsupportedVersion(2)));
+    }
+
+    #[test]
+    fn test_checksums() {
+        let mut compressed = Vec::new();
+        compress(&mut &b"hello, world"[..], &mut compressed).unwrap();
+        for (position, part) in [(20, "block header"), (compressed.len() - 1, "data")] {
+            let mut corrupted = compressed.clone();
+            corrupted[position] ^= 0x01;
+            let err = decompress(&mut &corrupted[..], &mut Vec::new()).unwrap_err();
+            assert!(
+                matches!(err, RcompressError::ChecksumMismatch { part: p, .. } if p == part),
+                "{}",
+                err
+            );
+        }
+    }
+
+    /// Replaces the header of the first block of `compressed`, with a valid checksum
+    fn rewrite_header(compressed: &[u8], change: impl Fn(&mut Header)) -> Vec<u8> {
+        let mut rest = &compressed[5..];
+        let mut block = Header::read(&mut rest).unwrap().unwrap();
+        change(&mut block);
+        let mut rewritten = compressed[..5].to_vec();
+        block.write(&mut rewritten).unwrap();
+        rewritten.extend_from_slice(rest);
+        rewritten
+    }
+
+    #[test]
+    fn test_corrupted_lengths() {
+        // Three codes of one bit
+        let mut content = Vec::new();
+        header::write_start(&mut content).unwrap();
+        let mut literal_lengths = vec![0; LITERAL_LENGTH_SYMBOLS];
+        literal_lengths[..3].fill(1);
+        let block = Header {
+            size: 1,
+            compressed_size: 1,
+            literal_lengths,
+            distance_lengths: vec![0; DISTANCE_SYMBOLS],
+        };
+        block.write(&mut content).unwrap();
+        content.extend_from_slice(&[0; 9]);
+        let err = decompress(&mut &content[..], &mut Vec::new()).unwrap_err();
+        assert!(matches!(err, RcompressError::InvalidData(_)), "{}", err);
+    }
+
+    #[test]
+    fn test_corrupted_sizes() {
+        let mut compressed = Vec::new();
+        compress(&mut &b"hello, world"[..], &mut compressed).unwrap();
+        let large = rewrite_header(&compressed, |block| block.size = BLOCK_SIZE as u32 + 1);
+        let overlong = rewrite_header(&compressed, |block| block.compressed_size = 100);
+        for content in [large, overlong] {
+            let err = decompress(&mut &content[..], &mut Vec::new()).unwrap_err();
+            assert!(matches!(err, RcompressError::InvalidData(_)), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_levels() {
+        let content: Vec<u8> = (0..5_000u32)
+            .flat_map(|i| format!("line {} of {}\n", i % 100, i % 7).into_bytes())
+            .collect();
+        let mut sizes = Vec::new();
+        for level in 1..=9 {
+            for window_bits in [8, 15] {
+                let mut compressed = Vec::new();
+                let options = Options {
+                    level,
+                    window_bits,
+                    ..Options::default()
+                };
+                compress_with(&mut &content[..], &mut compressed, options).unwrap();
+                let mut decompressed = Vec::new();
+                decompress(&mut &compressed[..], &mut decompressed).unwrap();
+                assert_eq!(decompressed, content, "{:?}", options);
+                sizes.push(compressed.len());
+            }
+        }
+        // The repetitions are far apart, and the highest level finds the most
+        assert!(sizes[1] < sizes[0] && sizes[17] <= sizes[1], "{:?}", sizes);
+        assert!(sizes[17] < content.len() / 5, "{:?}", sizes);
+
+        for options in [
+            Options {
+                level: 0,
+                ..Options::default()
+            },
+            Options {
+                window_bits: 16,
+                ..Options::default()
+            },
+        ] {
+            let err = compress_with(&mut &content[..], &mut Vec::new(), options).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
     }
 }
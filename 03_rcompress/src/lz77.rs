@@ -0,0 +1,480 @@
+/// Shortest repeated sequence replaced by a match
+pub(crate) const MIN_MATCH: usize = 3;
+
+/// Longest match, as in DEFLATE
+pub(crate) const MAX_MATCH: usize = 258;
+
+/// Largest distance of a match, as a power of 2
+pub(crate) const MAX_WINDOW_BITS: u32 = 15;
+
+/// Symbol ending a block, in the alphabet of the literals and lengths
+pub(crate) const END_OF_BLOCK: u16 = 256;
+
+/// Symbols of the literals, of the end of a block and of the lengths
+pub(crate) const LITERAL_LENGTH_SYMBOLS: usize = 286;
+
+/// Symbols of the distances
+pub(crate) const DISTANCE_SYMBOLS: usize = 30;
+
+/// Smallest length of each length symbol from 257, and the number of extra bits giving
+/// the rest of the length, as in DEFLATE
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// Smallest distance of each distance symbol, and the number of extra bits giving the
+/// rest of the distance
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Bits of the hash of the 3 bytes starting a match
+const HASH_BITS: u32 = 15;
+
+/// Distance above which a match of `MIN_MATCH` bytes takes more bits than its literals
+const TOO_FAR: usize = 4096;
+
+/// Part of the data: a byte, or a copy of the `length` bytes starting `distance` bytes
+/// before
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Token {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+/// Returns the symbol of a match length, the number of its extra bits and their value
+pub(crate) fn length_symbol(length: u16) -> (u16, u32, u32) {
+    let index = LENGTH_BASES.partition_point(|&base| base <= length) - 1;
+    (
+        257 + index as u16,
+        LENGTH_EXTRA_BITS[index] as u32,
+        (length - LENGTH_BASES[index]) as u32,
+    )
+}
+
+/// Returns the symbol of a match distance, the number of its extra bits and their value
+pub(crate) fn distance_symbol(distance: u16) -> (u16, u32, u32) {
+    let index = DISTANCE_BASES.partition_point(|&base| base <= distance) - 1;
+    (
+        index as u16,
+        DISTANCE_EXTRA_BITS[index] as u32,
+        (distance - DISTANCE_BASES[index]) as u32,
+    )
+}
+
+/// Returns the smallest length of a length symbol and the number of its extra bits, None
+/// if the symbol is not a length
+pub(crate) fn length_base(symbol: u16) -> Option<(usize, u32)> {
+    let index = (symbol as usize).checked_sub(257)?;
+    Some((
+        *LENGTH_BASES.get(index)? as usize,
+        LENGTH_EXTRA_BITS[index] as u32,
+    ))
+}
+
+/// Returns the smallest distance of a distance symbol and the number of its extra bits,
+/// None if the symbol is not a distance
+pub(crate) fn distance_base(symbol: u16) -> Option<(usize, u32)> {
+    let index = symbol as usize;
+    Some((
+        *DISTANCE_BASES.get(index)? as usize,
+        DISTANCE_EXTRA_BITS[index] as u32,
+    ))
+}
+
+/// Effort of the search of matches at a compression level, as in zlib
+struct Level {
+    /// Length of the previous match from which fewer candidates are tried
+    good: usize,
+    /// Length of a match from which the next position is not tried, or for the levels
+    /// without lazy matching, above which the positions inside it are not indexed
+    lazy: usize,
+    /// Length of a match long enough to stop the search
+    nice: usize,
+    /// Most candidates tried at a position
+    chain: usize,
+}
+
+/// `good`, `lazy`, `nice` and `chain` of each level
+const LEVELS: [(usize, usize, usize, usize); 9] = [
+    (4, 4, 8, 4),
+    (4, 5, 16, 8),
+    (4, 6, 32, 32),
+    (4, 4, 16, 16),
+    (8, 16, 32, 32),
+    (8, 16, 128, 128),
+    (8, 32, 128, 256),
+    (32, 128, 258, 1024),
+    (32, 258, 258, 4096),
+];
+
+impl Level {
+    fn new(level: u32) -> Level {
+        let (good, lazy, nice, chain) = LEVELS[level as usize - 1];
+        Level {
+            good,
+            lazy,
+            nice,
+            chain,
+        }
+    }
+}
+
+/// First level trying the next position before taking a match
+const FIRST_LAZY_LEVEL: u32 = 4;
+
+/// Finds the repeated sequences of the data with hash chains: the positions with the
+/// same hash of their first 3 bytes are linked from the most recent, so the candidates
+/// of a match are tried from the closest.
+pub(crate) struct MatchFinder {
+    level: Level,
+    lazy: bool,
+    /// Largest distance of a match
+    window: usize,
+    /// Last position with each hash plus 1, or 0 if there is none
+    head: Vec<u32>,
+    /// Previous position with the same hash as each position plus 1, or 0 if there is
+    /// none
+    prev: Vec<u32>,
+}
+
+impl MatchFinder {
+    /// `level` is from 1 to 9 and `window_bits` at most `MAX_WINDOW_BITS`
+    pub fn new(level: u32, window_bits: u32) -> MatchFinder {
+        MatchFinder {
+            level: Level::new(level),
+            lazy: level >= FIRST_LAZY_LEVEL,
+            window: 1 << window_bits,
+            head: vec![0; 1 << HASH_BITS],
+            prev: Vec::new(),
+        }
+    }
+
+    /// Splits `data[start..]` into literals and matches, added to `tokens`. The bytes
+    /// before `start`, the end of the previous block, can be copied by the matches.
+    pub fn tokens(&mut self, data: &[u8], start: usize, tokens: &mut Vec<Token>) {
+        self.head.fill(0);
+        self.prev.resize(data.len(), 0);
+        for pos in start.saturating_sub(self.window)..start {
+            self.insert(data, pos);
+        }
+
+        if self.lazy {
+            self.lazy_tokens(data, start, tokens);
+        } else {
+            self.greedy_tokens(data, start, tokens);
+        }
+    }
+
+    /// Takes the longest match at each position
+    fn greedy_tokens(&mut self, data: &[u8], start: usize, tokens: &mut Vec<Token>) {
+        let mut pos = start;
+        while pos < data.len() {
+            let found = self.longest_match(data, pos, 0);
+            self.insert(data, pos);
+            match found {
+                Some((length, distance)) => {
+                    tokens.push(Token::Match {
+                        length: length as u16,
+                        distance: distance as u16,
+                    });
+                    // Indexing the inside of long matches takes longer than it saves
+                    if length <= self.level.lazy {
+                        for inside in pos + 1..pos + length {
+                            self.insert(data, inside);
+                        }
+                    }
+                    pos += length;
+                }
+                None => {
+                    tokens.push(Token::Literal(data[pos]));
+                    pos += 1;
+                }
+            }
+        }
+    }
+
+    /// Takes the match at a position only if the next position has no longer one,
+    /// otherwise the byte at the position becomes a literal
+    fn lazy_tokens(&mut self, data: &[u8], start: usize, tokens: &mut Vec<Token>) {
+        // Match at the previous position, whose byte is not in a token yet if `pending`
+        let mut previous: Option<(usize, usize)> = None;
+        let mut pending = false;
+        let mut pos = start;
+        while pos < data.len() {
+            let shortest = previous.map_or(0, |(length, _)| length);
+            let found = if shortest < self.level.lazy {
+                self.longest_match(data, pos, shortest)
+            } else {
+                None
+            };
+            self.insert(data, pos);
+
+            if let (Some((length, distance)), None) = (previous, found) {
+                tokens.push(Token::Match {
+                    length: length as u16,
+                    distance: distance as u16,
+                });
+                let end = pos - 1 + length;
+                for inside in pos + 1..end {
+                    self.insert(data, inside);
+                }
+                previous = None;
+                pending = false;
+                pos = end;
+                continue;
+            }
+
+            if pending {
+                tokens.push(Token::Literal(data[pos - 1]));
+            }
+            previous = found;
+            pending = true;
+            pos += 1;
+        }
+        if pending {
+            tokens.push(Token::Literal(data[pos - 1]));
+        }
+    }
+
+    /// Returns the length and distance of the longest match at `pos` longer than
+    /// `shortest`, among the positions before it
+    fn longest_match(&self, data: &[u8], pos: usize, shortest: usize) -> Option<(usize, usize)> {
+        let max = MAX_MATCH.min(data.len() - pos);
+        if max < MIN_MATCH || shortest >= max {
+            return None;
+        }
+        let mut chain = self.level.chain;
+        if shortest >= self.level.good {
+            chain /= 4;
+        }
+        let limit = pos.saturating_sub(self.window);
+
+        let mut best = (shortest.max(MIN_MATCH - 1), 0);
+        let mut candidate = self.head[hash(data, pos)] as usize;
+        while candidate > limit && chain > 0 {
+            let from = candidate - 1;
+            // A longer match has the same byte after the best one
+            if data[from + best.0] == data[pos + best.0] {
+                let length = match_length(data, from, pos, max);
+                if length > best.0 {
+                    best = (length, pos - from);
+                    if length >= self.level.nice || length == max {
+                        break;
+                    }
+                }
+            }
+            candidate = self.prev[from] as usize;
+            chain -= 1;
+        }
+
+        match best {
+            (_, 0) => None,
+            (MIN_MATCH, distance) if distance > TOO_FAR => None,
+            best => Some(best),
+        }
+    }
+
+    /// Adds a position to the chain of its hash
+    #[inline]
+    fn insert(&mut self, data: &[u8], pos: usize) {
+        if pos + MIN_MATCH <= data.len() {
+            let hash = hash(data, pos);
+            self.prev[pos] = self.head[hash];
+            self.head[hash] = pos as u32 + 1;
+        }
+    }
+}
+
+#[inline]
+fn hash(data: &[u8], pos: usize) -> usize {
+    let bytes = u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], 0]);
+    (bytes.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
+
+/// Length of the common prefix of the bytes at `a` and `b`, at most `max`
+#[inline]
+fn match_length(data: &[u8], a: usize, b: usize, max: usize) -> usize {
+    let mut length = 0;
+    while length + 8 <= max {
+        let x = u64::from_le_bytes(data[a + length..a + length + 8].try_into().unwrap());
+        let y = u64::from_le_bytes(data[b + length..b + length + 8].try_into().unwrap());
+        if x != y {
+            return length + ((x ^ y).trailing_zeros() / 8) as usize;
+        }
+        length += 8;
+    }
+    while length < max && data[a + length] == data[b + length] {
+        length += 1;
+    }
+    length
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lz77::{
+        distance_base, distance_symbol, length_base, length_symbol, MatchFinder, Token, MAX_MATCH,
+    };
+
+    /// Copies the bytes of the tokens after `history`
+    fn expand(history: &[u8], tokens: &[Token]) -> Vec<u8> {
+        let mut data = history.to_vec();
+        for &token in tokens {
+            match token {
+                Token::Literal(byte) => data.push(byte),
+                Token::Match { length, distance } => {
+                    for _ in 0..length {
+                        data.push(data[data.len() - distance as usize]);
+                    }
+                }
+            }
+        }
+        data[history.len()..].to_vec()
+    }
+
+    fn tokens(level: u32, data: &[u8], start: usize) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        MatchFinder::new(level, 15).tokens(data, start, &mut tokens);
+        assert_eq!(expand(&data[..start], &tokens), &data[start..]);
+        tokens
+    }
+
+    #[test]
+    fn test_symbols() {
+        assert_eq!(length_symbol(3), (257, 0, 0));
+        assert_eq!(length_symbol(12), (265, 1, 1));
+        assert_eq!(length_symbol(257), (284, 5, 30));
+        assert_eq!(length_symbol(258), (285, 0, 0));
+        assert_eq!(distance_symbol(1), (0, 0, 0));
+        assert_eq!(distance_symbol(6), (4, 1, 1));
+        assert_eq!(distance_symbol(32768), (29, 13, 8191));
+
+        // Every length and distance is decoded back
+        for length in 3..=MAX_MATCH as u16 {
+            let (symbol, extra_bits, extra) = length_symbol(length);
+            let (base, bits) = length_base(symbol).unwrap();
+            assert_eq!((bits, base + extra as usize), (extra_bits, length as usize));
+            assert!(extra < 1 << extra_bits || extra == 0);
+        }
+        for distance in 1..=32768 {
+            let (symbol, extra_bits, extra) = distance_symbol(distance);
+            let (base, bits) = distance_base(symbol).unwrap();
+            assert_eq!(
+                (bits, base + extra as usize),
+                (extra_bits, distance as usize)
+            );
+        }
+        assert_eq!(length_base(256), None);
+        assert_eq!(length_base(286), None);
+        assert_eq!(distance_base(30), None);
+    }
+
+    #[test]
+    fn test_matches() {
+        assert_eq!(
+            tokens(6, b"abcabcabcd", 0),
+            [
+                Token::Literal(b'a'),
+                Token::Literal(b'b'),
+                Token::Literal(b'c'),
+                Token::Match {
+                    length: 6,
+                    distance: 3
+                },
+                Token::Literal(b'd'),
+            ]
+        );
+        // A run is a match overlapping the bytes it copies
+        assert_eq!(
+            tokens(1, &[0; 100], 0),
+            [
+                Token::Literal(0),
+                Token::Match {
+                    length: 99,
+                    distance: 1
+                }
+            ]
+        );
+        // The longest match is limited
+        assert_eq!(tokens(9, &[7; 1000], 0).len(), 5);
+        assert_eq!(tokens(9, b"", 0), []);
+        assert_eq!(tokens(9, b"ab", 0).len(), 2);
+    }
+
+    #[test]
+    fn test_lazy_matching() {
+        // At "bcdef", "bcd" matches at once, but "cdef" one byte later
+        let data = b"bcd-cdef-bcdef";
+        let greedy = tokens(1, data, 0);
+        let lazy = tokens(6, data, 0);
+        assert_eq!(
+            greedy[9..],
+            [
+                Token::Match {
+                    length: 3,
+                    distance: 9
+                },
+                Token::Literal(b'e'),
+                Token::Literal(b'f')
+            ]
+        );
+        assert_eq!(
+            lazy[9..],
+            [
+                Token::Literal(b'b'),
+                Token::Match {
+                    length: 4,
+                    distance: 6
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_matches_in_history() {
+        let data = b"hello, world! hello, world!";
+        assert_eq!(
+            tokens(6, data, 14),
+            [Token::Match {
+                length: 13,
+                distance: 14
+            }]
+        );
+
+        // Matches are not further than the window
+        let mut tokens = Vec::new();
+        MatchFinder::new(6, 8).tokens(&b"abcdef".repeat(50), 0, &mut tokens);
+        assert!(tokens.iter().all(|token| match token {
+            Token::Match { distance, .. } => *distance <= 256,
+            Token::Literal(_) => true,
+        }));
+        let mut tokens = Vec::new();
+        let far: Vec<u8> = [&b"abcdefgh"[..], &[b'-'; 300], b"abcdefgh"].concat();
+        MatchFinder::new(6, 8).tokens(&far, 0, &mut tokens);
+        // The run of dashes takes a literal and two matches
+        assert_eq!(tokens.len(), 8 + 3 + 8);
+    }
+
+    #[test]
+    fn test_levels() {
+        let text: Vec<u8> = (0..20_000u32)
+            .flat_map(|i| format!("{} {} ", i % 97, i % 13).into_bytes())
+            .collect();
+        let counts: Vec<usize> = (1..=9).map(|level| tokens(level, &text, 0).len()).collect();
+        // Higher levels find fewer, longer matches
+        assert!(
+            counts[8] <= counts[5] && counts[5] <= counts[0],
+            "{:?}",
+            counts
+        );
+    }
+}
@@ -1,5 +1,11 @@
-use clap::{Parser, Subcommand};
-use rcompress::{compress, decompress};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    process,
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use rcompress::{compress_with, decompress_with, Format, Options};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -8,13 +14,37 @@ struct Args {
     #[command(subcommand)]
     command: Option<Commands>,
 
-    /// Path of the input file
-    #[arg(short, long)]
-    input: String,
+    /// Path of the input file, standard input if missing or -
+    #[arg(short, long, global = true)]
+    input: Option<String>,
+
+    /// Path of the output file, standard output if missing or -
+    #[arg(short, long, global = true)]
+    output: Option<String>,
+
+    /// Compression level, from 1 (fastest) to 9 (smallest)
+    #[arg(
+        short,
+        long,
+        global = true,
+        default_value_t = 6,
+        value_parser = clap::value_parser!(u32).range(1..=9)
+    )]
+    level: u32,
 
-    /// Path of the output file
-    #[arg(short, long)]
-    output: String,
+    /// Largest distance of the repeated sequences, as a power of 2
+    #[arg(
+        short,
+        long,
+        global = true,
+        default_value_t = 15,
+        value_parser = clap::value_parser!(u32).range(8..=15)
+    )]
+    window_bits: u32,
+
+    /// Format of the compressed data
+    #[arg(short, long, global = true, value_enum, default_value_t = FormatArg::Rcompress)]
+    format: FormatArg,
 }
 #[derive(Subcommand, Debug)]
 enum Commands {
@@ -22,18 +52,65 @@ enum Commands {
     Decompress,
 }
 
-fn main() {
-    let args = Args::parse();
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum FormatArg {
+    /// Blocks of this tool
+    Rcompress,
+    /// gzip, read by gzip -d and zlib
+    Gzip,
+}
 
-    let path_input = args.input;
-    let path_output = args.output;
+impl From<FormatArg> for Format {
+    fn from(format: FormatArg) -> Self {
+        match format {
+            FormatArg::Rcompress => Format::Rcompress,
+            FormatArg::Gzip => Format::Gzip,
+        }
+    }
+}
+
+fn open_input(path: Option<&str>) -> io::Result<Box<dyn Read>> {
+    match path {
+        None | Some("-") => Ok(Box::new(io::stdin().lock())),
+        Some(path) => Ok(Box::new(File::open(path)?)),
+    }
+}
+
+fn create_output(path: Option<&str>) -> io::Result<Box<dyn Write>> {
+    match path {
+        None | Some("-") => Ok(Box::new(BufWriter::new(io::stdout().lock()))),
+        Some(path) => Ok(Box::new(BufWriter::new(File::create(path)?))),
+    }
+}
+
+fn run(args: &Args) -> io::Result<()> {
+    let mut input = open_input(args.input.as_deref())?;
+    let mut output = create_output(args.output.as_deref())?;
 
     match args.command {
         Some(Commands::Compress) | None => {
-            compress(&path_input, &path_output).expect("Error during compression")
+            let options = Options {
+                level: args.level,
+                window_bits: args.window_bits,
+                format: args.format.into(),
+            };
+            compress_with(&mut input, &mut output, options)
         }
         Some(Commands::Decompress) => {
-            decompress(&path_input, &path_output).expect("Error during decompression")
+            decompress_with(&mut input, &mut output, args.format.into()).map_err(io::Error::from)
         }
-    };
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    if let Err(err) = run(&args) {
+        let action = match args.command {
+            Some(Commands::Compress) | None => "compression",
+            Some(Commands::Decompress) => "decompression",
+        };
+        eprintln!("rcompress: error during {}: {}", action, err);
+        process::exit(1);
+    }
 }
@@ -1,28 +1,40 @@
-use std::{cmp::Ordering, collections::BinaryHeap};
+use std::{cmp::Reverse, collections::BinaryHeap};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum HuffmanTree {
     Node(usize, Box<HuffmanTree>, Box<HuffmanTree>),
-    Leaf(usize, u8),
+    Leaf(usize, u16),
 }
 
 impl HuffmanTree {
-    pub fn create(counts: &Vec<(u8, usize)>) -> HuffmanTree {
+    /// Builds the tree by merging the two least frequent trees until one is left. Trees
+    /// with the same count are taken in a fixed order: leaves first by symbol, then
+    /// nodes in the order they were created.
+    pub fn create(counts: &[(u16, usize)]) -> Option<HuffmanTree> {
+        let mut trees: Vec<Option<HuffmanTree>> = Vec::with_capacity(2 * counts.len());
         let mut heap = BinaryHeap::new();
-        for (elem, count) in counts.clone() {
-            heap.push(Box::new(HuffmanTree::Leaf(count, elem)));
+        let mut leaves = counts.to_vec();
+        leaves.sort_unstable_by_key(|&(symbol, _)| symbol);
+        for (symbol, count) in leaves {
+            heap.push(Reverse((count, trees.len())));
+            trees.push(Some(HuffmanTree::Leaf(count, symbol)));
         }
 
         while heap.len() > 1 {
-            let left = heap.pop().unwrap();
-            let right = heap.pop().unwrap();
-            heap.push(Box::new(HuffmanTree::Node(
-                (*left).value() + (*right).value(),
-                left,
-                right,
+            let Reverse((_, left)) = heap.pop()?;
+            let Reverse((_, right)) = heap.pop()?;
+            let left = trees[left].take()?;
+            let right = trees[right].take()?;
+            let count = left.value() + right.value();
+            heap.push(Reverse((count, trees.len())));
+            trees.push(Some(HuffmanTree::Node(
+                count,
+                Box::new(left),
+                Box::new(right),
             )));
         }
-        return *(heap.pop().unwrap());
+        let Reverse((_, root)) = heap.pop()?;
+        trees[root].take()
     }
 
     pub fn value(&self) -> usize {
@@ -32,76 +44,75 @@ impl HuffmanTree {
         }
     }
 
-    pub fn encode(&self, value: u8) -> Option<Vec<u8>> {
+    /// Returns the depth of each leaf by symbol, for an alphabet of `symbols` symbols,
+    /// 0 for the symbols not in the tree. A single leaf still needs one bit.
+    pub fn lengths(&self, symbols: usize) -> Vec<usize> {
+        let mut lengths = vec![0; symbols];
         match self {
-            HuffmanTree::Leaf(_, val) => {
-                if *val == value {
-                    return Some(vec![]);
-                }
-                return None;
-            }
-            HuffmanTree::Node(_, left, right) => {
-                if let Some(mut res) = left.encode(value) {
-                    res.insert(0, 0);
-                    return Some(res);
-                }
-                if let Some(mut res) = right.encode(value) {
-                    res.insert(0, 1);
-                    return Some(res);
-                }
-            }
+            HuffmanTree::Leaf(_, symbol) => lengths[*symbol as usize] = 1,
+            HuffmanTree::Node(..) => self.collect_lengths(0, &mut lengths),
         }
-        None
+        lengths
     }
-}
 
-impl Ord for HuffmanTree {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        match (self, other) {
-            (HuffmanTree::Leaf(a, _), HuffmanTree::Leaf(b, _)) => b.cmp(a),
-            (HuffmanTree::Leaf(a, _), HuffmanTree::Node(b, _, _)) => b.cmp(a),
-            (HuffmanTree::Node(a, _, _), HuffmanTree::Leaf(b, _)) => b.cmp(a),
-            (HuffmanTree::Node(a, _, _), HuffmanTree::Node(b, _, _)) => b.cmp(a),
+    fn collect_lengths(&self, depth: usize, lengths: &mut [usize]) {
+        match self {
+            HuffmanTree::Leaf(_, symbol) => lengths[*symbol as usize] = depth,
+            HuffmanTree::Node(_, left, right) => {
+                left.collect_lengths(depth + 1, lengths);
+                right.collect_lengths(depth + 1, lengths);
+            }
         }
     }
 }
 
-impl PartialOrd for HuffmanTree {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
+#[cfg(test)]
 mod tests {
-    use crate::*;
+    use super::HuffmanTree;
+
+    /// Counts of "abacba"
+    const COUNTS: [(u16, usize); 3] = [(b'a' as u16, 3), (b'b' as u16, 2), (b'c' as u16, 1)];
 
     #[test]
     fn test_create_huffman_tree() {
-        let mut buf = "abacba".as_bytes();
-        let counts = count_frequencies(&mut buf);
-        let tree = HuffmanTree::create(&counts);
+        let tree = HuffmanTree::create(&COUNTS).unwrap();
         assert_eq!(
             tree,
             HuffmanTree::Node(
                 6,
-                Box::new(HuffmanTree::Leaf(3, b'a')),
+                Box::new(HuffmanTree::Leaf(3, b'a' as u16)),
                 Box::new(HuffmanTree::Node(
                     3,
-                    Box::new(HuffmanTree::Leaf(1, b'c')),
-                    Box::new(HuffmanTree::Leaf(2, b'b'))
+                    Box::new(HuffmanTree::Leaf(1, b'c' as u16)),
+                    Box::new(HuffmanTree::Leaf(2, b'b' as u16))
                 ))
             )
         );
+        assert_eq!(HuffmanTree::create(&[]), None);
+    }
+
+    #[test]
+    fn test_ties_do_not_depend_on_order() {
+        let tree = HuffmanTree::create(&[(3, 1), (2, 1), (1, 1), (4, 1)]);
+        let sorted = HuffmanTree::create(&[(1, 1), (2, 1), (3, 1), (4, 1)]);
+        assert_eq!(tree, sorted);
     }
 
     #[test]
-    fn test_encode() {
-        let mut buf = "abacba".as_bytes();
-        let counts = count_frequencies(&mut buf);
-        let tree = HuffmanTree::create(&counts);
+    fn test_lengths() {
+        let tree = HuffmanTree::create(&COUNTS).unwrap();
+        let lengths = tree.lengths(256);
+        assert_eq!(
+            (
+                lengths[b'a' as usize],
+                lengths[b'b' as usize],
+                lengths[b'c' as usize]
+            ),
+            (1, 2, 2)
+        );
+        assert_eq!(lengths.iter().filter(|&&length| length > 0).count(), 3);
 
-        assert_eq!(Some(vec![0]), tree.encode(b'a'));
-        assert_eq!(Some(vec![1, 0]), tree.encode(b'c'));
-        assert_eq!(Some(vec![1, 1]), tree.encode(b'b'));
+        let single = HuffmanTree::create(&[(285, 5)]).unwrap();
+        assert_eq!(single.lengths(286)[285], 1);
     }
 }
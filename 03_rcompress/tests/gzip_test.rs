@@ -0,0 +1,97 @@
+use std::{
+    fs,
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+use rcompress::{compress_with, decompress_with, Format, Options};
+
+fn gzip(content: &[u8], level: u32) -> Vec<u8> {
+    let options = Options {
+        level,
+        format: Format::Gzip,
+        ..Options::default()
+    };
+    let mut compressed = Vec::new();
+    compress_with(&mut &content[..], &mut compressed, options).unwrap();
+    compressed
+}
+
+fn gunzip(compressed: &[u8]) -> io::Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    decompress_with(&mut &compressed[..], &mut decompressed, Format::Gzip)?;
+    Ok(decompressed)
+}
+
+/// Decompresses with the system gzip, `None` if it is not installed
+fn system_gunzip(compressed: &[u8]) -> Option<Vec<u8>> {
+    let mut child = Command::new("gzip")
+        .arg("-dc")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .ok()?;
+    let mut stdin = child.stdin.take().unwrap();
+    let compressed = compressed.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&compressed));
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap().unwrap();
+    assert!(output.status.success(), "gzip -dc failed");
+    Some(output.stdout)
+}
+
+/// The fixtures were written by GNU gzip: text.txt.gz with -9 and the file name,
+/// random.bin.gz in stored blocks, empty.gz from no data and members.gz from two parts of
+/// text.txt compressed separately
+#[test]
+fn gzip_fixtures_are_decompressed() {
+    let text = fs::read("tests/files/text.txt").unwrap();
+    let random = fs::read("tests/files/random.bin").unwrap();
+    let cases = [
+        ("tests/files/text.txt.gz", &text[..]),
+        ("tests/files/random.bin.gz", &random[..]),
+        ("tests/files/empty.gz", &[][..]),
+        ("tests/files/members.gz", &text[..]),
+    ];
+
+    for (path, expected) in cases {
+        let compressed = fs::read(path).unwrap();
+        assert_eq!(gunzip(&compressed).unwrap(), expected, "{}", path);
+    }
+}
+
+#[test]
+fn gzip_output_is_decompressed() {
+    for path in ["tests/files/text.txt", "tests/files/random.bin"] {
+        let content = fs::read(path).unwrap();
+        for level in [1, 6, 9] {
+            let compressed = gzip(&content, level);
+            assert_eq!(gunzip(&compressed).unwrap(), content, "{} {}", path, level);
+            if let Some(decompressed) = system_gunzip(&compressed) {
+                assert_eq!(decompressed, content, "{} {}", path, level);
+            }
+        }
+    }
+
+    // Within 2% of GNU gzip
+    let text = fs::read("tests/files/text.txt").unwrap();
+    let reference = fs::read("tests/files/text.txt.gz").unwrap();
+    assert!(gzip(&text, 9).len() < reference.len() * 102 / 100);
+}
+
+#[test]
+fn corrupted_gzip_is_an_error() {
+    let mut compressed = fs::read("tests/files/text.txt.gz").unwrap();
+    let middle = compressed.len() / 2;
+    let err = gunzip(&compressed[..middle]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+    // Either the codes or the CRC-32 do not match anymore
+    compressed[middle] ^= 0x10;
+    let err = gunzip(&compressed).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    // Not gzip
+    let err = gunzip(b"plain text, not compressed").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}